@@ -0,0 +1,289 @@
+use chrono::{Datelike, Duration, NaiveDate};
+
+use crate::{
+    inline,
+    link::heading_title,
+    object::Object,
+    object_types::ObjectTypes,
+    structs::{self, Priority, TodoStates},
+    terminal::{strip_colors, Style},
+    time_management::ParsedDateTime,
+};
+
+/// How many days before a deadline it shows up, if the deadline has no warning period like -3d
+pub const DEFAULT_WARNING_DAYS: i64 = 14;
+
+/// Where the date of an entry came from
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+pub enum EntryKind {
+    Deadline,
+    Scheduled,
+    /// A plain active timestamp, in the heading line or in the text underneath
+    Timestamp,
+}
+
+/// A dated heading, as it is collected from the org files
+#[derive(Debug, Clone)]
+pub struct AgendaEntry {
+    /// The category, #+CATEGORY or the file name without extension
+    pub category: String,
+    pub heading: String,
+    pub todo: Option<TodoStates>,
+    pub priority: Option<Priority>,
+    pub kind: EntryKind,
+    pub timestamp: ParsedDateTime,
+}
+
+/// An entry on a concrete date, repeating entries have one of these per occurrence
+#[derive(Debug, Clone)]
+pub struct AgendaItem<'a> {
+    pub entry: &'a AgendaEntry,
+    pub date: NaiveDate,
+}
+
+/// How many days the agenda shows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AgendaView {
+    Day,
+    /// Monday to Sunday of the week
+    Week,
+    /// First to last day of the month
+    Month,
+}
+
+impl AgendaView {
+    /// First and last day the view shows around a date
+    pub fn range(&self, date: NaiveDate) -> (NaiveDate, NaiveDate) {
+        match self {
+            AgendaView::Day => (date, date),
+            AgendaView::Week => {
+                let start = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+                (start, start + Duration::days(6))
+            }
+            AgendaView::Month => {
+                let start = date.with_day(1).unwrap();
+                let next = match date.month() {
+                    12 => NaiveDate::from_ymd_opt(date.year() + 1, 1, 1),
+                    month => NaiveDate::from_ymd_opt(date.year(), month + 1, 1),
+                };
+                (start, next.unwrap() - Duration::days(1))
+            }
+        }
+    }
+}
+
+/// All dated entries of one or many org files
+#[derive(Debug, Default)]
+pub struct Agenda {
+    entries: Vec<AgendaEntry>,
+}
+
+impl Agenda {
+    pub fn new() -> Agenda {
+        Agenda { entries: Vec::new() }
+    }
+
+    /// Collects the entries of a file, name is the path it was read from and is used for the category
+    pub fn add_file(&mut self, name: &str, file: &structs::File) {
+        let category = file.category(name);
+        for child in &file.children {
+            collect(child, &category, None, &mut self.entries);
+        }
+    }
+
+    pub fn entries(&self) -> &Vec<AgendaEntry> {
+        &self.entries
+    }
+
+    /// Everything that happens on a day, repeaters are expanded
+    /// Timed entries come first ordered by time, then deadlines, scheduled and plain timestamps ordered by priority
+    pub fn day(&self, date: NaiveDate) -> Vec<AgendaItem<'_>> {
+        let mut items: Vec<AgendaItem> = self
+            .entries
+            .iter()
+            .filter(|entry| !entry.timestamp.occurrences(date, date).is_empty())
+            .map(|entry| AgendaItem { entry, date })
+            .collect();
+        items.sort_by_key(|item| {
+            (
+                item.entry.timestamp.time().is_none(),
+                item.entry.timestamp.time(),
+                item.entry.kind,
+                item.entry.priority.as_ref().map(|p| p.value()).unwrap_or(1),
+            )
+        });
+        items
+    }
+
+    /// Scheduled and deadline entries before today that are not DONE, oldest first
+    /// A repeating entry is overdue if its current date, the one that was not done yet, is in the past
+    pub fn overdue(&self, today: NaiveDate) -> Vec<&AgendaEntry> {
+        let mut entries: Vec<&AgendaEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.kind != EntryKind::Timestamp && !is_done(entry))
+            .filter(|entry| entry.timestamp.date() < today)
+            .collect();
+        entries.sort_by_key(|entry| entry.timestamp.date());
+        entries
+    }
+
+    /// Deadlines after today that are inside of their warning period, with the date of the deadline, nearest first
+    pub fn upcoming_deadlines(&self, today: NaiveDate) -> Vec<AgendaItem<'_>> {
+        let mut items = Vec::new();
+        for entry in &self.entries {
+            if entry.kind != EntryKind::Deadline || is_done(entry) {
+                continue;
+            }
+            let warning = entry.timestamp.warning();
+            let days = warning.map(|w| w.days()).unwrap_or(DEFAULT_WARNING_DAYS);
+            let next = entry
+                .timestamp
+                .occurrences(today + Duration::days(1), today + Duration::days(days))
+                .into_iter()
+                .next();
+            if let Some(date) = next {
+                if warning.is_some_and(|w| w.first_only) && date != entry.timestamp.date() {
+                    continue;
+                }
+                items.push(AgendaItem { entry, date });
+            }
+        }
+        items.sort_by_key(|item| item.date);
+        items
+    }
+
+    /// Renders the view around today as text, with the overdue and upcoming deadline sections as seen from today
+    pub fn render(&self, view: AgendaView, today: NaiveDate) -> String {
+        self.render_styled(view, today, &Style::default())
+    }
+
+    /// Like render, with colored TODO keywords and priorities and overdue entries highlighted if the style has colors
+    pub fn render_styled(&self, view: AgendaView, today: NaiveDate, style: &Style) -> String {
+        let (start, end) = view.range(today);
+        let mut lines = Vec::new();
+        let title = match view {
+            AgendaView::Day => "Day-agenda".to_string(),
+            AgendaView::Week => format!("Week-agenda (W{:02})", start.iso_week().week()),
+            AgendaView::Month => format!("Month-agenda ({})", start.format("%B %Y")),
+        };
+        lines.push(style.bold(&title));
+        let overdue = self.overdue(today);
+        if !overdue.is_empty() {
+            lines.push(style.overdue("Overdue"));
+            for entry in overdue {
+                let days = (today - entry.timestamp.date()).num_days();
+                let label = match entry.kind {
+                    EntryKind::Deadline => format!("{days} d. ago:"),
+                    _ => format!("Sched.{days:>2}x:"),
+                };
+                lines.push(render_line(entry, &style.overdue(&label), style));
+            }
+        }
+        let mut date = start;
+        while date <= end {
+            let items = self.day(date);
+            if view != AgendaView::Month || !items.is_empty() || date == today {
+                let heading = format!("{:<10} {} W{:02}", date.format("%A"), date.format("%e %B %Y"), date.iso_week().week());
+                lines.push(style.bold(&heading));
+            }
+            for item in items {
+                let label = match (item.entry.timestamp.time(), item.entry.kind) {
+                    (Some(time), _) => format!("{}......", time.format("%H:%M")),
+                    (None, EntryKind::Deadline) => "Deadline:".to_string(),
+                    (None, EntryKind::Scheduled) => "Scheduled:".to_string(),
+                    (None, EntryKind::Timestamp) => "".to_string(),
+                };
+                let label = match item.entry.kind {
+                    EntryKind::Deadline if !is_done(item.entry) => style.due_soon(&label),
+                    _ => label,
+                };
+                lines.push(render_line(item.entry, &label, style));
+            }
+            date += Duration::days(1);
+        }
+        let upcoming = self.upcoming_deadlines(today);
+        if !upcoming.is_empty() {
+            lines.push(style.bold("Upcoming deadlines"));
+            for item in upcoming {
+                let days = (item.date - today).num_days();
+                lines.push(render_line(item.entry, &style.due_soon(&format!("In {days:>3} d.:")), style));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+fn is_done(entry: &AgendaEntry) -> bool {
+    entry.todo == Some(TodoStates::DONE)
+}
+
+fn render_line(entry: &AgendaEntry, label: &str, style: &Style) -> String {
+    let todo = match &entry.todo {
+        Some(todo) => style.todo(todo) + " ",
+        None => "".to_string(),
+    };
+    let priority = match &entry.priority {
+        Some(priority) => style.priority(priority) + " ",
+        None => "".to_string(),
+    };
+    // Padding is added by hand, the color codes of the label would count as width
+    let label_padding = " ".repeat(12usize.saturating_sub(strip_colors(label).chars().count()));
+    format!("  {:<12} {label}{label_padding} {todo}{priority}{}", format!("{}:", entry.category), entry.heading)
+}
+
+fn collect(obj: &Object, category: &str, heading: Option<&AgendaEntry>, entries: &mut Vec<AgendaEntry>) {
+    let mut current = heading.cloned();
+    match obj.object_type() {
+        ObjectTypes::Heading {
+            text,
+            todo,
+            deadline,
+            scheduled,
+            in_line_scedule,
+            priority,
+            ..
+        } => {
+            let base = AgendaEntry {
+                category: category.to_string(),
+                heading: heading_title(text),
+                todo: todo.clone(),
+                priority: priority.clone(),
+                kind: EntryKind::Timestamp,
+                timestamp: ParsedDateTime::new(NaiveDate::MIN, None, false),
+            };
+            let dates = [
+                (EntryKind::Deadline, deadline),
+                (EntryKind::Scheduled, scheduled),
+                (EntryKind::Timestamp, in_line_scedule),
+            ];
+            for (kind, date) in dates {
+                if let Some(date) = date.as_ref().filter(|date| date.is_active()) {
+                    entries.push(AgendaEntry {
+                        kind,
+                        timestamp: date.clone(),
+                        ..base.clone()
+                    });
+                }
+            }
+            current = Some(base);
+        }
+        ObjectTypes::INFO { .. } => (),
+        other => {
+            if let Some(current) = &current {
+                for date in inline::collect_timestamps(&other.inline_objects()) {
+                    if date.is_active() {
+                        entries.push(AgendaEntry {
+                            kind: EntryKind::Timestamp,
+                            timestamp: date,
+                            ..current.clone()
+                        });
+                    }
+                }
+            }
+        }
+    }
+    for child in obj.children() {
+        collect(child, category, current.as_ref(), entries);
+    }
+}
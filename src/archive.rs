@@ -0,0 +1,283 @@
+use std::path::Path;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use regex::Regex;
+
+use crate::{
+    link::heading_title,
+    object::Object,
+    object_types::ObjectTypes,
+    structs::{self, TodoStates},
+    time_management::ParsedDateTime,
+};
+
+/// Where archived subtrees go, written like org does it: %s_archive::* Archived
+/// %s stands for the name of the file the subtree comes from
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveLocation {
+    /// None archives into the same file
+    pub file: Option<String>,
+    /// The heading line the subtrees go under, like "* Archived", None puts them at the top level
+    pub heading: Option<String>,
+}
+
+/// A subtree that was cut out of a file, with the ARCHIVE_ properties already set
+#[derive(Debug)]
+pub struct Archived {
+    /// None if neither #+ARCHIVE: nor :ARCHIVE: say where it goes
+    pub location: Option<ArchiveLocation>,
+    pub subtree: Object,
+}
+
+impl ArchiveLocation {
+    /// Parses a location, source is the path of the file the subtrees come from
+    pub fn parse(location: &str, source: &str) -> ArchiveLocation {
+        let name = Path::new(source)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| source.to_string());
+        let location = location.replace("%s", &name);
+        let (file, heading) = location.split_once("::").unwrap_or((location.as_str(), ""));
+        ArchiveLocation {
+            file: Some(file.trim().to_string()).filter(|file| !file.is_empty()),
+            heading: Some(heading.trim().to_string()).filter(|heading| !heading.is_empty()),
+        }
+    }
+    /// The title of the location heading, without stars
+    fn heading_title(&self) -> Option<String> {
+        self.heading.as_ref().map(|heading| heading_title(heading.trim_start_matches('*').trim()))
+    }
+    /// The level of the location heading, a heading without stars is a top level one
+    fn stars(&self) -> u32 {
+        let heading = self.heading.as_deref().unwrap_or_default();
+        heading.chars().take_while(|c| *c == '*').count().max(1) as u32
+    }
+}
+
+/// Decides which headings get archived and when
+#[derive(Debug, Clone)]
+pub struct ArchivePolicy {
+    /// The keywords that get archived, with how many days after CLOSED
+    /// A heading without CLOSED counts from its newest State note in the LOGBOOK, without either it only goes if there are no days to wait
+    /// Archiving never writes CLOSED itself, --log-closed and --log-notes record when headings got done
+    pub keywords: Vec<(TodoStates, i64)>,
+    /// Done headings below a heading that stays, because it is not done or still waits, stay with it
+    pub keep_under_active: bool,
+    /// Headings with this tag, or below a heading with it, are never archived
+    pub skip_tag: Option<String>,
+}
+
+impl ArchivePolicy {
+    /// Archives headings with the todostate right away, except the ones tagged :noarchive:
+    pub fn new(todostate: &TodoStates) -> ArchivePolicy {
+        ArchivePolicy {
+            keywords: vec![(todostate.clone(), 0)],
+            keep_under_active: false,
+            skip_tag: Some("noarchive".to_string()),
+        }
+    }
+    /// Sets after how many days a done keyword gets archived, parsed from KEYWORD=DAYS like DONE=7
+    /// The parser knows TODO, NEXT, DONE and LOOP and no #+TODO: keywords, so DONE is the only done keyword a delay can be set for
+    pub fn add_delay(&mut self, delay: &str) -> Result<(), String> {
+        let (keyword, days) = delay.split_once('=').ok_or_else(|| format!("expected KEYWORD=DAYS, got {delay}"))?;
+        let todo = TodoStates::get("", &format!("{} ", keyword.trim())).ok_or_else(|| format!("unknown keyword {keyword}"))?;
+        // Only done headings get archived, a delay for TODO or NEXT would archive open tasks
+        if todo != TodoStates::DONE {
+            return Err(format!("{keyword} is not a done state"));
+        }
+        let days = days.trim().parse::<i64>().map_err(|_| format!("days have to be a number, got {days}"))?;
+        self.keywords.retain(|(other, _)| *other != todo);
+        self.keywords.push((todo, days));
+        Ok(())
+    }
+    /// The days a keyword has to wait, None if it never gets archived
+    fn delay(&self, todo: &TodoStates) -> Option<i64> {
+        self.keywords.iter().find(|(keyword, _)| keyword == todo).map(|(_, days)| *days)
+    }
+}
+
+/// What is known about the headings above while walking down the tree
+#[derive(Clone)]
+struct Outline {
+    path: Vec<String>,
+    tags: Vec<String>,
+    location: Option<String>,
+    category: String,
+    /// Outline paths of the headings this file archives into itself
+    archive_headings: Vec<Vec<String>>,
+    /// The closest heading with a keyword above stays, it is not done yet or still waits to be archived
+    active_parent: bool,
+}
+
+impl structs::File {
+    /// Cuts out every heading with the todostate, together with everything below it, and adds the properties org adds when archiving:
+    /// ARCHIVE_TIME, ARCHIVE_FILE, ARCHIVE_OLPATH, ARCHIVE_CATEGORY, ARCHIVE_TODO and ARCHIVE_ITAGS for inherited tags
+    /// The location comes from the closest :ARCHIVE: property, or else #+ARCHIVE:
+    /// Headings tagged :ARCHIVE: and the headings this file archives into itself are left alone
+    pub fn archive(&mut self, todostate: &TodoStates, source: &str, time: NaiveDateTime) -> Vec<Archived> {
+        self.archive_with_policy(&ArchivePolicy::new(todostate), source, time)
+    }
+
+    /// Like archive, but the policy decides which headings go, time is also used to check how long ago they where closed
+    pub fn archive_with_policy(&mut self, policy: &ArchivePolicy, source: &str, time: NaiveDateTime) -> Vec<Archived> {
+        let location = self.keyword("ARCHIVE").pop();
+        let archive_headings = self
+            .iter()
+            .filter_map(|obj| obj.property("ARCHIVE"))
+            .chain(location.as_deref())
+            .map(|location| ArchiveLocation::parse(location, source))
+            .filter(|location| location.file.is_none())
+            .filter_map(|location| self.location_path(&location).or(location.heading_title().map(|title| vec![title])))
+            .collect();
+        let outline = Outline {
+            path: Vec::new(),
+            tags: Vec::new(),
+            location,
+            category: self.category(source),
+            archive_headings,
+            active_parent: false,
+        };
+        let mut archived = Vec::new();
+        take(&mut self.children, policy, source, &time, &outline, &mut archived);
+        archived
+    }
+
+    /// Puts an archived subtree into this file, below the heading of the location which is created if it is missing
+    pub fn add_archived(&mut self, location: &ArchiveLocation, subtree: Object) -> Result<(), String> {
+        let Some(title) = location.heading_title() else {
+            return self.insert_heading(&[], subtree);
+        };
+        let path = match self.location_path(location) {
+            Some(path) => path,
+            None => {
+                let heading = location.heading.as_deref().unwrap_or_default();
+                let mut parent = Object::heading(heading.trim_start_matches('*').trim());
+                parent.set_level(location.stars())?;
+                self.children.push(parent);
+                vec![title]
+            }
+        };
+        self.insert_heading(&path.iter().map(|title| title.as_str()).collect::<Vec<_>>(), subtree)
+    }
+
+    /// The outline path of the first heading with the title and the stars of the location heading
+    fn location_path(&self, location: &ArchiveLocation) -> Option<Vec<String>> {
+        let title = location.heading_title()?;
+        self.headings()
+            .filter(|entry| entry.object.level() == Some(location.stars()))
+            .map(|entry| entry.outline_path())
+            .find(|path| path.last() == Some(&title))
+    }
+}
+
+/// A new archive file with the header org writes into it
+pub fn new_archive_file(source: &str) -> structs::File {
+    let lines = vec![
+        "#    -*- mode: org -*-".to_string(),
+        "".to_string(),
+        "".to_string(),
+        format!("Archived entries from file {source}"),
+        "".to_string(),
+        "".to_string(),
+    ];
+    structs::File::parse(structs::FileContext::Todo, lines)
+}
+
+fn take(
+    children: &mut Vec<Object>,
+    policy: &ArchivePolicy,
+    source: &str,
+    time: &NaiveDateTime,
+    outline: &Outline,
+    archived: &mut Vec<Archived>,
+) {
+    let mut index = 0;
+    while index < children.len() {
+        let ObjectTypes::Heading { text, todo, closed, .. } = children[index].object_type() else {
+            index += 1;
+            continue;
+        };
+        let title = heading_title(text);
+        let mut path = outline.path.clone();
+        path.push(title.clone());
+        let tags = children[index].object_type().tags();
+        let location = children[index]
+            .property("ARCHIVE")
+            .map(|location| location.to_string())
+            .or(outline.location.clone());
+        let skipped = policy
+            .skip_tag
+            .as_ref()
+            .is_some_and(|skip| outline.tags.contains(skip) || tags.contains(skip));
+        if tags.iter().any(|tag| tag == "ARCHIVE") || outline.archive_headings.contains(&path) || skipped {
+            index += 1;
+            continue;
+        }
+        let category = children[index]
+            .property("CATEGORY")
+            .map(|category| category.to_string())
+            .unwrap_or(outline.category.clone());
+        let delay = todo.as_ref().and_then(|todo| policy.delay(todo));
+        let kept = policy.keep_under_active && outline.active_parent;
+        let todo_keyword = todo.as_ref().map(|todo| todo.build());
+        let done_on = closed
+            .as_ref()
+            .map(|closed| closed.date())
+            .or_else(|| todo_keyword.as_deref().and_then(|todo| noted(&children[index], todo)));
+        let ripe = match (delay, done_on) {
+            (None, _) => false,
+            (Some(days), Some(done_on)) => (time.date() - done_on).num_days() >= days,
+            (Some(days), None) => days <= 0,
+        };
+        if ripe && !kept {
+            let todo = todo_keyword.unwrap_or_default();
+            let mut subtree = children.remove(index);
+            subtree.set_property("ARCHIVE_TIME", &time.format("%Y-%m-%d %a %H:%M").to_string());
+            subtree.set_property("ARCHIVE_FILE", &absolute(source));
+            if !outline.path.is_empty() {
+                subtree.set_property("ARCHIVE_OLPATH", &outline.path.join("/"));
+            }
+            subtree.set_property("ARCHIVE_CATEGORY", &category);
+            subtree.set_property("ARCHIVE_TODO", &todo);
+            if !outline.tags.is_empty() {
+                subtree.set_property("ARCHIVE_ITAGS", &outline.tags.join(" "));
+            }
+            archived.push(Archived {
+                location: location.map(|location| ArchiveLocation::parse(&location, source)),
+                subtree,
+            });
+            continue;
+        }
+        let mut below = outline.clone();
+        below.path.push(title);
+        below.tags.extend(tags);
+        below.location = location;
+        below.category = category;
+        if todo_keyword.is_some() {
+            below.active_parent = true;
+        }
+        take(children[index].children_mut(), policy, source, time, &below, archived);
+        index += 1;
+    }
+}
+
+/// The day of the newest - State "KEYWORD" note in the LOGBOOK of a heading
+fn noted(heading: &Object, todo: &str) -> Option<NaiveDate> {
+    let re = Regex::new(&format!(r#"^\s*- State "{}"\s+from\s+\S+\s+(\[[^\]]+\])"#, regex::escape(todo))).unwrap();
+    heading
+        .logbook()
+        .iter()
+        .filter_map(|line| re.captures(line))
+        .filter_map(|caps| ParsedDateTime::parse(&caps[1]))
+        .map(|date| date.date())
+        .max()
+}
+
+/// The absolute path of source, like Emacs writes it into ARCHIVE_FILE, so restoring works from any directory
+/// Links are resolved when the file exists, otherwise it is only made absolute
+fn absolute(source: &str) -> String {
+    std::fs::canonicalize(source)
+        .or_else(|_| std::path::absolute(source))
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_else(|_| source.to_string())
+}
@@ -8,6 +8,10 @@ impl structs::File {
             .map(|(obj, _)| obj.build())
             .collect()
     }
+    /// The lines of the file as they are in the tree, changes to the tree included
+    pub fn build(&self) -> Vec<String> {
+        self.children.iter().flat_map(|child| child.build()).collect()
+    }
 }
 
 pub fn build_value(value: u32, repeat: char, end: char) -> String {
@@ -0,0 +1,182 @@
+use crate::{link::heading_title, object::Object, object_types::ObjectTypes, structs, traversal::is_heading};
+
+impl Object {
+    /// Creates a level 1 heading from the text after the stars, like "TODO [#A] Call mom :family:"
+    pub fn heading(text: &str) -> Object {
+        Object::new(ObjectTypes::new_heading(&format!("* {text}")))
+    }
+    /// The level of a heading, the number of stars, None for everything else
+    pub fn level(&self) -> Option<u32> {
+        match self.object_type() {
+            ObjectTypes::Heading { value, .. } => Some(*value),
+            _ => None,
+        }
+    }
+    /// Changes the level of this heading and of every heading below it by delta
+    /// Fails without changing anything if a heading would end up with less than one star
+    pub fn shift_level(&mut self, delta: i32) -> Result<(), String> {
+        let lowest = self.iter().filter_map(|obj| obj.level()).min();
+        if let Some(lowest) = lowest {
+            if lowest as i64 + (delta as i64) < 1 {
+                return Err(format!("can't shift a level {lowest} heading by {delta}"));
+            }
+        }
+        shift(self, delta);
+        Ok(())
+    }
+    /// Gives this heading the level, descendants keep their distance to it
+    pub fn set_level(&mut self, level: u32) -> Result<(), String> {
+        match self.level() {
+            Some(current) => self.shift_level(level as i32 - current as i32),
+            None => Err("only headings have a level".to_string()),
+        }
+    }
+    /// One star less for this heading and everything below it
+    pub fn promote(&mut self) -> Result<(), String> {
+        self.shift_level(-1)
+    }
+    /// One star more for this heading and everything below it
+    pub fn demote(&mut self) -> Result<(), String> {
+        self.shift_level(1)
+    }
+}
+
+fn shift(obj: &mut Object, delta: i32) {
+    if let ObjectTypes::Heading { value, .. } = obj.object_type_mut() {
+        *value = (*value as i32 + delta) as u32;
+    }
+    for child in obj.children_mut() {
+        shift(child, delta);
+    }
+}
+
+/// Paths are outline paths: the titles of the headings from the top, without TODO keyword, priority and tags
+impl structs::File {
+    /// The heading at an outline path, like ["Projects", "Website"]
+    pub fn heading_mut(&mut self, path: &[&str]) -> Option<&mut Object> {
+        let indices = heading_indices(&self.children, path)?;
+        let (last, parents) = indices.split_last()?;
+        Some(&mut siblings_mut(&mut self.children, parents)[*last])
+    }
+
+    /// Appends a heading, with everything below it, as the last child of the heading at parent
+    /// An empty parent appends it at the end of the file. Levels are adjusted to fit under the parent
+    pub fn insert_heading(&mut self, parent: &[&str], mut heading: Object) -> Result<(), String> {
+        let Some(indices) = heading_indices(&self.children, parent) else {
+            return Err(format!("no heading {}", parent.join("/")));
+        };
+        let siblings = match indices.split_last() {
+            Some((last, parents)) => {
+                let parent = &mut siblings_mut(&mut self.children, parents)[*last];
+                heading.set_level(parent.level().unwrap_or(0) + 1)?;
+                parent.children_mut()
+            }
+            None => {
+                heading.set_level(1)?;
+                &mut self.children
+            }
+        };
+        siblings.push(heading);
+        Ok(())
+    }
+
+    /// Removes the heading at path with everything below it and hands it back
+    pub fn cut(&mut self, path: &[&str]) -> Option<Object> {
+        let indices = heading_indices(&self.children, path)?;
+        let (last, parents) = indices.split_last()?;
+        Some(siblings_mut(&mut self.children, parents).remove(*last))
+    }
+
+    /// Removes the heading at path with everything below it, returns false if there is no such heading
+    pub fn delete(&mut self, path: &[&str]) -> bool {
+        self.cut(path).is_some()
+    }
+
+    /// Moves the heading at from, with everything below it, to the end of the heading at to
+    /// Nothing changes if it fails, the subtree is only taken out once its new place is known
+    pub fn move_subtree(&mut self, from: &[&str], to: &[&str]) -> Result<(), String> {
+        let from_indices = heading_indices(&self.children, from)
+            .filter(|indices| !indices.is_empty())
+            .ok_or_else(|| format!("no heading {}", from.join("/")))?;
+        let mut to_indices = heading_indices(&self.children, to).ok_or_else(|| format!("no heading {}", to.join("/")))?;
+        if to_indices.starts_with(&from_indices) {
+            return Err(format!("can't move {} into itself", from.join("/")));
+        }
+        let (last, parents) = from_indices.split_last().unwrap();
+        // Taking the subtree out moves later siblings on the way to the target one up
+        if to_indices.len() > parents.len() && to_indices.starts_with(parents) && to_indices[parents.len()] > *last {
+            to_indices[parents.len()] -= 1;
+        }
+        let mut subtree = siblings_mut(&mut self.children, parents).remove(*last);
+        let level = match to_indices.split_last() {
+            Some((target, target_parents)) => siblings_mut(&mut self.children, target_parents)[*target].level().unwrap_or(0) + 1,
+            None => 1,
+        };
+        if let Err(error) = subtree.set_level(level) {
+            siblings_mut(&mut self.children, parents).insert(*last, subtree);
+            return Err(error);
+        }
+        match to_indices.split_last() {
+            Some((target, target_parents)) => siblings_mut(&mut self.children, target_parents)[*target].children_mut().push(subtree),
+            None => self.children.push(subtree),
+        }
+        Ok(())
+    }
+
+    /// Makes the heading at path a sibling of its parent, placed right after the parent
+    /// Following siblings stay where they are, unlike with org's level based promotion
+    pub fn promote(&mut self, path: &[&str]) -> Result<(), String> {
+        let indices = heading_indices(&self.children, path).ok_or_else(|| format!("no heading {}", path.join("/")))?;
+        if indices.len() < 2 {
+            return Err(format!("{} is already a top level heading", path.join("/")));
+        }
+        let (last, parents) = indices.split_last().unwrap();
+        let (parent, grandparents) = parents.split_last().unwrap();
+        let level = siblings_mut(&mut self.children, grandparents)[*parent].level().unwrap_or(1);
+        let mut subtree = siblings_mut(&mut self.children, parents).remove(*last);
+        subtree.set_level(level)?;
+        siblings_mut(&mut self.children, grandparents).insert(parent + 1, subtree);
+        Ok(())
+    }
+
+    /// Makes the heading at path the last child of the heading before it
+    pub fn demote(&mut self, path: &[&str]) -> Result<(), String> {
+        let indices = heading_indices(&self.children, path)
+            .filter(|indices| !indices.is_empty())
+            .ok_or_else(|| format!("no heading {}", path.join("/")))?;
+        let (last, parents) = indices.split_last().unwrap();
+        let siblings = siblings_mut(&mut self.children, parents);
+        let Some(previous) = siblings[..*last].iter().rposition(is_heading) else {
+            return Err(format!("{} has no heading before it", path.join("/")));
+        };
+        let mut subtree = siblings.remove(*last);
+        if let Err(error) = subtree.set_level(siblings[previous].level().unwrap_or(0) + 1) {
+            siblings.insert(*last, subtree);
+            return Err(error);
+        }
+        siblings[previous].children_mut().push(subtree);
+        Ok(())
+    }
+}
+
+/// The positions in the children lists that lead to the heading at path
+fn heading_indices(mut children: &[Object], path: &[&str]) -> Option<Vec<usize>> {
+    let mut indices = Vec::new();
+    for title in path {
+        let index = children.iter().position(|obj| match obj.object_type() {
+            ObjectTypes::Heading { text, .. } => heading_title(text) == *title,
+            _ => false,
+        })?;
+        indices.push(index);
+        children = children[index].children();
+    }
+    Some(indices)
+}
+
+/// The children list reached by following the indices, the top level list for no indices
+fn siblings_mut<'a>(mut children: &'a mut Vec<Object>, indices: &[usize]) -> &'a mut Vec<Object> {
+    for index in indices {
+        children = children[*index].children_mut();
+    }
+    children
+}
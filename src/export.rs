@@ -0,0 +1,440 @@
+use std::collections::{HashMap, HashSet};
+
+use clap::ValueEnum;
+use regex::Regex;
+
+use crate::{
+    link::heading_title,
+    object::Object,
+    object_types::ObjectTypes,
+    structs::{self, Priority, TodoStates},
+    time_management::InfoType,
+};
+
+/// The formats the export command can write
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ExportFormat {
+    Html,
+    /// GitHub flavored Markdown
+    #[value(alias = "md")]
+    Markdown,
+    /// A LaTeX document, for pdflatex
+    #[value(alias = "tex")]
+    Latex,
+    /// Deadlines, scheduled items and timestamps as events and TODOs, for calendar apps
+    #[value(alias = "ics")]
+    Icalendar,
+    /// Headings with a TODO keyword as todo.txt lines
+    #[value(name = "todo-txt", alias = "todotxt")]
+    TodoTxt,
+    /// Headings with a TODO keyword as the JSON of task import
+    #[cfg(feature = "serde")]
+    #[value(alias = "task")]
+    Taskwarrior,
+    /// The headings as outline for outliner apps
+    Opml,
+    /// The headings as Graphviz diagram
+    #[value(alias = "graphviz")]
+    Dot,
+    /// The headings as Mermaid mindmap, a flowchart with --colors or --links
+    #[value(alias = "mmd")]
+    Mermaid,
+    /// The tree as JSON, see schema/org-ast.schema.json
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+/// The formats the import command can read
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ImportFormat {
+    #[value(alias = "md")]
+    Markdown,
+    /// Events and todos, merged by their UID
+    #[value(alias = "ics")]
+    Icalendar,
+    /// todo.txt lines, merged by their id: or title
+    #[value(name = "todo-txt", alias = "todotxt")]
+    TodoTxt,
+    /// The output of task export, merged by orgid, uuid or title
+    #[cfg(feature = "serde")]
+    #[value(alias = "task")]
+    Taskwarrior,
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+/// The parts of a document exporters work with
+/// The tree only knows lines, this groups them into paragraphs, lists, tables and blocks
+#[derive(Debug, Clone)]
+pub enum Element {
+    Heading(Heading),
+    /// The planning line below a heading
+    Planning(Vec<InfoType>),
+    /// Lines of text that belong together, without their indentation
+    Paragraph(Vec<String>),
+    List(List),
+    Table(Vec<Row>),
+    /// #+begin_NAME PARAMETERS up to #+end_NAME, the lines are kept as they are
+    Block {
+        name: String,
+        parameters: String,
+        lines: Vec<String>,
+    },
+    /// #+KEY: value, key is uppercased
+    Keyword { key: String, value: String },
+    FootnoteDefinition { label: String, text: String },
+    /// A line of five or more dashes
+    Rule,
+}
+
+#[derive(Debug, Clone)]
+pub struct Heading {
+    pub level: u32,
+    pub todo: Option<TodoStates>,
+    pub priority: Option<Priority>,
+    /// The text without tags
+    pub title: String,
+    pub tags: Vec<String>,
+    /// CUSTOM_ID, ID or a slug of the title, unique in the document
+    pub anchor: String,
+    /// The :ID: property, id: links point to the anchor of the heading with it
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListKind {
+    /// - item
+    Unordered,
+    /// 1. item or 1) item
+    Ordered,
+    /// - term :: description
+    Description,
+}
+
+#[derive(Debug, Clone)]
+pub struct List {
+    pub kind: ListKind,
+    pub items: Vec<ListItem>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ListItem {
+    pub checkbox: Option<bool>,
+    pub todo: Option<TodoStates>,
+    /// The term of a description list item
+    pub term: Option<String>,
+    /// The text, continuation lines are joined with a space
+    pub text: String,
+    /// A list nested below the item
+    pub sublist: Option<List>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Row {
+    Cells(Vec<String>),
+    /// |---+---|
+    Rule,
+}
+
+/// A list item before the nesting is known
+struct FlatItem {
+    indent: usize,
+    kind: ListKind,
+    item: ListItem,
+}
+
+impl structs::File {
+    /// The elements of the whole file, in the order they appear
+    pub fn elements(&self) -> Vec<Element> {
+        elements(&self.children)
+    }
+}
+
+impl Object {
+    /// The elements of this Object and everything below it
+    pub fn elements(&self) -> Vec<Element> {
+        elements(std::slice::from_ref(self))
+    }
+}
+
+/// Groups the lines of the trees into elements
+/// Drawers, comments and the lines of the File context are left out
+pub fn elements(roots: &[Object]) -> Vec<Element> {
+    let lines: Vec<&Object> = roots.iter().flat_map(|root| root.iter()).collect();
+    let re_begin = Regex::new(r"(?i)^\s*#\+begin_(\S+)\s*(.*?)\s*$").unwrap();
+    let re_keyword = Regex::new(r"^\s*#\+([\w-]+):\s*(.*?)\s*$").unwrap();
+    let re_ordered = Regex::new(r"^(\s*)(?:\d+|[A-Za-z])[.)]\s+(.*)$").unwrap();
+    let re_rule = Regex::new(r"^\s*-{5,}\s*$").unwrap();
+    let mut result = Vec::new();
+    let mut anchors = HashSet::new();
+    let mut paragraph: Vec<String> = Vec::new();
+    let mut items: Vec<FlatItem> = Vec::new();
+    let mut rows: Vec<Row> = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let obj = lines[index];
+        index += 1;
+        let text = match obj.object_type() {
+            ObjectTypes::Text { text } => Some(text.as_str()),
+            _ => None,
+        };
+        let list_item = match (obj.object_type(), text) {
+            (ObjectTypes::ListElement { text, todo, checkbox, value }, _) => {
+                Some(flat_item((*value as usize).saturating_sub(1), ListKind::Unordered, text, todo.clone(), *checkbox))
+            }
+            (_, Some(text)) => re_ordered
+                .captures(text)
+                .map(|caps| flat_item(caps[1].len(), ListKind::Ordered, &caps[2], None, None)),
+            _ => None,
+        };
+        let table_row = text.filter(|text| text.trim_start().starts_with('|')).map(table_row);
+        let continues_list = !items.is_empty()
+            && list_item.is_none()
+            && text.is_some_and(|text| text.starts_with(char::is_whitespace) && !text.trim().is_empty());
+        if continues_list {
+            let last = &mut items.last_mut().unwrap().item;
+            last.text = format!("{} {}", last.text, text.unwrap_or_default().trim());
+            continue;
+        }
+        let blank = matches!(obj.object_type(), ObjectTypes::EmptyLine);
+        // A single empty line does not end a list when another item follows
+        let list_goes_on = blank
+            && lines.get(index).is_some_and(|next| match next.object_type() {
+                ObjectTypes::ListElement { .. } => true,
+                ObjectTypes::Text { text } => re_ordered.is_match(text),
+                _ => false,
+            });
+        if list_item.is_none() && !list_goes_on && !items.is_empty() {
+            result.push(Element::List(nest(&items)));
+            items.clear();
+        }
+        if table_row.is_none() && !rows.is_empty() {
+            result.push(Element::Table(std::mem::take(&mut rows)));
+        }
+        let plain = text.filter(|text| !text.trim_start().starts_with('#') && !re_rule.is_match(text) && list_item.is_none() && table_row.is_none());
+        if plain.is_none() && !paragraph.is_empty() {
+            result.push(Element::Paragraph(std::mem::take(&mut paragraph)));
+        }
+        if let Some(item) = list_item {
+            // Numbered items after bullets, or the other way around, start a new list
+            let first = items.first().filter(|first| item.indent <= first.indent);
+            if first.is_some_and(|first| (first.kind == ListKind::Ordered) != (item.kind == ListKind::Ordered)) {
+                result.push(Element::List(nest(&items)));
+                items.clear();
+            }
+            items.push(item);
+            continue;
+        }
+        if let Some(row) = table_row {
+            rows.push(row);
+            continue;
+        }
+        if let Some(text) = plain {
+            paragraph.push(text.trim().to_string());
+            continue;
+        }
+        match obj.object_type() {
+            ObjectTypes::Heading { text, todo, priority, value, .. } => {
+                let heading = Heading {
+                    level: *value,
+                    todo: todo.clone(),
+                    priority: priority.clone(),
+                    title: without_tags(text),
+                    tags: obj.object_type().tags(),
+                    anchor: anchor(obj, &without_tags(text), &mut anchors),
+                    id: obj.property("ID").map(|id| id.to_string()),
+                };
+                result.push(Element::Heading(heading));
+            }
+            ObjectTypes::INFO { info, .. } if !info.is_empty() => result.push(Element::Planning(info.clone())),
+            ObjectTypes::Drawer { name, .. } if !name.eq_ignore_ascii_case("END") => {
+                // Skips everything up to the :END:
+                while index < lines.len() {
+                    index += 1;
+                    if matches!(lines[index - 1].object_type(), ObjectTypes::Drawer { name, .. } if name.eq_ignore_ascii_case("END")) {
+                        break;
+                    }
+                }
+            }
+            ObjectTypes::FootnoteDefinition { label, text } => result.push(Element::FootnoteDefinition {
+                label: label.clone(),
+                text: text.clone(),
+            }),
+            ObjectTypes::Text { text } => {
+                if re_rule.is_match(text) {
+                    result.push(Element::Rule);
+                } else if let Some(caps) = re_begin.captures(text).filter(|caps| {
+                    // Without its #+end_ line the #+begin_ line is text, the headings after it are no code
+                    let end = format!("#+end_{}", caps[1].to_lowercase());
+                    lines[index..].iter().any(|line| line.object_type().build().trim().to_lowercase().starts_with(&end))
+                }) {
+                    let name = caps[1].to_lowercase();
+                    let end = format!("#+end_{name}");
+                    let mut block_lines = Vec::new();
+                    while index < lines.len() {
+                        let line = lines[index].object_type().build();
+                        index += 1;
+                        if line.trim().to_lowercase().starts_with(&end) {
+                            break;
+                        }
+                        block_lines.push(line);
+                    }
+                    result.push(Element::Block {
+                        name,
+                        parameters: caps[2].to_string(),
+                        lines: block_lines,
+                    });
+                } else if re_begin.is_match(text) {
+                    result.push(Element::Paragraph(vec![text.trim().to_string()]));
+                } else if let Some(caps) = re_keyword.captures(text) {
+                    result.push(Element::Keyword {
+                        key: caps[1].to_uppercase(),
+                        value: caps[2].to_string(),
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+    if !paragraph.is_empty() {
+        result.push(Element::Paragraph(paragraph));
+    }
+    if !items.is_empty() {
+        result.push(Element::List(nest(&items)));
+    }
+    if !rows.is_empty() {
+        result.push(Element::Table(rows));
+    }
+    result
+}
+
+/// The headings of the elements up to a level, for a table of contents
+pub fn headings(elements: &[Element], max_level: u32) -> Vec<&Heading> {
+    elements
+        .iter()
+        .filter_map(|element| match element {
+            Element::Heading(heading) if heading.level <= max_level => Some(heading),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The anchors of the headings by their title, a title used twice points to the first heading
+/// Headings with an :ID: are also found as id:ID, the anchor is their CUSTOM_ID if they have one
+pub fn heading_anchors(elements: &[Element]) -> HashMap<String, String> {
+    let mut anchors = HashMap::new();
+    for element in elements {
+        if let Element::Heading(heading) = element {
+            anchors.entry(heading_title(&heading.title)).or_insert(heading.anchor.clone());
+        }
+    }
+    for element in elements {
+        if let Element::Heading(Heading { id: Some(id), anchor, .. }) = element {
+            anchors.insert(format!("id:{id}"), anchor.clone());
+        }
+    }
+    anchors
+}
+
+/// Where an [[id:...]] link points to: the anchor of the heading with the ID, the ID itself if it is in another file
+pub fn id_anchor(id: &str, anchors: &HashMap<String, String>) -> String {
+    anchors.get(&format!("id:{id}")).cloned().unwrap_or_else(|| id.to_string())
+}
+
+/// Where a [[*Heading]] or fuzzy link points to: the anchor of the heading, the slug for targets and missing headings
+pub fn heading_anchor(title: &str, anchors: &HashMap<String, String>) -> String {
+    anchors.get(&heading_title(title)).cloned().unwrap_or_else(|| slug(title))
+}
+
+/// Lowercase letters and digits, everything else turned into single dashes, used for anchors
+pub fn slug(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// The cells of a table line
+fn table_row(line: &str) -> Row {
+    let line = line.trim();
+    if line.starts_with("|-") {
+        return Row::Rule;
+    }
+    let inner = line.trim_start_matches('|');
+    let inner = inner.strip_suffix('|').unwrap_or(inner);
+    Row::Cells(inner.split('|').map(|cell| cell.trim().to_string()).collect())
+}
+
+fn flat_item(indent: usize, kind: ListKind, text: &str, todo: Option<TodoStates>, checkbox: Option<bool>) -> FlatItem {
+    let re_checkbox = Regex::new(r"^\[([ Xx-])\]\s*").unwrap();
+    let (checkbox, text) = match (checkbox, re_checkbox.captures(text)) {
+        (None, Some(caps)) => (Some(&caps[1] != " "), &text[caps[0].len()..]),
+        _ => (checkbox, text),
+    };
+    let (kind, term, text) = match text.split_once(" :: ") {
+        Some((term, text)) if kind == ListKind::Unordered => (ListKind::Description, Some(term.trim().to_string()), text),
+        _ => (kind, None, text),
+    };
+    FlatItem {
+        indent,
+        kind,
+        item: ListItem {
+            checkbox,
+            todo,
+            term,
+            text: text.trim().to_string(),
+            sublist: None,
+        },
+    }
+}
+
+/// Builds the nested list, items with more indentation than the one before go into its sublist
+fn nest(items: &[FlatItem]) -> List {
+    let mut list = List {
+        kind: items[0].kind,
+        items: Vec::new(),
+    };
+    let mut index = 0;
+    while index < items.len() {
+        let indent = items[index].indent;
+        let end = items[index + 1..]
+            .iter()
+            .position(|other| other.indent <= indent)
+            .map(|position| index + 1 + position)
+            .unwrap_or(items.len());
+        let mut item = items[index].item.clone();
+        if end > index + 1 {
+            item.sublist = Some(nest(&items[index + 1..end]));
+        }
+        list.items.push(item);
+        index = end;
+    }
+    list
+}
+
+/// The text of a heading without the tags at the end
+pub fn without_tags(text: &str) -> String {
+    let re_tags = Regex::new(r"\s+:[\w@#%:]+:\s*$").unwrap();
+    re_tags.replace(text, "").trim().to_string()
+}
+
+fn anchor(obj: &Object, title: &str, taken: &mut HashSet<String>) -> String {
+    let base = obj
+        .property("CUSTOM_ID")
+        .or(obj.property("ID"))
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| slug(title));
+    let base = if base.is_empty() { "heading".to_string() } else { base };
+    let mut anchor = base.clone();
+    let mut number = 1;
+    while !taken.insert(anchor.clone()) {
+        number += 1;
+        anchor = format!("{base}-{number}");
+    }
+    anchor
+}
@@ -0,0 +1,364 @@
+use chrono::{Duration, Local, NaiveDate};
+use regex::Regex;
+
+use crate::{
+    link::heading_title,
+    object::Object,
+    object_types::ObjectTypes,
+    structs::{self, Priority},
+    time_management::ParsedDateTime,
+};
+
+/// How a property is compared to the value of a match
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+/// The right hand side of a property comparison
+#[derive(Debug, Clone)]
+pub enum Value {
+    Number(f64),
+    Text(String),
+    /// {regex}, only for = and <>
+    Regex(Regex),
+    /// "<2024-07-12>", "<today>" or "<+1w>", relative dates are resolved when the query is parsed
+    Date(NaiveDate),
+}
+
+/// A single element of a match, like +work, -home, {^proj} or PRIORITY="A"
+#[derive(Debug, Clone)]
+pub enum Condition {
+    Tag(String),
+    TagRegex(Regex),
+    Property {
+        name: String,
+        comparison: Comparison,
+        value: Value,
+    },
+}
+
+/// A tags/property match like org uses for its tags views: +work-home+PRIORITY="A"|TODO="NEXT"
+/// Conditions of one alternative all have to match, alternatives are separated by |
+/// An optional /TODO|NEXT part at the end limits the match to these TODO keywords
+#[derive(Debug, Clone)]
+pub struct Query {
+    /// Every alternative is a list of conditions, the bool is false if the condition is negated with -
+    alternatives: Vec<Vec<(bool, Condition)>>,
+    /// Allowed or, if negated, excluded TODO keywords from the /... part
+    todo: Vec<(bool, String)>,
+    /// The day relative dates like <+1w> where resolved to
+    today: NaiveDate,
+}
+
+/// A heading that matched, with the titles of its parents
+#[derive(Debug)]
+pub struct Match<'a> {
+    pub object: &'a Object,
+    /// Outline path: the titles of all headings above and including the matched one
+    pub path: Vec<String>,
+}
+
+impl Query {
+    /// Parses a match with today as the reference for relative dates
+    pub fn parse(input: &str) -> Result<Query, String> {
+        Query::parse_at(input, Local::now().date_naive())
+    }
+
+    /// Parses a match, relative dates like <today> or <+1w> are calculated from today
+    pub fn parse_at(input: &str, today: NaiveDate) -> Result<Query, String> {
+        let (tags, todo) = match split_todo(input) {
+            Some((tags, todo)) => (tags, Some(todo)),
+            None => (input, None),
+        };
+        let alternatives = split_outside_quotes(tags, '|')
+            .iter()
+            .map(|alternative| parse_alternative(alternative, today))
+            .collect::<Result<Vec<_>, String>>()?;
+        let todo = match todo {
+            Some(todo) => parse_todo(todo)?,
+            None => Vec::new(),
+        };
+        Ok(Query {
+            alternatives,
+            todo,
+            today,
+        })
+    }
+
+    /// Checks a heading against the query, tags are the tags of the heading including the inherited ones
+    pub fn matches(&self, obj: &Object, tags: &[String], category: Option<&str>) -> bool {
+        let ObjectTypes::Heading { todo, .. } = obj.object_type() else {
+            return false;
+        };
+        let todo = todo.as_ref().map(|t| t.build());
+        if !self.todo.is_empty() {
+            let keyword = todo.clone().unwrap_or_default();
+            let allowed: Vec<&String> = self.todo.iter().filter(|(positive, _)| *positive).map(|(_, k)| k).collect();
+            if !allowed.is_empty() && !allowed.contains(&&keyword) {
+                return false;
+            }
+            if self.todo.iter().any(|(positive, k)| !positive && *k == keyword) {
+                return false;
+            }
+        }
+        self.alternatives.iter().any(|conditions| {
+            conditions.iter().all(|(positive, condition)| {
+                self.condition(condition, obj, tags, category) == *positive
+            })
+        })
+    }
+
+    fn condition(&self, condition: &Condition, obj: &Object, tags: &[String], category: Option<&str>) -> bool {
+        match condition {
+            Condition::Tag(tag) => tags.iter().any(|t| t == tag),
+            Condition::TagRegex(re) => tags.iter().any(|t| re.is_match(t)),
+            Condition::Property {
+                name,
+                comparison,
+                value,
+            } => {
+                let property = property(obj, name, category);
+                compare(property.as_deref(), *comparison, value)
+            }
+        }
+    }
+
+    /// The day relative dates of this query are calculated from
+    pub fn today(&self) -> NaiveDate {
+        self.today
+    }
+}
+
+impl structs::File {
+    /// All headings matching the query with their outline path, in the order of the file
+    /// Tags are inherited from parent headings like in org
+    pub fn query(&self, query: &Query) -> Vec<Match<'_>> {
+        let category = self.keyword("CATEGORY").pop();
+        self.headings()
+            .filter_map(|entry| {
+                let tags: Vec<String> = entry
+                    .ancestors
+                    .iter()
+                    .chain([&entry.object])
+                    .flat_map(|obj| obj.object_type().tags())
+                    .collect();
+                query.matches(entry.object, &tags, category.as_deref()).then(|| Match {
+                    object: entry.object,
+                    path: entry.outline_path(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// The value of a property for comparing, special properties like TODO come from the heading itself
+fn property(obj: &Object, name: &str, category: Option<&str>) -> Option<String> {
+    let ObjectTypes::Heading {
+        text,
+        todo,
+        deadline,
+        scheduled,
+        closed,
+        value,
+        priority,
+        ..
+    } = obj.object_type()
+    else {
+        return None;
+    };
+    match name.to_uppercase().as_str() {
+        "TODO" => todo.as_ref().map(|t| t.build()),
+        "PRIORITY" => Some(
+            match priority {
+                Some(Priority::A) => "A",
+                Some(Priority::C) => "C",
+                _ => "B", // Org treats headings without priority as B
+            }
+            .to_string(),
+        ),
+        "LEVEL" => Some(value.to_string()),
+        "ITEM" => Some(heading_title(text)),
+        "DEADLINE" => deadline.as_ref().map(|d| d.build()),
+        "SCHEDULED" => scheduled.as_ref().map(|d| d.build()),
+        "CLOSED" => closed.as_ref().map(|d| d.build()),
+        "CATEGORY" => obj.property("CATEGORY").or(category).map(|c| c.to_string()),
+        _ => obj.property(name).map(|p| p.to_string()),
+    }
+}
+
+fn compare(property: Option<&str>, comparison: Comparison, value: &Value) -> bool {
+    use std::cmp::Ordering;
+    let ordering = match value {
+        Value::Regex(re) => {
+            let found = re.is_match(property.unwrap_or_default());
+            return match comparison {
+                Comparison::NotEqual => !found,
+                _ => found,
+            };
+        }
+        Value::Number(number) => match property.and_then(|p| p.trim().parse::<f64>().ok()) {
+            Some(property) => property.partial_cmp(number),
+            None => None,
+        },
+        Value::Date(date) => {
+            let parsed = property.and_then(ParsedDateTime::parse);
+            match parsed {
+                Some(parsed) => Some(parsed.date().cmp(date)),
+                // A missing date does not compare, except for <>
+                None => return comparison == Comparison::NotEqual,
+            }
+        }
+        // Missing properties count as empty, like in org
+        Value::Text(text) => Some(property.unwrap_or_default().cmp(text.as_str())),
+    };
+    match ordering {
+        Some(ordering) => match comparison {
+            Comparison::Equal => ordering == Ordering::Equal,
+            Comparison::NotEqual => ordering != Ordering::Equal,
+            Comparison::Less => ordering == Ordering::Less,
+            Comparison::LessEqual => ordering != Ordering::Greater,
+            Comparison::Greater => ordering == Ordering::Greater,
+            Comparison::GreaterEqual => ordering != Ordering::Less,
+        },
+        None => comparison == Comparison::NotEqual,
+    }
+}
+
+/// Splits off the /TODO part, a / inside quotes or braces does not count
+fn split_todo(input: &str) -> Option<(&str, &str)> {
+    let mut in_quotes = false;
+    let mut braces = 0;
+    for (index, c) in input.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '{' if !in_quotes => braces += 1,
+            '}' if !in_quotes => braces -= 1,
+            '/' if !in_quotes && braces == 0 => return Some((&input[..index], &input[index + 1..])),
+            _ => (),
+        }
+    }
+    None
+}
+
+fn split_outside_quotes(input: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut in_quotes = false;
+    let mut braces = 0;
+    let mut start = 0;
+    for (index, c) in input.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '{' if !in_quotes => braces += 1,
+            '}' if !in_quotes => braces -= 1,
+            c if c == separator && !in_quotes && braces == 0 => {
+                parts.push(&input[start..index]);
+                start = index + c.len_utf8();
+            }
+            _ => (),
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
+fn parse_alternative(input: &str, today: NaiveDate) -> Result<Vec<(bool, Condition)>, String> {
+    let re = Regex::new(
+        r#"^([+&-]?)(?:\{([^}]*)\}|([\w@#%]+)(?:(<>|<=|>=|!=|=|<|>)("[^"]*"|\{[^}]*\}|-?[^+&|-]+))?)"#,
+    )
+    .unwrap();
+    let mut conditions = Vec::new();
+    let mut rest = input.trim();
+    while !rest.is_empty() {
+        let caps = re
+            .captures(rest)
+            .ok_or_else(|| format!("Could not understand the match at: {rest}"))?;
+        let positive = &caps[1] != "-";
+        let condition = if let Some(regex) = caps.get(2) {
+            Condition::TagRegex(Regex::new(regex.as_str()).map_err(|e| e.to_string())?)
+        } else if let Some(comparison) = caps.get(4) {
+            Condition::Property {
+                name: caps[3].to_string(),
+                comparison: match comparison.as_str() {
+                    "=" => Comparison::Equal,
+                    "<>" | "!=" => Comparison::NotEqual,
+                    "<" => Comparison::Less,
+                    "<=" => Comparison::LessEqual,
+                    ">" => Comparison::Greater,
+                    _ => Comparison::GreaterEqual,
+                },
+                value: parse_value(&caps[5], today)?,
+            }
+        } else {
+            Condition::Tag(caps[3].to_string())
+        };
+        conditions.push((positive, condition));
+        rest = rest[caps[0].len()..].trim_start();
+    }
+    Ok(conditions)
+}
+
+fn parse_value(input: &str, today: NaiveDate) -> Result<Value, String> {
+    let input = input.trim();
+    if let Some(regex) = input.strip_prefix('{').and_then(|i| i.strip_suffix('}')) {
+        return Ok(Value::Regex(Regex::new(regex).map_err(|e| e.to_string())?));
+    }
+    if let Some(text) = input.strip_prefix('"').and_then(|i| i.strip_suffix('"')) {
+        if let Some(date) = text.strip_prefix('<').and_then(|t| t.strip_suffix('>')) {
+            return parse_date(date, today).map(Value::Date);
+        }
+        return Ok(Value::Text(text.to_string()));
+    }
+    input
+        .parse::<f64>()
+        .map(Value::Number)
+        .map_err(|_| format!("Values have to be numbers or in quotes: {input}"))
+}
+
+/// today, tomorrow, yesterday, now, +3d / -1w relative to today, or an absolute 2024-07-12
+fn parse_date(input: &str, today: NaiveDate) -> Result<NaiveDate, String> {
+    let re_relative = Regex::new(r"^([+-])(\d+)([dwmy])$").unwrap();
+    let input = input.trim();
+    match input {
+        "today" | "now" => return Ok(today),
+        "tomorrow" => return Ok(today + Duration::days(1)),
+        "yesterday" => return Ok(today - Duration::days(1)),
+        _ => (),
+    }
+    if let Some(caps) = re_relative.captures(input) {
+        let value: i64 = caps[2].parse().map_err(|_| format!("Not a number: {}", &caps[2]))?;
+        let days = match &caps[3] {
+            "d" => value,
+            "w" => value * 7,
+            "m" => value * 30,
+            _ => value * 365,
+        };
+        return Ok(match &caps[1] {
+            "+" => today + Duration::days(days),
+            _ => today - Duration::days(days),
+        });
+    }
+    let date = input.split_whitespace().next().unwrap_or_default();
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| format!("Could not understand the date: {input}"))
+}
+
+fn parse_todo(input: &str) -> Result<Vec<(bool, String)>, String> {
+    let re = Regex::new(r"^([+-]?)([\w@#%]+)").unwrap();
+    let mut keywords = Vec::new();
+    for part in input.trim_start_matches('!').split('|') {
+        let mut rest = part.trim();
+        while !rest.is_empty() {
+            let caps = re
+                .captures(rest)
+                .ok_or_else(|| format!("Could not understand the TODO match at: {rest}"))?;
+            keywords.push((&caps[1] != "-", caps[2].to_string()));
+            rest = rest[caps[0].len()..].trim_start();
+        }
+    }
+    Ok(keywords)
+}
@@ -0,0 +1,224 @@
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    inline::{self, InlineObject},
+    object::Object,
+    object_types::ObjectTypes,
+    structs,
+};
+
+/// A footnote of a file, with its definition connected to the references pointing to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Footnote {
+    /// None for anonymous inline footnotes [fn:: text]
+    pub label: Option<String>,
+    /// The text of the [fn:label] definition line or of the inline footnote, None if it is never defined
+    pub definition: Option<String>,
+    /// How often the footnote is referenced, inline footnotes count as a reference
+    pub references: usize,
+}
+
+/// Footnotes that are missing their other half
+#[derive(Debug, Default, PartialEq)]
+pub struct FootnoteReport {
+    /// Labels that are referenced but never defined
+    pub undefined_references: Vec<String>,
+    /// Labels that are defined but never referenced
+    pub unreferenced_definitions: Vec<String>,
+}
+
+impl FootnoteReport {
+    pub fn is_empty(&self) -> bool {
+        self.undefined_references.is_empty() && self.unreferenced_definitions.is_empty()
+    }
+}
+
+impl structs::File {
+    /// All footnotes of the file in the order they first appear, with their references connected to the definitions
+    pub fn footnotes(&self) -> Vec<Footnote> {
+        let mut footnotes: Vec<Footnote> = Vec::new();
+        for obj in self.iter() {
+            match obj.object_type() {
+                ObjectTypes::FootnoteDefinition { label, text } => {
+                    let footnote = named(&mut footnotes, label);
+                    footnote.definition = Some(text.clone());
+                }
+                other => collect_references(&other.inline_objects(), &mut footnotes),
+            }
+        }
+        footnotes
+    }
+
+    /// The definition of a footnote label, either from a definition line or from an inline footnote
+    pub fn footnote_definition(&self, label: &str) -> Option<String> {
+        self.footnotes()
+            .into_iter()
+            .find(|footnote| footnote.label.as_deref() == Some(label))
+            .and_then(|footnote| footnote.definition)
+    }
+
+    /// Finds references without definition and definitions without reference
+    pub fn orphaned_footnotes(&self) -> FootnoteReport {
+        let mut report = FootnoteReport::default();
+        for footnote in self.footnotes() {
+            let Some(label) = footnote.label else { continue };
+            if footnote.definition.is_none() {
+                report.undefined_references.push(label);
+            } else if footnote.references == 0 {
+                report.unreferenced_definitions.push(label);
+            }
+        }
+        report
+    }
+
+    /// Renumbers numbered and anonymous footnotes to 1, 2, 3, ... in the order they first appear
+    /// Anonymous inline footnotes [fn:: text] get a number too, footnotes with a name like [fn:source] are left alone
+    pub fn renumber_footnotes(&mut self) {
+        let mut numbers: HashMap<String, String> = HashMap::new();
+        let mut anonymous: Vec<String> = Vec::new();
+        let mut next = 1;
+        for footnote in self.footnotes() {
+            match footnote.label {
+                Some(label) if label.chars().all(|c| c.is_ascii_digit()) => {
+                    numbers.insert(label, next.to_string());
+                }
+                Some(_) => continue,
+                None => anonymous.push(next.to_string()),
+            }
+            next += 1;
+        }
+        let mut anonymous = anonymous.into_iter();
+        for child in &mut self.children {
+            renumber(child, &numbers, &mut anonymous);
+        }
+    }
+}
+
+fn named<'a>(footnotes: &'a mut Vec<Footnote>, label: &str) -> &'a mut Footnote {
+    let position = footnotes
+        .iter()
+        .position(|footnote| footnote.label.as_deref() == Some(label));
+    let index = match position {
+        Some(index) => index,
+        None => {
+            footnotes.push(Footnote {
+                label: Some(label.to_string()),
+                definition: None,
+                references: 0,
+            });
+            footnotes.len() - 1
+        }
+    };
+    &mut footnotes[index]
+}
+
+fn collect_references(objects: &[InlineObject], footnotes: &mut Vec<Footnote>) {
+    for object in objects {
+        if let InlineObject::FootnoteReference { label, definition, .. } = object {
+            let definition = definition.as_ref().map(|d| inline::build_inline(d));
+            match label {
+                Some(label) => {
+                    let footnote = named(footnotes, label);
+                    footnote.references += 1;
+                    if definition.is_some() {
+                        footnote.definition = definition;
+                    }
+                }
+                None => footnotes.push(Footnote {
+                    label: None,
+                    definition,
+                    references: 1,
+                }),
+            }
+        }
+        if let Some(children) = object.children() {
+            collect_references(children, footnotes);
+        }
+    }
+}
+
+fn renumber(obj: &mut Object, numbers: &HashMap<String, String>, anonymous: &mut impl Iterator<Item = String>) {
+    if let ObjectTypes::FootnoteDefinition { label, .. } = obj.object_type_mut() {
+        if let Some(number) = numbers.get(label.as_str()) {
+            *label = number.clone();
+        }
+    } else {
+        let objects = obj.object_type().inline_objects();
+        let mut edits = Vec::new();
+        if let Some(text) = obj.object_type_mut().text_mut() {
+            // Only the labels are replaced, the rest of the line stays as it was written
+            if relabel(text, &objects, &mut 0, numbers, anonymous, &mut edits).is_some() {
+                for (range, label) in edits.into_iter().rev() {
+                    text.replace_range(range, &label);
+                }
+            }
+        }
+    }
+    for child in obj.children_mut() {
+        renumber(child, numbers, anonymous);
+    }
+}
+
+/// Collects where labels of the references in text change, pos is where objects start in text
+/// Returns None if the text does not look like the objects say, then nothing is changed
+fn relabel(
+    text: &str,
+    objects: &[InlineObject],
+    pos: &mut usize,
+    numbers: &HashMap<String, String>,
+    anonymous: &mut impl Iterator<Item = String>,
+    edits: &mut Vec<(Range<usize>, String)>,
+) -> Option<()> {
+    for object in objects {
+        match object {
+            InlineObject::FootnoteReference { label, definition, .. } => {
+                if !text.get(*pos..)?.starts_with("[fn:") {
+                    return None;
+                }
+                let start = *pos + 4;
+                let end = start + label.as_ref().map_or(0, |label| label.len());
+                match label {
+                    Some(label) => {
+                        if let Some(number) = numbers.get(label.as_str()).filter(|number| *number != label) {
+                            edits.push((start..end, number.clone()));
+                        }
+                    }
+                    None => edits.push((start..end, anonymous.next()?)),
+                }
+                *pos = end + 1;
+                if let Some(definition) = definition {
+                    *pos += whitespace(text.get(*pos..)?);
+                    relabel(text, definition, pos, numbers, anonymous, edits)?;
+                    *pos += whitespace(text.get(*pos..)?);
+                    if !text.get(*pos..)?.starts_with(']') {
+                        return None;
+                    }
+                    *pos += 1;
+                }
+            }
+            _ if !has_reference(std::slice::from_ref(object)) => *pos += object.build().len(),
+            InlineObject::Link { target, description: Some(description) } => {
+                *pos += target.len() + 4;
+                relabel(text, description, pos, numbers, anonymous, edits)?;
+                *pos += 2;
+            }
+            other => {
+                // Markup, one character around its contents
+                *pos += 1;
+                relabel(text, other.children()?, pos, numbers, anonymous, edits)?;
+                *pos += 1;
+            }
+        }
+    }
+    Some(())
+}
+
+fn has_reference(objects: &[InlineObject]) -> bool {
+    objects.iter().any(|object| {
+        matches!(object, InlineObject::FootnoteReference { .. }) || object.children().is_some_and(|children| has_reference(children))
+    })
+}
+
+fn whitespace(text: &str) -> usize {
+    text.len() - text.trim_start().len()
+}
@@ -0,0 +1,210 @@
+use chrono::{Duration, NaiveDate};
+use regex::Regex;
+
+use crate::{
+    link::heading_title,
+    object::Object,
+    object_types::ObjectTypes,
+    structs::{self, TodoStates},
+    time_management::{ParsedDateTime, Repeater},
+};
+
+/// A habit: a heading with :STYLE: habit, or a LOOP heading, that has a repeating SCHEDULED or DEADLINE
+#[derive(Debug, Clone)]
+pub struct Habit {
+    pub title: String,
+    /// The next time it is scheduled
+    pub scheduled: ParsedDateTime,
+    pub repeater: Repeater,
+    /// Days it was done on, from the State "DONE" notes of the LOGBOOK and LAST_REPEAT, oldest first
+    pub completions: Vec<NaiveDate>,
+}
+
+/// How a day looks in the consistency graph, the colors are the ones org-habit uses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DayState {
+    /// Blue, done recently enough, nothing to do yet
+    Early,
+    /// Green, can be done now
+    Due,
+    /// Yellow, the last day before it is overdue
+    Deadline,
+    /// Red, should have been done already
+    Overdue,
+}
+
+/// A day of the consistency graph
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GraphDay {
+    pub date: NaiveDate,
+    pub state: DayState,
+    pub done: bool,
+}
+
+/// How well a habit was kept up
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct HabitStats {
+    /// Completions in a row, each at most the deadline interval after the one before, that are still going on
+    pub current_streak: usize,
+    pub longest_streak: usize,
+    pub total: usize,
+    /// Completions in the last 30 days
+    pub last_30_days: usize,
+}
+
+impl Habit {
+    /// Reads the habit of a heading, None if the heading is no habit
+    pub fn from_heading(obj: &Object) -> Option<Habit> {
+        let ObjectTypes::Heading {
+            text,
+            todo,
+            scheduled,
+            deadline,
+            ..
+        } = obj.object_type()
+        else {
+            return None;
+        };
+        let is_habit = obj.property("STYLE").is_some_and(|style| style.eq_ignore_ascii_case("habit"));
+        if !is_habit && *todo != Some(TodoStates::LOOP) {
+            return None;
+        }
+        let date = scheduled
+            .as_ref()
+            .filter(|date| date.repeater().is_some())
+            .or(deadline.as_ref().filter(|date| date.repeater().is_some()))?;
+        let re = Regex::new(r#"^\s*- State "DONE"\s+from\s+\S+\s+(\[[^\]]+\])"#).unwrap();
+        let mut completions: Vec<NaiveDate> = obj
+            .logbook()
+            .iter()
+            .filter_map(|line| re.captures(line))
+            .filter_map(|caps| ParsedDateTime::parse(&caps[1]))
+            .chain(obj.property("LAST_REPEAT").and_then(ParsedDateTime::parse))
+            .map(|date| date.date())
+            .collect();
+        completions.sort();
+        completions.dedup();
+        Some(Habit {
+            title: heading_title(text),
+            scheduled: date.clone(),
+            repeater: date.repeater()?,
+            completions,
+        })
+    }
+
+    /// The state of every day from..=to, as seen from today: days after today assume it is not done until then
+    pub fn graph(&self, from: NaiveDate, to: NaiveDate, today: NaiveDate) -> Vec<GraphDay> {
+        let min = self.repeater.days();
+        let max = self.repeater.deadline_days().max(min);
+        let mut days = Vec::new();
+        let mut date = from;
+        while date <= to {
+            let last = self.completions.iter().rev().find(|done| **done < date.min(today + Duration::days(1)));
+            let (due, deadline) = match last {
+                Some(last) => (*last + Duration::days(min), *last + Duration::days(max)),
+                None => (self.scheduled.date(), self.scheduled.date() + Duration::days(max - min)),
+            };
+            let state = if date < due {
+                DayState::Early
+            } else if date < deadline {
+                DayState::Due
+            } else if date == deadline {
+                DayState::Deadline
+            } else {
+                DayState::Overdue
+            };
+            days.push(GraphDay {
+                date,
+                state,
+                done: self.completions.contains(&date),
+            });
+            date += Duration::days(1);
+        }
+        days
+    }
+
+    /// Streaks count completions that follow each other within the deadline interval
+    pub fn stats(&self, today: NaiveDate) -> HabitStats {
+        let allowed = self.repeater.deadline_days().max(self.repeater.days());
+        let mut streak = 0;
+        let mut longest = 0;
+        let mut previous: Option<NaiveDate> = None;
+        for done in &self.completions {
+            streak = match previous {
+                Some(previous) if (*done - previous).num_days() <= allowed => streak + 1,
+                _ => 1,
+            };
+            longest = longest.max(streak);
+            previous = Some(*done);
+        }
+        let going_on = previous.is_some_and(|last| (today - last).num_days() <= allowed);
+        HabitStats {
+            current_streak: if going_on { streak } else { 0 },
+            longest_streak: longest,
+            total: self.completions.len(),
+            last_30_days: self
+                .completions
+                .iter()
+                .filter(|done| (today - **done).num_days() < 30 && **done <= today)
+                .count(),
+        }
+    }
+}
+
+impl DayState {
+    /// A character for plain text graphs, where colors are not available
+    pub fn ascii(&self) -> char {
+        match self {
+            DayState::Early => ' ',
+            DayState::Due => '-',
+            DayState::Deadline => '=',
+            DayState::Overdue => '#',
+        }
+    }
+    /// The css class used by the html graph
+    pub fn class(&self) -> &str {
+        match self {
+            DayState::Early => "habit-early",
+            DayState::Due => "habit-due",
+            DayState::Deadline => "habit-deadline",
+            DayState::Overdue => "habit-overdue",
+        }
+    }
+}
+
+/// The colors of org-habit for the classes of the html graph
+pub const HABIT_CSS: &str = ".habit-graph span { display: inline-block; width: 1em; text-align: center; font-family: monospace; }
+.habit-early { background: #4a75c5; } .habit-due { background: #3aa33a; }
+.habit-deadline { background: #e5c12d; } .habit-overdue { background: #d24b4b; }
+.habit-today { outline: 1px solid black; }";
+
+/// The graph as one line of text: * on days it was done, ! for today if it is not done yet
+pub fn ascii_graph(days: &[GraphDay], today: NaiveDate) -> String {
+    days.iter()
+        .map(|day| match (day.done, day.date == today) {
+            (true, _) => '*',
+            (false, true) => '!',
+            (false, false) => day.state.ascii(),
+        })
+        .collect()
+}
+
+/// The graph as html spans, styled with HABIT_CSS
+pub fn html_graph(days: &[GraphDay], today: NaiveDate) -> String {
+    let spans: String = days
+        .iter()
+        .map(|day| {
+            let today_class = if day.date == today { " habit-today" } else { "" };
+            let mark = if day.done { "*" } else { "&nbsp;" };
+            format!("<span class=\"{}{today_class}\" title=\"{}\">{mark}</span>", day.state.class(), day.date)
+        })
+        .collect();
+    format!("<div class=\"habit-graph\">{spans}</div>")
+}
+
+impl structs::File {
+    /// All habits of the file
+    pub fn habits(&self) -> Vec<Habit> {
+        self.iter().filter_map(Habit::from_heading).collect()
+    }
+}
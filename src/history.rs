@@ -0,0 +1,418 @@
+use std::io::Write;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime};
+use clap::ValueEnum;
+
+use crate::{link::heading_title, object::Object, object_types::ObjectTypes, structs, traversal::is_heading};
+
+/// The properties archiving adds, they are removed again when an item is restored
+pub const ARCHIVE_PROPERTIES: [&str; 6] = [
+    "ARCHIVE_TIME",
+    "ARCHIVE_FILE",
+    "ARCHIVE_OLPATH",
+    "ARCHIVE_CATEGORY",
+    "ARCHIVE_TODO",
+    "ARCHIVE_ITAGS",
+];
+
+/// How the history file is written
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum HistoryFormat {
+    /// One heading per run with the archived subtrees below it
+    #[default]
+    Org,
+    /// One JSON object per archived item and line, needs the serde feature
+    #[cfg(feature = "serde")]
+    Jsonl,
+}
+
+/// A history file: the archived subtrees of every run
+/// Runs are org headings with a RUN_TIME property, or in older history files start with a TIMESTAMP:<millis> line
+/// Archive files like todo.org_archive work too, they just have a single batch without a time
+#[derive(Debug, Default)]
+pub struct History {
+    format: HistoryFormat,
+    batches: Vec<Batch>,
+}
+
+/// The items one run archived
+#[derive(Debug, Default)]
+struct Batch {
+    /// The TIMESTAMP:<millis> of the run in older history files
+    millis: Option<i64>,
+    /// The heading of the run, without the archived items below it
+    run: Option<Object>,
+    /// When the run was, for JSON lines
+    time: Option<NaiveDateTime>,
+    /// Lines that are not part of an archived heading, like the header of an archive file
+    text: Vec<Object>,
+    items: Vec<Object>,
+}
+
+/// How completed items are grouped in a report
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Grouping {
+    Day,
+    /// ISO weeks, like 2024-W28
+    Week,
+    Month,
+    /// Every tag of the item, inherited ones included
+    Tag,
+}
+
+/// An archived subtree of the history
+#[derive(Debug, Clone, Copy)]
+pub struct ArchivedItem<'a> {
+    /// Position in the history, counting from 0 over all batches
+    pub number: usize,
+    /// When it was archived: ARCHIVE_TIME, or the TIMESTAMP of the run for old entries without it
+    pub time: Option<NaiveDateTime>,
+    pub subtree: &'a Object,
+}
+
+impl History {
+    /// Parses an org history or archive file, or a JSON Lines history
+    /// Fails on a JSON line that is not an archived item, saving would drop it otherwise
+    pub fn parse(lines: Vec<String>) -> Result<History, String> {
+        if lines.iter().find(|line| !line.trim().is_empty()).is_some_and(|line| line.trim_start().starts_with('{')) {
+            #[cfg(feature = "serde")]
+            return jsonl::parse(&lines);
+            #[cfg(not(feature = "serde"))]
+            return Err(NEEDS_SERDE.to_string());
+        }
+        let mut chunks: Vec<(Option<i64>, Vec<String>)> = vec![(None, Vec::new())];
+        for line in lines {
+            match line.strip_prefix("TIMESTAMP:").map(|millis| millis.trim().parse::<i64>()) {
+                Some(Ok(millis)) => chunks.push((Some(millis), Vec::new())),
+                _ => chunks.last_mut().unwrap().1.push(line),
+            }
+        }
+        let mut batches = Vec::new();
+        for (millis, lines) in chunks {
+            if millis.is_none() && lines.is_empty() {
+                continue;
+            }
+            let file = structs::File::parse(structs::FileContext::Todo, lines);
+            let mut batch = Batch { millis, ..Batch::default() };
+            for mut obj in file.children {
+                if obj.property("RUN_TIME").is_some() {
+                    let mut run = Batch::default();
+                    for child in std::mem::take(obj.children_mut()) {
+                        match is_heading(&child) {
+                            true => add_item(child, &mut run.items),
+                            false => obj.add_child(child),
+                        }
+                    }
+                    run.run = Some(obj);
+                    batches.push(std::mem::replace(&mut batch, run));
+                } else if is_heading(&obj) {
+                    add_item(obj, &mut batch.items);
+                } else {
+                    batch.text.push(obj);
+                }
+            }
+            batches.push(batch);
+        }
+        batches.retain(|batch| batch.millis.is_some() || batch.run.is_some() || !batch.text.is_empty() || !batch.items.is_empty());
+        Ok(History {
+            format: HistoryFormat::Org,
+            batches,
+        })
+    }
+
+    pub fn open(path: &str) -> std::io::Result<History> {
+        check_path(path)?;
+        let content = std::fs::read_to_string(path)?;
+        History::parse(content.lines().map(|line| line.to_string()).collect())
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{path}: {error}")))
+    }
+
+    /// The lines of the history, in the format it was read in
+    pub fn build(&self) -> Vec<String> {
+        match self.format {
+            HistoryFormat::Org => (),
+            #[cfg(feature = "serde")]
+            HistoryFormat::Jsonl => return self.items().iter().map(|item| item.json()).collect(),
+        }
+        let mut lines = Vec::new();
+        for batch in &self.batches {
+            if let Some(millis) = batch.millis {
+                lines.push(format!("TIMESTAMP:{millis}"));
+            }
+            lines.extend(batch.run.iter().chain(&batch.text).chain(&batch.items).flat_map(|obj| obj.build()));
+        }
+        lines
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let mut content = self.build().join("\n");
+        content.push('\n');
+        std::fs::write(path, content)
+    }
+
+    /// All archived items, oldest run first
+    pub fn items(&self) -> Vec<ArchivedItem<'_>> {
+        let mut items = Vec::new();
+        for batch in &self.batches {
+            let batch_time = batch
+                .millis
+                .and_then(DateTime::from_timestamp_millis)
+                .map(|time| time.with_timezone(&Local).naive_local())
+                .or_else(|| batch.run.as_ref().and_then(|run| run.property("RUN_TIME")).and_then(parse_time))
+                .or(batch.time);
+            for subtree in &batch.items {
+                let time = subtree
+                    .property("ARCHIVE_TIME")
+                    .and_then(parse_time)
+                    .or(batch_time);
+                items.push(ArchivedItem {
+                    number: items.len(),
+                    time,
+                    subtree,
+                });
+            }
+        }
+        items
+    }
+
+    /// The items that where completed between from and to, by the day of their CLOSED or else the day they where archived
+    pub fn completions(&self, from: NaiveDate, to: NaiveDate) -> Vec<ArchivedItem<'_>> {
+        self.items()
+            .into_iter()
+            .filter(|item| item.completed().is_some_and(|date| from <= date && date <= to))
+            .collect()
+    }
+
+    /// Removes an item from the history and hands it back, the RUN_ARCHIVED of its run counts one less
+    pub fn take(&mut self, number: usize) -> Option<Object> {
+        let mut number = number;
+        for batch in &mut self.batches {
+            if number < batch.items.len() {
+                if let Some(run) = &mut batch.run {
+                    if let Some(count) = run.property("RUN_ARCHIVED").and_then(|count| count.trim().parse::<usize>().ok()) {
+                        run.set_property("RUN_ARCHIVED", &count.saturating_sub(1).to_string());
+                    }
+                }
+                return Some(batch.items.remove(number));
+            }
+            number -= batch.items.len();
+        }
+        None
+    }
+}
+
+/// Adds an archived heading, headings below it that where archived on their own (they have an ARCHIVE_TIME) become items too
+/// The history keeps the levels they had, so an archived ** heading ends up below the * heading archived before it
+fn add_item(mut obj: Object, items: &mut Vec<Object>) {
+    let position = items.len();
+    split_items(obj.children_mut(), items);
+    items.insert(position, obj);
+}
+
+fn split_items(children: &mut Vec<Object>, items: &mut Vec<Object>) {
+    let mut index = 0;
+    while index < children.len() {
+        if is_heading(&children[index]) && children[index].property("ARCHIVE_TIME").is_some() {
+            add_item(children.remove(index), items);
+        } else {
+            split_items(children[index].children_mut(), items);
+            index += 1;
+        }
+    }
+}
+
+impl ArchivedItem<'_> {
+    /// The day it was completed: its CLOSED, or else when it was archived
+    pub fn completed(&self) -> Option<NaiveDate> {
+        match self.subtree.object_type() {
+            ObjectTypes::Heading { closed: Some(closed), .. } => Some(closed.date()),
+            _ => self.time.map(|time| time.date()),
+        }
+    }
+    /// Its own tags and the ones it inherited before it got archived
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .subtree
+            .property("ARCHIVE_ITAGS")
+            .map(|tags| tags.split_whitespace().map(|tag| tag.to_string()).collect())
+            .unwrap_or_default();
+        tags.extend(self.subtree.object_type().tags());
+        let mut seen = std::collections::HashSet::new();
+        tags.retain(|tag| seen.insert(tag.clone()));
+        tags
+    }
+    pub fn todo(&self) -> Option<String> {
+        match self.subtree.object_type() {
+            ObjectTypes::Heading { todo, .. } => todo.as_ref().map(|todo| todo.build()),
+            _ => None,
+        }
+    }
+    pub fn title(&self) -> String {
+        match self.subtree.object_type() {
+            ObjectTypes::Heading { text, .. } => heading_title(text),
+            _ => String::new(),
+        }
+    }
+    /// The titles of the headings it was below before it got archived, empty for old entries
+    pub fn outline_path(&self) -> Vec<String> {
+        self.subtree.property("ARCHIVE_OLPATH").map(split_olpath).unwrap_or_default()
+    }
+    /// The file it was archived from, if it is known
+    pub fn source(&self) -> Option<&str> {
+        self.subtree.property("ARCHIVE_FILE")
+    }
+    /// Case insensitive search in the title, the outline path and the text below the heading
+    pub fn contains(&self, term: &str) -> bool {
+        let term = term.to_lowercase();
+        self.outline_path().iter().any(|title| title.to_lowercase().contains(&term))
+            || self.subtree.build().iter().any(|line| line.to_lowercase().contains(&term))
+    }
+}
+
+/// Groups items by the day, week or month they where completed, or by their tags, in ascending order
+pub fn group<'a>(items: &[ArchivedItem<'a>], grouping: Grouping) -> Vec<(String, Vec<ArchivedItem<'a>>)> {
+    let mut groups: std::collections::BTreeMap<String, Vec<ArchivedItem>> = std::collections::BTreeMap::new();
+    for item in items {
+        let keys = match (grouping, item.completed()) {
+            (Grouping::Tag, _) => item.tags(),
+            (_, None) => vec!["unknown".to_string()],
+            (Grouping::Day, Some(date)) => vec![date.format("%Y-%m-%d %a").to_string()],
+            (Grouping::Week, Some(date)) => vec![format!("{}-W{:02}", date.iso_week().year(), date.iso_week().week())],
+            (Grouping::Month, Some(date)) => vec![date.format("%Y-%m").to_string()],
+        };
+        for key in keys {
+            groups.entry(key).or_default().push(*item);
+        }
+    }
+    groups.into_iter().collect()
+}
+
+/// Appends the items archived in one run to the history file, as a run heading or as JSON lines
+pub fn append_run(path: &str, format: HistoryFormat, time: NaiveDateTime, source: &str, items: Vec<Object>) -> std::io::Result<()> {
+    if items.is_empty() {
+        return Ok(());
+    }
+    check_path(path)?;
+    let mut file = std::fs::OpenOptions::new().append(true).open(path)?;
+    let lines = match format {
+        HistoryFormat::Org => {
+            let mut run = Object::heading(&format!("[{}] {source}", time.format("%Y-%m-%d %a %H:%M")));
+            run.set_property("RUN_TIME", &time.format("%Y-%m-%d %a %H:%M").to_string());
+            run.set_property("RUN_FILE", source);
+            run.set_property("RUN_ARCHIVED", &items.len().to_string());
+            for mut item in items {
+                item.set_level(2).map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidInput, error))?;
+                run.add_child(item);
+            }
+            run.build()
+        }
+        #[cfg(feature = "serde")]
+        HistoryFormat::Jsonl => {
+            let history = History {
+                format,
+                batches: vec![Batch {
+                    time: Some(time),
+                    items,
+                    ..Batch::default()
+                }],
+            };
+            history.build()
+        }
+    };
+    for line in lines {
+        writeln!(file, "{line}")?;
+    }
+    Ok(())
+}
+
+const NEEDS_SERDE: &str = "JSON Lines histories need the serde feature";
+
+/// Without the serde feature a .jsonl history can't be read or written, it would be taken for an org file
+fn check_path(path: &str) -> std::io::Result<()> {
+    if cfg!(not(feature = "serde")) && std::path::Path::new(path).extension().is_some_and(|extension| extension == "jsonl") {
+        return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, NEEDS_SERDE));
+    }
+    Ok(())
+}
+
+fn parse_time(time: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(time, "%Y-%m-%d %a %H:%M").ok()
+}
+
+fn split_olpath(olpath: &str) -> Vec<String> {
+    olpath.split('/').filter(|title| !title.is_empty()).map(|title| title.to_string()).collect()
+}
+
+impl structs::File {
+    /// Puts an archived subtree back to where it was archived from, and removes the ARCHIVE_ properties again
+    /// Parents of the outline path that don't exist anymore are created. Returns the path of the parent
+    pub fn restore(&mut self, mut subtree: Object) -> Result<Vec<String>, String> {
+        let path = subtree.property("ARCHIVE_OLPATH").map(split_olpath).unwrap_or_default();
+        for property in ARCHIVE_PROPERTIES {
+            subtree.remove_property(property);
+        }
+        for depth in 0..path.len() {
+            let parent: Vec<&str> = path[..depth + 1].iter().map(|title| title.as_str()).collect();
+            if self.find_path(&parent).is_none() {
+                self.insert_heading(&parent[..depth], Object::heading(&path[depth]))?;
+            }
+        }
+        self.insert_heading(&path.iter().map(|title| title.as_str()).collect::<Vec<_>>(), subtree)?;
+        Ok(path)
+    }
+}
+
+#[cfg(feature = "serde")]
+mod jsonl {
+    //! JSON Lines histories, one archived item per line
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// Every line is a batch of its own, so items without ARCHIVE_TIME keep the time of their run
+    pub(super) fn parse(lines: &[String]) -> Result<History, String> {
+        let batches = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(number, line)| {
+                let bad = |error: String| format!("line {}: {error}", number + 1);
+                let value: Value = serde_json::from_str(line).map_err(|error| bad(error.to_string()))?;
+                let lines = value.get("lines").cloned().ok_or_else(|| bad("no \"lines\"".to_string()))?;
+                let lines: Vec<String> = serde_json::from_value(lines).map_err(|error| bad(error.to_string()))?;
+                let file = structs::File::parse(structs::FileContext::Todo, lines);
+                let subtree = file.children.into_iter().find(is_heading).unwrap_or_default();
+                let time = value["archived"]
+                    .as_str()
+                    .and_then(|time| NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M").ok());
+                Ok(Batch {
+                    time,
+                    items: vec![subtree],
+                    ..Batch::default()
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(History {
+            format: HistoryFormat::Jsonl,
+            batches,
+        })
+    }
+
+    impl ArchivedItem<'_> {
+        /// A line of a JSON Lines history, lines holds the whole subtree so it can be restored
+        pub fn json(&self) -> String {
+            json!({
+                "archived": self.time.map(|time| time.format("%Y-%m-%dT%H:%M").to_string()),
+                "completed": self.completed().map(|date| date.to_string()),
+                "source": self.source(),
+                "category": self.subtree.property("ARCHIVE_CATEGORY"),
+                "todo": self.todo(),
+                "title": self.title(),
+                "path": self.outline_path(),
+                "tags": self.tags(),
+                "lines": self.subtree.build(),
+            })
+            .to_string()
+        }
+    }
+}
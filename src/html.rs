@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+
+use crate::{
+    export::{self, Element, Heading, List, ListKind, Row},
+    inline::{self, InlineObject},
+    link::{self, Link, LinkType},
+    structs::{self, TodoStates},
+    time_management::InfoType,
+};
+
+/// The page the exported content is put into
+/// {{title}}, {{author}}, {{head}} (#+HTML_HEAD lines), {{style}}, {{toc}} and {{content}} are replaced
+pub const DEFAULT_TEMPLATE: &str = "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{{title}}</title>
+<style>
+{{style}}
+</style>
+{{head}}
+</head>
+<body>
+<h1 class=\"title\">{{title}}</h1>
+{{toc}}
+{{content}}
+</body>
+</html>
+";
+
+/// Styles for the classes the exporter uses, replaces {{style}} in the template
+pub const DEFAULT_STYLE: &str = ".todo { color: #c0392b; font-weight: bold; }
+.done { color: #27ae60; font-weight: bold; }
+.priority { color: #d68910; }
+.tag { background: #eee; border-radius: 3px; padding: 0 4px; margin-left: 4px; font-size: 80%; }
+.timestamp { color: #7f8c8d; }
+.planning { font-size: 90%; }
+pre.src { background: #f5f5f5; padding: 8px; }
+table { border-collapse: collapse; } td, th { border: 1px solid #ccc; padding: 2px 8px; }";
+
+/// How a file is exported to html
+#[derive(Debug, Clone)]
+pub struct HtmlOptions {
+    pub template: String,
+    pub style: String,
+    /// Headings up to this level go into the table of contents, 0 leaves it out
+    /// #+OPTIONS: toc:nil or toc:2 in the file wins
+    pub toc_depth: u32,
+    /// Write the planning lines below the headings
+    pub planning: bool,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        HtmlOptions {
+            template: DEFAULT_TEMPLATE.to_string(),
+            style: DEFAULT_STYLE.to_string(),
+            toc_depth: 3,
+            planning: true,
+        }
+    }
+}
+
+/// Turns elements into html, knows the link abbreviations and heading anchors and collects the footnotes
+struct Renderer<'a> {
+    options: &'a HtmlOptions,
+    abbreviations: HashMap<String, String>,
+    anchors: HashMap<String, String>,
+    footnotes: Vec<(String, String)>,
+}
+
+impl structs::File {
+    /// The whole page, with the content put into the template of the options
+    pub fn to_html(&self, options: &HtmlOptions) -> String {
+        let elements = self.elements();
+        let mut renderer = Renderer {
+            options,
+            abbreviations: link::abbreviations(self),
+            anchors: export::heading_anchors(&elements),
+            footnotes: Vec::new(),
+        };
+        let content = renderer.elements(&elements);
+        let toc_depth = self
+            .keyword("OPTIONS")
+            .iter()
+            .flat_map(|options| options.split_whitespace())
+            .find_map(|option| option.strip_prefix("toc:"))
+            .map(|toc| toc.parse().unwrap_or(if toc == "nil" { 0 } else { options.toc_depth }))
+            .unwrap_or(options.toc_depth);
+        let title = self.keyword("TITLE").pop().unwrap_or_default();
+        fill(
+            &options.template,
+            &[
+                ("title", escape(&title)),
+                ("author", escape(&self.keyword("AUTHOR").pop().unwrap_or_default())),
+                ("head", self.keyword("HTML_HEAD").join("\n")),
+                ("style", options.style.clone()),
+                ("toc", toc(&export::headings(&elements, toc_depth))),
+                ("content", content),
+            ],
+        )
+    }
+}
+
+/// Replaces the {{placeholders}} of a template in one pass, so a {{toc}} in the title or the content stays as it is
+fn fill(template: &str, values: &[(&str, String)]) -> String {
+    let mut filled = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = values.iter().find(|(name, _)| rest[2..].starts_with(name) && rest[2 + name.len()..].starts_with("}}"));
+        match value {
+            Some((name, value)) => {
+                filled.push_str(value);
+                rest = &rest[name.len() + 4..];
+            }
+            None => {
+                filled.push_str("{{");
+                rest = &rest[2..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// The html of the elements alone, without a template around it
+pub fn render(elements: &[Element], options: &HtmlOptions) -> String {
+    let mut renderer = Renderer {
+        options,
+        abbreviations: HashMap::new(),
+        anchors: export::heading_anchors(elements),
+        footnotes: Vec::new(),
+    };
+    renderer.elements(elements)
+}
+
+/// A nested list of links to the headings, empty if there are none
+pub fn toc(headings: &[&Heading]) -> String {
+    if headings.is_empty() {
+        return String::new();
+    }
+    let mut html = String::from("<nav id=\"table-of-contents\">\n<h2>Table of Contents</h2>\n");
+    let mut open: Vec<u32> = Vec::new();
+    for heading in headings {
+        while open.last().is_some_and(|level| *level > heading.level) {
+            open.pop();
+            html.push_str("</li>\n</ul>\n");
+        }
+        match open.last() {
+            Some(level) if *level == heading.level => html.push_str("</li>\n"),
+            _ => {
+                open.push(heading.level);
+                html.push_str("<ul>\n");
+            }
+        }
+        html.push_str(&format!("<li><a href=\"#{}\">{}</a>", heading.anchor, escape(&plain_text(&heading.title))));
+    }
+    for _ in open {
+        html.push_str("</li>\n</ul>\n");
+    }
+    html.push_str("</nav>");
+    html
+}
+
+fn plain_text(text: &str) -> String {
+    inline::parse_inline(text).iter().map(|object| object.plain_text()).collect()
+}
+
+/// Escapes the characters html gives a meaning
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// The html of a line of org text, with its markup, links and timestamps
+/// Links to headings point to the slug of their title, the renderer knows their anchors
+pub fn inline_html(text: &str, abbreviations: &HashMap<String, String>) -> String {
+    objects_html(&inline::parse_inline(text), abbreviations, &HashMap::new())
+}
+
+fn objects_html(objects: &[InlineObject], abbreviations: &HashMap<String, String>, anchors: &HashMap<String, String>) -> String {
+    objects.iter().map(|object| object_html(object, abbreviations, anchors)).collect()
+}
+
+fn object_html(object: &InlineObject, abbreviations: &HashMap<String, String>, anchors: &HashMap<String, String>) -> String {
+    match object {
+        InlineObject::Plain(text) => escape(text),
+        InlineObject::Bold(children) => format!("<b>{}</b>", objects_html(children, abbreviations, anchors)),
+        InlineObject::Italic(children) => format!("<i>{}</i>", objects_html(children, abbreviations, anchors)),
+        InlineObject::Underline(children) => format!("<span class=\"underline\">{}</span>", objects_html(children, abbreviations, anchors)),
+        InlineObject::StrikeThrough(children) => format!("<del>{}</del>", objects_html(children, abbreviations, anchors)),
+        InlineObject::Verbatim(text) | InlineObject::Code(text) => format!("<code>{}</code>", escape(text)),
+        InlineObject::Link { target, description } => {
+            let link = Link::parse(target, None, abbreviations);
+            let href = escape(&href(&link, anchors));
+            let is_image = [".png", ".jpg", ".jpeg", ".gif", ".svg", ".webp"]
+                .iter()
+                .any(|extension| link.target.to_lowercase().ends_with(extension));
+            match description {
+                None if is_image => format!("<img src=\"{href}\" alt=\"{href}\">"),
+                Some(description) => format!("<a href=\"{href}\">{}</a>", objects_html(description, abbreviations, anchors)),
+                None => format!("<a href=\"{href}\">{}</a>", escape(target)),
+            }
+        }
+        InlineObject::Timestamp { raw, .. } => timestamp(raw),
+        InlineObject::FootnoteReference { label, definition, .. } => {
+            let label = label.clone().filter(|label| !label.is_empty()).unwrap_or_else(|| "*".to_string());
+            let title = definition
+                .as_ref()
+                .map(|definition| format!(" title=\"{}\"", escape(&definition.iter().map(|d| d.plain_text()).collect::<String>())))
+                .unwrap_or_default();
+            format!("<sup><a id=\"fnr.{label}\" href=\"#fn.{label}\"{title}>{label}</a></sup>")
+        }
+        InlineObject::Entity { name, .. } => match inline::entity_utf8(name) {
+            Some(utf8) => escape(utf8),
+            None => format!("&{name};"),
+        },
+        InlineObject::LatexFragment(text) => escape(text),
+        InlineObject::Macro { .. } => String::new(),
+        InlineObject::RadioTarget(text) => format!("<a id=\"{}\">{}</a>", export::slug(text), escape(text)),
+        InlineObject::Target(text) => format!("<a id=\"{}\"></a>", export::slug(text)),
+    }
+}
+
+/// Where a link points to in the exported pages, links to org files point to their html export
+fn href(link: &Link, anchors: &HashMap<String, String>) -> String {
+    match &link.link_type {
+        LinkType::File { path, search } => {
+            let path = match path.strip_suffix(".org") {
+                Some(stem) => format!("{stem}.html"),
+                None => path.clone(),
+            };
+            match search {
+                Some(search) => format!("{path}#{}", search_anchor(search)),
+                None => path,
+            }
+        }
+        LinkType::Id(id) => format!("#{}", export::id_anchor(id, anchors)),
+        LinkType::External { .. } => link.target.clone(),
+        LinkType::Heading(heading) => format!("#{}", export::heading_anchor(heading, anchors)),
+        LinkType::CustomId(id) => format!("#{id}"),
+        LinkType::Fuzzy(target) => format!("#{}", export::heading_anchor(target, anchors)),
+    }
+}
+
+fn search_anchor(search: &str) -> String {
+    match search.strip_prefix('#') {
+        Some(custom_id) => custom_id.to_string(),
+        None => export::slug(search.trim_start_matches('*')),
+    }
+}
+
+fn timestamp(raw: &str) -> String {
+    format!("<span class=\"timestamp\">{}</span>", escape(raw))
+}
+
+fn todo_span(todo: &TodoStates) -> String {
+    let class = match todo {
+        TodoStates::DONE => "done",
+        _ => "todo",
+    };
+    let keyword = todo.build();
+    format!("<span class=\"{class} {keyword}\">{keyword}</span>")
+}
+
+impl Renderer<'_> {
+    fn elements(&mut self, elements: &[Element]) -> String {
+        let mut html: Vec<String> = elements.iter().map(|element| self.element(element)).filter(|html| !html.is_empty()).collect();
+        if !self.footnotes.is_empty() {
+            let definitions: String = self
+                .footnotes
+                .iter()
+                .map(|(label, text)| {
+                    format!("<div class=\"footdef\"><sup><a id=\"fn.{label}\" href=\"#fnr.{label}\">{label}</a></sup> {text}</div>\n")
+                })
+                .collect();
+            html.push(format!("<div id=\"footnotes\">\n<h2 class=\"footnotes\">Footnotes</h2>\n{definitions}</div>"));
+        }
+        html.join("\n")
+    }
+
+    fn inline(&self, text: &str) -> String {
+        objects_html(&inline::parse_inline(text), &self.abbreviations, &self.anchors)
+    }
+
+    fn element(&mut self, element: &Element) -> String {
+        match element {
+            Element::Heading(heading) => {
+                let level = (heading.level + 1).min(6);
+                let mut parts = Vec::new();
+                if let Some(todo) = &heading.todo {
+                    parts.push(todo_span(todo));
+                }
+                if let Some(priority) = &heading.priority {
+                    parts.push(format!("<span class=\"priority\">{}</span>", priority.build()));
+                }
+                parts.push(self.inline(&heading.title));
+                if !heading.tags.is_empty() {
+                    let tags: String = heading.tags.iter().map(|tag| format!("<span class=\"tag {0}\">{0}</span>", escape(tag))).collect();
+                    parts.push(format!("<span class=\"tags\">{tags}</span>"));
+                }
+                format!("<h{level} id=\"{}\">{}</h{level}>", escape(&heading.anchor), parts.join(" "))
+            }
+            Element::Planning(info) if self.options.planning => {
+                let parts: Vec<String> = info
+                    .iter()
+                    .map(|info| {
+                        let keyword = match info {
+                            InfoType::DEADLINE { .. } => "DEADLINE:",
+                            InfoType::SCHEDULED { .. } => "SCHEDULED:",
+                            InfoType::CLOSED { .. } => "CLOSED:",
+                        };
+                        format!("<span class=\"timestamp-kwd\">{keyword}</span> {}", timestamp(&info.date().build()))
+                    })
+                    .collect();
+                format!("<p class=\"planning\">{}</p>", parts.join(" "))
+            }
+            Element::Planning(_) | Element::Keyword { .. } => String::new(),
+            Element::Paragraph(lines) => format!("<p>\n{}\n</p>", lines.iter().map(|line| self.inline(line)).collect::<Vec<_>>().join("\n")),
+            Element::List(list) => self.list(list),
+            Element::Table(rows) => self.table(rows),
+            Element::Block { name, parameters, lines } => self.block(name, parameters, lines),
+            Element::FootnoteDefinition { label, text } => {
+                let text = self.inline(text);
+                self.footnotes.push((label.clone(), text));
+                String::new()
+            }
+            Element::Rule => "<hr>".to_string(),
+        }
+    }
+
+    fn list(&self, list: &List) -> String {
+        let tag = match list.kind {
+            ListKind::Unordered => "ul",
+            ListKind::Ordered => "ol",
+            ListKind::Description => "dl",
+        };
+        let mut html = format!("<{tag}>\n");
+        for item in &list.items {
+            let mut text = String::new();
+            if let Some(checked) = item.checkbox {
+                let class = if checked { "on" } else { "off" };
+                let checked = if checked { " checked" } else { "" };
+                text.push_str(&format!("<input type=\"checkbox\" class=\"{class}\" disabled{checked}> "));
+            }
+            if let Some(todo) = &item.todo {
+                text.push_str(&(todo_span(todo) + " "));
+            }
+            text.push_str(&self.inline(&item.text));
+            if let Some(sublist) = &item.sublist {
+                text.push('\n');
+                text.push_str(&self.list(sublist));
+            }
+            match (list.kind, &item.term) {
+                (ListKind::Description, Some(term)) => html.push_str(&format!("<dt>{}</dt><dd>{text}</dd>\n", self.inline(term))),
+                (ListKind::Description, None) => html.push_str(&format!("<dd>{text}</dd>\n")),
+                (_, Some(term)) => html.push_str(&format!("<li>{} :: {text}</li>\n", self.inline(term))),
+                (_, None) => html.push_str(&format!("<li>{text}</li>\n")),
+            }
+        }
+        html.push_str(&format!("</{tag}>"));
+        html
+    }
+
+    /// Rows before the first rule are the header, if there is a rule with rows after it
+    fn table(&self, rows: &[Row]) -> String {
+        let header_end = rows
+            .iter()
+            .position(|row| *row == Row::Rule)
+            .filter(|position| *position > 0 && rows[*position..].iter().any(|row| *row != Row::Rule));
+        let mut html = String::from("<table>\n");
+        for (index, row) in rows.iter().enumerate() {
+            let Row::Cells(cells) = row else {
+                continue;
+            };
+            let cell = if header_end.is_some_and(|end| index < end) { "th" } else { "td" };
+            let cells: String = cells.iter().map(|text| format!("<{cell}>{}</{cell}>", self.inline(text))).collect();
+            html.push_str(&format!("<tr>{cells}</tr>\n"));
+        }
+        html.push_str("</table>");
+        html
+    }
+
+    fn block(&self, name: &str, parameters: &str, lines: &[String]) -> String {
+        let language = parameters.split_whitespace().next().unwrap_or_default();
+        let raw = escape(&lines.join("\n"));
+        match name {
+            "src" => format!("<pre class=\"src src-{0}\"><code class=\"language-{0}\">{raw}</code></pre>", escape(language)),
+            "example" => format!("<pre class=\"example\">{raw}</pre>"),
+            "export" if language.eq_ignore_ascii_case("html") => lines.join("\n"),
+            "export" => String::new(),
+            "quote" => format!("<blockquote>\n{}\n</blockquote>", self.paragraphs(lines)),
+            "center" => format!("<div class=\"center\">\n{}\n</div>", self.paragraphs(lines)),
+            "verse" => format!(
+                "<p class=\"verse\">\n{}\n</p>",
+                lines.iter().map(|line| self.inline(line)).collect::<Vec<_>>().join("<br>\n")
+            ),
+            _ => format!("<div class=\"{}\">\n{}\n</div>", escape(name), self.paragraphs(lines)),
+        }
+    }
+
+    /// Text of a block, empty lines separate paragraphs
+    fn paragraphs(&self, lines: &[String]) -> String {
+        lines
+            .split(|line| line.trim().is_empty())
+            .filter(|paragraph| !paragraph.is_empty())
+            .map(|paragraph| format!("<p>\n{}\n</p>", paragraph.iter().map(|line| self.inline(line.trim())).collect::<Vec<_>>().join("\n")))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
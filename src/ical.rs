@@ -0,0 +1,804 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+use regex::Regex;
+
+use crate::{
+    inline,
+    link::heading_title,
+    object::Object,
+    object_types::ObjectTypes,
+    structs::{self, Priority, TodoStates},
+    time_management::{InfoType, ParsedDateTime, Repeat, RepeatKind, Repeater, Warning},
+};
+
+/// Who wrote the calendar, every iCalendar file needs one
+pub const PRODID: &str = "-//org-parse//org-parse//EN";
+
+/// A BEGIN:NAME up to END:NAME block of an iCalendar file, with its properties and nested components like VALARM
+#[derive(Debug, Clone, PartialEq)]
+pub struct Component {
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub components: Vec<Component>,
+}
+
+/// A line like DTSTART;VALUE=DATE:20240712
+#[derive(Debug, Clone, PartialEq)]
+pub struct Property {
+    /// Uppercased
+    pub name: String,
+    /// Names uppercased, values without quotes
+    pub parameters: Vec<(String, String)>,
+    /// The value as it is written, text is still escaped
+    pub value: String,
+}
+
+impl Component {
+    pub fn new(name: &str) -> Component {
+        Component {
+            name: name.to_uppercase(),
+            properties: Vec::new(),
+            components: Vec::new(),
+        }
+    }
+
+    /// Adds a property, the value is written as it is
+    pub fn add(&mut self, name: &str, value: &str) {
+        self.add_with(name, &[], value);
+    }
+
+    pub fn add_with(&mut self, name: &str, parameters: &[(&str, &str)], value: &str) {
+        self.properties.push(Property {
+            name: name.to_uppercase(),
+            parameters: parameters.iter().map(|(key, value)| (key.to_uppercase(), value.to_string())).collect(),
+            value: value.to_string(),
+        });
+    }
+
+    /// Adds a property with a text value, which gets escaped
+    pub fn add_text(&mut self, name: &str, text: &str) {
+        self.add(name, &escape(text));
+    }
+
+    /// The first property with that name
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.iter().find(|property| property.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The lines of the component, folded after 75 bytes like RFC 5545 wants
+    pub fn build(&self) -> Vec<String> {
+        let mut lines = vec![format!("BEGIN:{}", self.name)];
+        for property in &self.properties {
+            lines.extend(fold(&property.build()));
+        }
+        for component in &self.components {
+            lines.extend(component.build());
+        }
+        lines.push(format!("END:{}", self.name));
+        lines
+    }
+}
+
+impl Property {
+    /// The unfolded line
+    pub fn build(&self) -> String {
+        let mut line = self.name.clone();
+        for (key, value) in &self.parameters {
+            match value.contains([':', ';', ',']) {
+                true => line += &format!(";{key}=\"{value}\""),
+                false => line += &format!(";{key}={value}"),
+            }
+        }
+        format!("{line}:{}", self.value)
+    }
+
+    /// The value of a parameter like VALUE or TZID
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+impl structs::File {
+    /// The calendar of the file, as the text of an .ics file
+    /// Active DEADLINE, SCHEDULED and plain timestamps become VEVENTs, headings with a TODO keyword a VTODO
+    /// name is the path the file was read from, for the category, now is written as DTSTAMP
+    /// zone is where the times of the file are local times, CLOSED is written in UTC as COMPLETED
+    pub fn to_icalendar<Tz: TimeZone>(&self, name: &str, now: NaiveDateTime, zone: &Tz) -> String {
+        let mut calendar = Component::new("VCALENDAR");
+        calendar.add("VERSION", "2.0");
+        calendar.add("PRODID", PRODID);
+        calendar.add("CALSCALE", "GREGORIAN");
+        let category = self.category(name);
+        calendar.add_text("X-WR-CALNAME", &self.keyword("TITLE").pop().unwrap_or(category.clone()));
+        let mut path = Vec::new();
+        for child in &self.children {
+            collect(child, &category, now, zone, &mut path, &mut calendar);
+        }
+        let mut lines = calendar.build();
+        lines.push(String::new());
+        lines.join("\r\n")
+    }
+}
+
+/// Adds the components of a heading and everything below it
+fn collect<Tz: TimeZone>(obj: &Object, category: &str, now: NaiveDateTime, zone: &Tz, path: &mut Vec<String>, calendar: &mut Component) {
+    let ObjectTypes::Heading {
+        text,
+        todo,
+        deadline,
+        scheduled,
+        closed,
+        in_line_scedule,
+        priority,
+        ..
+    } = obj.object_type()
+    else {
+        return;
+    };
+    let title = heading_title(text);
+    path.push(title.clone());
+    // Without an ID the outline path is hashed, so the UIDs stay the same as long as the heading is not moved or renamed
+    let uid = match obj.property("ID") {
+        Some(id) => id.to_string(),
+        None => format!("{:016x}", hash(&format!("{category}/{}", path.join("/")))),
+    };
+    let mut categories = vec![escape(category)];
+    categories.extend(obj.object_type().tags().iter().map(|tag| escape(tag)));
+    let base = |kind: &str, prefix: &str, summary: &str| {
+        let mut component = Component::new(kind);
+        component.add("UID", &format!("{prefix}-{uid}"));
+        component.add("DTSTAMP", &now.format("%Y%m%dT%H%M%SZ").to_string());
+        component.add_text("SUMMARY", summary);
+        component.add("CATEGORIES", &categories.join(","));
+        if let Some(location) = obj.property("LOCATION") {
+            component.add_text("LOCATION", location);
+        }
+        component
+    };
+    let done = *todo == Some(TodoStates::DONE);
+    // Done deadlines and scheduled items would only clutter the calendar, the VTODO still says when it was done
+    let planning = [("DL", "DL: ", deadline), ("SC", "S: ", scheduled)];
+    for (prefix, summary, date) in planning {
+        let Some(date) = date.as_ref().filter(|date| date.is_active() && !done) else {
+            continue;
+        };
+        let mut event = base("VEVENT", prefix, &format!("{summary}{title}"));
+        event_dates(&mut event, date);
+        if let Some(warning) = date.warning().filter(|warning| warning.value > 0) {
+            event.components.push(alarm(warning.days(), &title));
+        }
+        calendar.components.push(event);
+    }
+    let mut timestamps: Vec<ParsedDateTime> = in_line_scedule.iter().cloned().collect();
+    for child in obj.children().iter().filter(|child| !matches!(child.object_type(), ObjectTypes::Heading { .. } | ObjectTypes::INFO { .. })) {
+        for line in child.iter() {
+            timestamps.extend(inline::collect_timestamps(&line.object_type().inline_objects()));
+        }
+    }
+    for (number, date) in timestamps.iter().filter(|date| date.is_active()).enumerate() {
+        let mut event = base("VEVENT", &format!("TS{}", number + 1), &title);
+        event_dates(&mut event, date);
+        calendar.components.push(event);
+    }
+    if let Some(todo) = todo {
+        let mut task = base("VTODO", "TODO", &title);
+        // DTSTART and DUE have to be both dates or both times, and a repeating VTODO needs a DTSTART,
+        // so the deadline is the start when the scheduled date can't be used
+        let start = scheduled
+            .as_ref()
+            .filter(|date| deadline.as_ref().is_none_or(|deadline| deadline.time().is_some() == date.time().is_some()))
+            .or(deadline.as_ref().filter(|date| date.repeater().is_some()));
+        if let Some(start) = start {
+            add_date(&mut task, "DTSTART", start.date(), start.time());
+        }
+        if let Some(deadline) = deadline {
+            add_date(&mut task, "DUE", deadline.date(), deadline.time());
+        }
+        if let Some(rule) = start.and_then(|date| date.repeater()).and_then(|repeater| rrule(&repeater)) {
+            task.add("RRULE", &rule);
+        }
+        task.add("STATUS", status(todo));
+        if let Some(priority) = priority {
+            task.add("PRIORITY", &self::priority(priority).to_string());
+        }
+        let completed = closed
+            .as_ref()
+            .filter(|_| done)
+            .and_then(|closed| zone.from_local_datetime(&closed.date().and_time(closed.time().unwrap_or_default())).earliest());
+        if let Some(completed) = completed {
+            task.add("COMPLETED", &completed.naive_utc().format("%Y%m%dT%H%M%SZ").to_string());
+        }
+        calendar.components.push(task);
+    }
+    for child in obj.children() {
+        collect(child, category, now, zone, path, calendar);
+    }
+    path.pop();
+}
+
+/// DTSTART and DTEND of an event, events without a time last the whole day
+fn event_dates(event: &mut Component, date: &ParsedDateTime) {
+    add_date(event, "DTSTART", date.date(), date.time());
+    match (date.time(), date.end_time()) {
+        (None, _) => add_date(event, "DTEND", date.date() + Duration::days(1), None),
+        (Some(_), Some(end)) => add_date(event, "DTEND", date.date(), Some(end)),
+        (Some(_), None) => (),
+    }
+    if let Some(rule) = date.repeater().and_then(|repeater| rrule(&repeater)) {
+        event.add("RRULE", &rule);
+    }
+}
+
+/// Times are written without a time zone, calendars show them in the local time of the device
+fn add_date(component: &mut Component, name: &str, date: NaiveDate, time: Option<NaiveTime>) {
+    match time {
+        Some(time) => component.add(name, &date.and_time(time).format("%Y%m%dT%H%M%S").to_string()),
+        None => component.add_with(name, &[("VALUE", "DATE")], &date.format("%Y%m%d").to_string()),
+    }
+}
+
+/// The RRULE of a repeater, None for +0d
+/// How ++ and .+ move the date can't be said in iCalendar, they repeat like + does
+pub fn rrule(repeater: &Repeater) -> Option<String> {
+    if repeater.value == 0 {
+        return None;
+    }
+    let frequency = match repeater.unit {
+        Repeat::Dayly => "DAILY",
+        Repeat::Weekly => "WEEKLY",
+        Repeat::Monthly => "MONTHLY",
+        Repeat::Yearly => "YEARLY",
+    };
+    match repeater.value {
+        1 => Some(format!("FREQ={frequency}")),
+        value => Some(format!("FREQ={frequency};INTERVAL={value}")),
+    }
+}
+
+/// A reminder the given number of days before the start
+fn alarm(days: i64, title: &str) -> Component {
+    let mut alarm = Component::new("VALARM");
+    alarm.add("ACTION", "DISPLAY");
+    alarm.add_text("DESCRIPTION", title);
+    alarm.add("TRIGGER", &format!("-P{days}D"));
+    alarm
+}
+
+/// The STATUS of a VTODO
+pub fn status(todo: &TodoStates) -> &'static str {
+    match todo {
+        TodoStates::TODO | TodoStates::LOOP => "NEEDS-ACTION",
+        TodoStates::NEXT => "IN-PROCESS",
+        TodoStates::DONE => "COMPLETED",
+    }
+}
+
+/// iCalendar priorities go from 1 (highest) to 9, A B and C are put at 1, 5 and 9
+pub fn priority(priority: &Priority) -> u32 {
+    match priority {
+        Priority::A => 1,
+        Priority::B => 5,
+        Priority::C => 9,
+    }
+}
+
+/// Escapes the characters that have a meaning in text values
+pub fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace('\n', "\\n")
+}
+
+/// Splits a line into lines of at most 75 bytes, the ones after the first start with a space
+fn fold(line: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for c in line.chars() {
+        let limit = if lines.is_empty() { 75 } else { 74 };
+        if current.len() + c.len_utf8() > limit {
+            lines.push(std::mem::take(&mut current));
+        }
+        current.push(c);
+    }
+    lines.push(current);
+    lines.into_iter().enumerate().map(|(index, line)| if index == 0 { line } else { format!(" {line}") }).collect()
+}
+
+/// FNV-1a, unlike the hasher of the standard library it gives the same result with every Rust version
+fn hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Reads the components of an .ics file, usually one VCALENDAR with the events inside
+pub fn parse(text: &str) -> Result<Vec<Component>, String> {
+    // Lines starting with a space or tab continue the line before
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ if line.trim().is_empty() => (),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    let mut stack = vec![Component::new("")];
+    for (number, line) in lines.iter().enumerate() {
+        let property = parse_property(line).ok_or(format!("line {}: can't read {line}", number + 1))?;
+        match property.name.as_str() {
+            "BEGIN" => stack.push(Component::new(&property.value)),
+            "END" => {
+                let component = stack.pop().filter(|component| component.name.eq_ignore_ascii_case(&property.value));
+                let (Some(component), Some(parent)) = (component, stack.last_mut()) else {
+                    return Err(format!("line {}: END:{} without BEGIN", number + 1, property.value));
+                };
+                parent.components.push(component);
+            }
+            _ if stack.len() == 1 => return Err(format!("line {}: {} outside of a component", number + 1, property.name)),
+            _ => stack.last_mut().unwrap().properties.push(property),
+        }
+    }
+    match stack.pop() {
+        Some(root) if stack.is_empty() => Ok(root.components),
+        _ => Err("the file ends inside of a component".to_string()),
+    }
+}
+
+/// NAME;KEY=value;KEY="value":value, the colons and semicolons in quotes don't count
+fn parse_property(line: &str) -> Option<Property> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut value = None;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => parts.push(std::mem::take(&mut current)),
+            ':' if !quoted => {
+                parts.push(std::mem::take(&mut current));
+                value = Some(&line[index + 1..]);
+                break;
+            }
+            _ => current.push(c),
+        }
+    }
+    let mut parts = parts.into_iter();
+    let name = parts.next().filter(|name| !name.is_empty())?;
+    let parameters = parts
+        .filter_map(|part| part.split_once('=').map(|(key, value)| (key.to_uppercase(), value.to_string())))
+        .collect();
+    Some(Property {
+        name: name.to_uppercase(),
+        parameters,
+        value: value?.to_string(),
+    })
+}
+
+/// Undoes escape
+pub fn unescape(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('n') | Some('N') => result.push('\n'),
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            },
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+impl structs::File {
+    /// Turns the events and todos of an .ics file into headings
+    /// Times in UTC or in a time zone of the file are converted to zone, times without zone are kept as they are
+    pub fn from_icalendar<Tz: TimeZone>(text: &str, zone: &Tz) -> Result<structs::File, String> {
+        let components = parse(text)?;
+        let mut lines = Vec::new();
+        for calendar in components.iter().filter(|component| component.name == "VCALENDAR") {
+            let zones: Vec<&Component> = calendar.components.iter().filter(|component| component.name == "VTIMEZONE").collect();
+            let converter = Converter { zones, zone };
+            for component in &calendar.components {
+                match component.name.as_str() {
+                    "VEVENT" => lines.extend(event_lines(component, &converter)),
+                    "VTODO" => lines.extend(todo_lines(component, &converter)),
+                    _ => (),
+                }
+            }
+        }
+        Ok(structs::File::parse(structs::FileContext::Todo, lines))
+    }
+
+    /// Adds imported headings below the heading at parent
+    /// A heading with the UID of one that is already in the file replaces it where it is, keeping its subheadings,
+    /// and cancelled events remove it
+    pub fn merge_icalendar(&mut self, parent: &[&str], imported: structs::File) -> Result<(), String> {
+        for mut heading in imported.children.into_iter().filter(|child| child.level().is_some()) {
+            let cancelled = heading.property("STATUS").is_some_and(|status| status.eq_ignore_ascii_case("CANCELLED"));
+            let uid = heading.property("UID").map(|uid| uid.to_string());
+            match uid.and_then(|uid| with_uid(&mut self.children, &uid)) {
+                Some((siblings, index)) if cancelled => {
+                    siblings.remove(index);
+                }
+                Some((siblings, index)) => {
+                    let old = siblings.remove(index);
+                    heading.set_level(old.level().unwrap_or(1))?;
+                    for child in old.get_children().into_iter().filter(|child| child.level().is_some()) {
+                        heading.add_child(child);
+                    }
+                    siblings.insert(index, heading);
+                }
+                None if cancelled => (),
+                None => self.insert_heading(parent, heading)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The list the heading with the UID is in, and where
+fn with_uid<'a>(objects: &'a mut Vec<Object>, uid: &str) -> Option<(&'a mut Vec<Object>, usize)> {
+    if let Some(index) = objects.iter().position(|obj| obj.level().is_some() && obj.property("UID") == Some(uid)) {
+        return Some((objects, index));
+    }
+    objects.iter_mut().find_map(|obj| with_uid(obj.children_mut(), uid))
+}
+
+/// Turns the times of a file into the local time of zone
+struct Converter<'a, Tz: TimeZone> {
+    zones: Vec<&'a Component>,
+    zone: &'a Tz,
+}
+
+impl<Tz: TimeZone> Converter<'_, Tz> {
+    /// The date, and the time unless it is a DATE value
+    fn date_time(&self, property: &Property) -> Option<(NaiveDate, Option<NaiveTime>)> {
+        let value = property.value.trim();
+        if property.parameter("VALUE").is_some_and(|kind| kind.eq_ignore_ascii_case("DATE")) || value.len() == 8 {
+            return Some((NaiveDate::parse_from_str(value, "%Y%m%d").ok()?, None));
+        }
+        let local = NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S").ok()?;
+        let utc = match (value.ends_with('Z'), property.parameter("TZID")) {
+            (true, _) => Some(local),
+            (false, Some(tzid)) => self
+                .zones
+                .iter()
+                .find(|zone| zone.property("TZID").is_some_and(|id| id.value == tzid))
+                .and_then(|zone| utc_offset(zone, local))
+                .map(|offset| local - Duration::seconds(offset)),
+            (false, None) => None,
+        };
+        let converted = match utc {
+            Some(utc) => self.zone.from_utc_datetime(&utc).naive_local(),
+            // Floating times, or a zone the file does not describe
+            None => local,
+        };
+        Some((converted.date(), Some(converted.time())))
+    }
+
+    /// The timestamp of DTSTART up to DTEND or DURATION, as org writes it
+    fn range(&self, component: &Component) -> Option<String> {
+        let (date, time) = self.date_time(component.property("DTSTART")?)?;
+        let end = match (component.property("DTEND"), component.property("DURATION")) {
+            (Some(end), _) => self.date_time(end),
+            (None, Some(duration)) => parse_duration(&duration.value).map(|duration| match time {
+                Some(time) => {
+                    let end = date.and_time(time) + duration;
+                    (end.date(), Some(end.time()))
+                }
+                None => (date + duration, None),
+            }),
+            (None, None) => None,
+        };
+        let mut start = ParsedDateTime::new(date, time, true);
+        start.set_repeater(component.property("RRULE").and_then(|rule| repeater(&rule.value, date)));
+        let end = match (time, end) {
+            (Some(_), Some((end_date, Some(end_time)))) if end_date == date => {
+                start.set_end_time(Some(end_time));
+                None
+            }
+            (Some(_), Some((end_date, Some(end_time)))) if end_date > date => Some(ParsedDateTime::new(end_date, Some(end_time), true)),
+            // The end of all day events is the day after the last one
+            (None, Some((end_date, None))) if end_date - date > Duration::days(1) => Some(ParsedDateTime::new(end_date - Duration::days(1), None, true)),
+            _ => None,
+        };
+        match end {
+            Some(end) => Some(format!("{}--{}", start.build(), end.build())),
+            None => Some(start.build()),
+        }
+    }
+}
+
+/// The offset from UTC in seconds a VTIMEZONE has at a local time
+/// The STANDARD or DAYLIGHT part that started last before the time is used
+fn utc_offset(zone: &Component, local: NaiveDateTime) -> Option<i64> {
+    let mut latest: Option<(NaiveDateTime, i64)> = None;
+    for observance in &zone.components {
+        let Some(offset) = observance.property("TZOFFSETTO").and_then(|offset| parse_offset(&offset.value)) else {
+            continue;
+        };
+        let Some(first) = observance.property("DTSTART").and_then(|start| NaiveDateTime::parse_from_str(&start.value, "%Y%m%dT%H%M%S").ok()) else {
+            continue;
+        };
+        let rule = observance.property("RRULE").map(|rule| rule_parts(&rule.value));
+        let onsets = match rule {
+            Some(rule) => [local.year() - 1, local.year()].iter().filter_map(|year| yearly_onset(&rule, first, *year)).collect(),
+            None => vec![first],
+        };
+        for onset in onsets.into_iter().filter(|onset| *onset <= local && *onset >= first) {
+            if latest.is_none_or(|(latest, _)| onset > latest) {
+                latest = Some((onset, offset));
+            }
+        }
+    }
+    latest.map(|(_, offset)| offset)
+}
+
+/// When a yearly rule like FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU starts in a year
+fn yearly_onset(rule: &[(String, String)], first: NaiveDateTime, year: i32) -> Option<NaiveDateTime> {
+    let get = |key: &str| rule.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str());
+    let month = get("BYMONTH").and_then(|month| month.parse().ok()).unwrap_or(first.month());
+    let date = match get("BYDAY") {
+        Some(day) => {
+            let split = day.find(|c: char| c.is_ascii_alphabetic())?;
+            let weekday = weekday(&day[split..])?;
+            let nth: i32 = match &day[..split] {
+                "" => 1,
+                number => number.trim_start_matches('+').parse().ok()?,
+            };
+            nth_weekday(year, month, weekday, nth)?
+        }
+        None => NaiveDate::from_ymd_opt(year, month, first.day())?,
+    };
+    Some(date.and_time(first.time()))
+}
+
+/// The nth weekday of a month, counted from the end for negative n
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, nth: i32) -> Option<NaiveDate> {
+    match nth {
+        0 => None,
+        nth if nth > 0 => NaiveDate::from_weekday_of_month_opt(year, month, weekday, nth as u8),
+        nth => {
+            let next = match month {
+                12 => NaiveDate::from_ymd_opt(year + 1, 1, 1)?,
+                month => NaiveDate::from_ymd_opt(year, month + 1, 1)?,
+            };
+            let mut date = next - Duration::days(1);
+            while date.weekday() != weekday {
+                date -= Duration::days(1);
+            }
+            Some(date - Duration::weeks((-nth - 1) as i64))
+        }
+    }
+}
+
+fn weekday(name: &str) -> Option<Weekday> {
+    match name.to_uppercase().as_str() {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// +0200 or -053000 in seconds
+fn parse_offset(text: &str) -> Option<i64> {
+    let sign = match text.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits = &text[1..];
+    let part = |range: std::ops::Range<usize>| digits.get(range).map(|part| part.parse::<i64>().ok()).unwrap_or(Some(0));
+    Some(sign * (part(0..2)? * 3600 + part(2..4)? * 60 + part(4..6)?))
+}
+
+/// The KEY=VALUE parts of a RRULE, keys uppercased
+fn rule_parts(rule: &str) -> Vec<(String, String)> {
+    rule.split(';')
+        .filter_map(|part| part.split_once('='))
+        .map(|(key, value)| (key.trim().to_uppercase(), value.trim().to_string()))
+        .collect()
+}
+
+/// The org repeater of a RRULE, None if org can't say it
+/// Only rules repeating every n days, weeks, months or years from the start, without an end, can be written
+pub fn repeater(rule: &str, start: NaiveDate) -> Option<Repeater> {
+    let mut unit = None;
+    let mut value = 1;
+    for (key, part) in rule_parts(rule) {
+        match key.as_str() {
+            "FREQ" => {
+                unit = match part.to_uppercase().as_str() {
+                    "DAILY" => Some(Repeat::Dayly),
+                    "WEEKLY" => Some(Repeat::Weekly),
+                    "MONTHLY" => Some(Repeat::Monthly),
+                    "YEARLY" => Some(Repeat::Yearly),
+                    _ => return None,
+                }
+            }
+            "INTERVAL" => value = part.parse().ok()?,
+            "WKST" => (),
+            // Allowed when they only repeat what the start date says anyway
+            "BYDAY" if weekday(&part) == Some(start.weekday()) => (),
+            "BYMONTHDAY" if part.parse() == Ok(start.day()) => (),
+            "BYMONTH" if part.parse() == Ok(start.month()) => (),
+            _ => return None,
+        }
+    }
+    Some(Repeater {
+        kind: RepeatKind::Cumulate,
+        value,
+        unit: unit?,
+        deadline: None,
+        written_as_l: false,
+    })
+}
+
+/// A duration like PT1H30M, P2D or -P1W
+fn parse_duration(text: &str) -> Option<Duration> {
+    let re = Regex::new(r"^([+-])?P(?:(\d+)W)?(?:(\d+)D)?(?:T(?:(\d+)H)?(?:(\d+)M)?(?:(\d+)S)?)?$").unwrap();
+    let caps = re.captures(text.trim())?;
+    let number = |index: usize| caps.get(index).map(|number| number.as_str().parse::<i64>().unwrap_or(0)).unwrap_or(0);
+    let duration = Duration::weeks(number(2)) + Duration::days(number(3)) + Duration::hours(number(4)) + Duration::minutes(number(5)) + Duration::seconds(number(6));
+    match caps.get(1).map(|sign| sign.as_str()) {
+        Some("-") => Some(-duration),
+        _ => Some(duration),
+    }
+}
+
+/// The heading line, with the summary and the categories as tags
+fn heading_line(component: &Component, keywords: &str) -> String {
+    let summary = component.property("SUMMARY").map(|summary| unescape(&summary.value)).unwrap_or_default();
+    let summary = summary.split_whitespace().collect::<Vec<_>>().join(" ");
+    let summary = if summary.is_empty() { "(no title)".to_string() } else { summary };
+    let re_tag = Regex::new(r"[^\w@#%]+").unwrap();
+    let tags: Vec<String> = component
+        .properties
+        .iter()
+        .filter(|property| property.name == "CATEGORIES")
+        .flat_map(|property| split_list(&property.value))
+        .map(|tag| re_tag.replace_all(&tag, "_").to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    match tags.is_empty() {
+        true => format!("* {keywords}{summary}"),
+        false => format!("* {keywords}{summary} :{}:", tags.join(":")),
+    }
+}
+
+/// The values of a list like CATEGORIES, split at the commas that are not escaped
+fn split_list(value: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut escaped = false;
+    for c in value.chars() {
+        match c {
+            ',' if !escaped => parts.push(String::new()),
+            _ => parts.last_mut().unwrap().push(c),
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    parts.iter().map(|part| unescape(part).trim().to_string()).collect()
+}
+
+/// The property drawer and the description, the lines after the planning
+fn body_lines(component: &Component, extra: &[(&str, String)]) -> Vec<String> {
+    let mut lines = vec![":PROPERTIES:".to_string()];
+    for name in ["UID", "LOCATION", "URL", "STATUS"] {
+        // The STATUS of a todo is its TODO keyword
+        if name == "STATUS" && component.name == "VTODO" {
+            continue;
+        }
+        if let Some(property) = component.property(name) {
+            lines.push(format!(":{name}: {}", unescape(&property.value).replace('\n', " ")));
+        }
+    }
+    for (name, value) in extra {
+        lines.push(format!(":{name}: {value}"));
+    }
+    lines.push(":END:".to_string());
+    if lines.len() == 2 {
+        lines.clear();
+    }
+    if let Some(description) = component.property("DESCRIPTION") {
+        // A line starting with a star would become a heading, org escapes those with a comma
+        for line in unescape(&description.value).lines() {
+            match line.starts_with('*') {
+                true => lines.push(format!(",{line}")),
+                false => lines.push(line.to_string()),
+            }
+        }
+    }
+    lines
+}
+
+/// A RRULE org can't write as repeater is kept as property, so it is not lost
+fn unexpressed_rule(component: &Component, start: Option<NaiveDate>) -> Vec<(&'static str, String)> {
+    match (component.property("RRULE"), start) {
+        (Some(rule), Some(start)) if repeater(&rule.value, start).is_none() => vec![("RRULE", rule.value.clone())],
+        _ => Vec::new(),
+    }
+}
+
+fn event_lines<Tz: TimeZone>(event: &Component, converter: &Converter<Tz>) -> Vec<String> {
+    let mut lines = vec![heading_line(event, "")];
+    let start = event.property("DTSTART").and_then(|start| converter.date_time(start)).map(|(date, _)| date);
+    let mut body = body_lines(event, &unexpressed_rule(event, start));
+    let drawer_end = body.iter().position(|line| line == ":END:").map(|end| end + 1).unwrap_or(0);
+    if let Some(range) = converter.range(event) {
+        body.insert(drawer_end, range);
+    }
+    lines.extend(body);
+    lines
+}
+
+fn todo_lines<Tz: TimeZone>(todo: &Component, converter: &Converter<Tz>) -> Vec<String> {
+    let state = match todo.property("STATUS").map(|status| status.value.to_uppercase()).as_deref() {
+        Some("COMPLETED") | Some("CANCELLED") => TodoStates::DONE,
+        Some("IN-PROCESS") => TodoStates::NEXT,
+        _ => TodoStates::TODO,
+    };
+    let priority = match todo.property("PRIORITY").and_then(|priority| priority.value.trim().parse::<u32>().ok()) {
+        Some(1..=4) => Some(Priority::A),
+        Some(5) => Some(Priority::B),
+        Some(6..=9) => Some(Priority::C),
+        _ => None,
+    };
+    let keywords = match priority {
+        Some(priority) => format!("{} {} ", state.build(), priority.build()),
+        None => format!("{} ", state.build()),
+    };
+    let mut lines = vec![heading_line(todo, &keywords)];
+    let date = |name: &str| {
+        let (date, time) = converter.date_time(todo.property(name)?)?;
+        Some(ParsedDateTime::new(date, time, true))
+    };
+    let mut scheduled = date("DTSTART");
+    let mut deadline = date("DUE");
+    let rule = todo.property("RRULE").map(|rule| rule.value.clone());
+    let start = scheduled.as_ref().or(deadline.as_ref()).map(|date| date.date());
+    // The repeater goes to the deadline if the start is the deadline, like the export writes it
+    let repeating = match (&scheduled, &deadline) {
+        (Some(scheduled_date), Some(deadline_date)) if scheduled_date == deadline_date => {
+            scheduled = None;
+            deadline.as_mut()
+        }
+        (Some(_), _) => scheduled.as_mut(),
+        (None, _) => deadline.as_mut(),
+    };
+    if let (Some(date), Some(rule)) = (repeating, &rule) {
+        date.set_repeater(repeater(rule, date.date()));
+    }
+    if let Some(deadline) = deadline.as_mut() {
+        let warning = todo
+            .components
+            .iter()
+            .filter(|component| component.name == "VALARM")
+            .filter_map(|alarm| parse_duration(&alarm.property("TRIGGER")?.value))
+            .filter(|trigger| *trigger < Duration::zero())
+            .map(|trigger| -trigger.num_days())
+            .find(|days| *days > 0);
+        deadline.set_warning(warning.map(|days| Warning {
+            value: days as u32,
+            unit: Repeat::Dayly,
+            first_only: false,
+        }));
+    }
+    let closed = todo.property("COMPLETED").filter(|_| state == TodoStates::DONE).and_then(|completed| converter.date_time(completed));
+    let mut planning = Vec::new();
+    if let Some(deadline) = deadline {
+        planning.push(InfoType::DEADLINE { date: deadline }.build());
+    }
+    if let Some(scheduled) = scheduled {
+        planning.push(InfoType::SCHEDULED { date: scheduled }.build());
+    }
+    if let Some((date, time)) = closed {
+        planning.push(InfoType::CLOSED { date: ParsedDateTime::new(date, time, false) }.build());
+    }
+    if !planning.is_empty() {
+        lines.push(planning.join(" "));
+    }
+    lines.extend(body_lines(todo, &unexpressed_rule(todo, start)));
+    lines
+}
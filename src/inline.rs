@@ -0,0 +1,730 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::time_management::ParsedDateTime;
+
+/// Objects that can appear inside the text of a single line.
+/// The line based parser in parser.rs only knows what a line is, this splits the text of a line further into its pieces.
+/// Markup containers (bold, italic, ...) hold their parsed contents, while verbatim and code keep their raw text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InlineObject {
+    Plain(String),
+    Bold(Vec<InlineObject>),
+    Italic(Vec<InlineObject>),
+    Underline(Vec<InlineObject>),
+    StrikeThrough(Vec<InlineObject>),
+    Verbatim(String),
+    Code(String),
+    /// [[target][description]] or [[target]]
+    Link {
+        target: String,
+        description: Option<Vec<InlineObject>>,
+    },
+    /// Active <...> or inactive [...] timestamps, date is None if the timestamp could not be understood
+    Timestamp {
+        raw: String,
+        date: Option<ParsedDateTime>,
+    },
+    /// [fn:label], [fn:: inline definition] or [fn:label: inline definition]
+    /// padding is the whitespace before and after an inline definition as written, so [fn::text] stays without a space
+    FootnoteReference {
+        label: Option<String>,
+        definition: Option<Vec<InlineObject>>,
+        padding: (String, String),
+    },
+    /// \alpha, \mdash{} ...
+    /// raw is the text as written, kept so \mdash{} is not written back as \mdash
+    Entity {
+        name: String,
+        raw: String,
+    },
+    /// \(...\), \[...\], $...$, $$...$$ and \command{...}
+    LatexFragment(String),
+    /// {{{name(arg1, arg2)}}}, arguments are trimmed, written are the arguments as written with their spaces
+    Macro {
+        name: String,
+        arguments: Vec<String>,
+        written: Vec<String>,
+    },
+    /// <<<target>>>
+    RadioTarget(String),
+    /// <<target>>
+    Target(String),
+}
+
+/// Entities org knows, with their UTF-8 representation
+/// The HTML entities and the LaTeX names org-entities adds
+const ENTITIES: &[(&str, &str)] = &[
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ε"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("pi", "π"),
+    ("sigma", "σ"),
+    ("tau", "τ"),
+    ("phi", "φ"),
+    ("omega", "ω"),
+    ("Delta", "Δ"),
+    ("Sigma", "Σ"),
+    ("Omega", "Ω"),
+    ("nbsp", "\u{a0}"),
+    ("ndash", "–"),
+    ("mdash", "—"),
+    ("hellip", "…"),
+    ("laquo", "«"),
+    ("raquo", "»"),
+    ("larr", "←"),
+    ("rarr", "→"),
+    ("uarr", "↑"),
+    ("darr", "↓"),
+    ("harr", "↔"),
+    ("rArr", "⇒"),
+    ("lArr", "⇐"),
+    ("copy", "©"),
+    ("reg", "®"),
+    ("trade", "™"),
+    ("deg", "°"),
+    ("times", "×"),
+    ("div", "÷"),
+    ("pm", "±"),
+    ("le", "≤"),
+    ("ge", "≥"),
+    ("ne", "≠"),
+    ("infin", "∞"),
+    ("euro", "€"),
+    ("auml", "ä"),
+    ("ouml", "ö"),
+    ("uuml", "ü"),
+    ("Auml", "Ä"),
+    ("Ouml", "Ö"),
+    ("Uuml", "Ü"),
+    ("szlig", "ß"),
+    // The rest of the HTML entities
+    ("Aacute", "Á"),
+    ("aacute", "á"),
+    ("Acirc", "Â"),
+    ("acirc", "â"),
+    ("acute", "´"),
+    ("AElig", "Æ"),
+    ("aelig", "æ"),
+    ("Agrave", "À"),
+    ("agrave", "à"),
+    ("alefsym", "ℵ"),
+    ("Alpha", "Α"),
+    ("amp", "&"),
+    ("and", "∧"),
+    ("ang", "∠"),
+    ("Aring", "Å"),
+    ("aring", "å"),
+    ("asymp", "≈"),
+    ("Atilde", "Ã"),
+    ("atilde", "ã"),
+    ("bdquo", "„"),
+    ("Beta", "Β"),
+    ("brvbar", "¦"),
+    ("bull", "•"),
+    ("cap", "∩"),
+    ("Ccedil", "Ç"),
+    ("ccedil", "ç"),
+    ("cedil", "¸"),
+    ("cent", "¢"),
+    ("Chi", "Χ"),
+    ("chi", "χ"),
+    ("circ", "ˆ"),
+    ("clubs", "♣"),
+    ("cong", "≅"),
+    ("crarr", "↵"),
+    ("cup", "∪"),
+    ("curren", "¤"),
+    ("Dagger", "‡"),
+    ("dagger", "†"),
+    ("dArr", "⇓"),
+    ("diams", "♦"),
+    ("divide", "÷"),
+    ("Eacute", "É"),
+    ("eacute", "é"),
+    ("Ecirc", "Ê"),
+    ("ecirc", "ê"),
+    ("Egrave", "È"),
+    ("egrave", "è"),
+    ("empty", "∅"),
+    ("emsp", "\u{2003}"),
+    ("ensp", "\u{2002}"),
+    ("Epsilon", "Ε"),
+    ("equiv", "≡"),
+    ("Eta", "Η"),
+    ("eta", "η"),
+    ("ETH", "Ð"),
+    ("eth", "ð"),
+    ("Euml", "Ë"),
+    ("euml", "ë"),
+    ("exist", "∃"),
+    ("fnof", "ƒ"),
+    ("forall", "∀"),
+    ("frac12", "½"),
+    ("frac14", "¼"),
+    ("frac34", "¾"),
+    ("frasl", "⁄"),
+    ("Gamma", "Γ"),
+    ("gt", ">"),
+    ("hArr", "⇔"),
+    ("hearts", "♥"),
+    ("Iacute", "Í"),
+    ("iacute", "í"),
+    ("Icirc", "Î"),
+    ("icirc", "î"),
+    ("iexcl", "¡"),
+    ("Igrave", "Ì"),
+    ("igrave", "ì"),
+    ("image", "ℑ"),
+    ("int", "∫"),
+    ("Iota", "Ι"),
+    ("iota", "ι"),
+    ("iquest", "¿"),
+    ("isin", "∈"),
+    ("Iuml", "Ï"),
+    ("iuml", "ï"),
+    ("Kappa", "Κ"),
+    ("kappa", "κ"),
+    ("Lambda", "Λ"),
+    ("lang", "〈"),
+    ("lceil", "⌈"),
+    ("ldquo", "“"),
+    ("lfloor", "⌊"),
+    ("lowast", "∗"),
+    ("loz", "◊"),
+    ("lrm", "\u{200e}"),
+    ("lsaquo", "‹"),
+    ("lsquo", "‘"),
+    ("lt", "<"),
+    ("macr", "¯"),
+    ("micro", "µ"),
+    ("middot", "·"),
+    ("minus", "−"),
+    ("Mu", "Μ"),
+    ("nabla", "∇"),
+    ("ni", "∋"),
+    ("not", "¬"),
+    ("notin", "∉"),
+    ("nsub", "⊄"),
+    ("Ntilde", "Ñ"),
+    ("ntilde", "ñ"),
+    ("Nu", "Ν"),
+    ("nu", "ν"),
+    ("Oacute", "Ó"),
+    ("oacute", "ó"),
+    ("Ocirc", "Ô"),
+    ("ocirc", "ô"),
+    ("OElig", "Œ"),
+    ("oelig", "œ"),
+    ("Ograve", "Ò"),
+    ("ograve", "ò"),
+    ("oline", "‾"),
+    ("Omicron", "Ο"),
+    ("omicron", "ο"),
+    ("oplus", "⊕"),
+    ("or", "∨"),
+    ("ordf", "ª"),
+    ("ordm", "º"),
+    ("Oslash", "Ø"),
+    ("oslash", "ø"),
+    ("Otilde", "Õ"),
+    ("otilde", "õ"),
+    ("otimes", "⊗"),
+    ("para", "¶"),
+    ("part", "∂"),
+    ("permil", "‰"),
+    ("perp", "⊥"),
+    ("Phi", "Φ"),
+    ("Pi", "Π"),
+    ("piv", "ϖ"),
+    ("plusmn", "±"),
+    ("pound", "£"),
+    ("Prime", "″"),
+    ("prime", "′"),
+    ("prod", "∏"),
+    ("prop", "∝"),
+    ("Psi", "Ψ"),
+    ("psi", "ψ"),
+    ("quot", "\""),
+    ("radic", "√"),
+    ("rang", "〉"),
+    ("rceil", "⌉"),
+    ("rdquo", "”"),
+    ("real", "ℜ"),
+    ("rfloor", "⌋"),
+    ("Rho", "Ρ"),
+    ("rho", "ρ"),
+    ("rlm", "\u{200f}"),
+    ("rsaquo", "›"),
+    ("rsquo", "’"),
+    ("sbquo", "‚"),
+    ("Scaron", "Š"),
+    ("scaron", "š"),
+    ("sdot", "⋅"),
+    ("sect", "§"),
+    ("shy", "\u{ad}"),
+    ("sigmaf", "ς"),
+    ("sim", "∼"),
+    ("spades", "♠"),
+    ("sub", "⊂"),
+    ("sube", "⊆"),
+    ("sum", "∑"),
+    ("sup", "⊃"),
+    ("sup1", "¹"),
+    ("sup2", "²"),
+    ("sup3", "³"),
+    ("supe", "⊇"),
+    ("Tau", "Τ"),
+    ("there4", "∴"),
+    ("Theta", "Θ"),
+    ("theta", "θ"),
+    ("thetasym", "ϑ"),
+    ("thinsp", "\u{2009}"),
+    ("THORN", "Þ"),
+    ("thorn", "þ"),
+    ("tilde", "˜"),
+    ("Uacute", "Ú"),
+    ("uacute", "ú"),
+    ("uArr", "⇑"),
+    ("Ucirc", "Û"),
+    ("ucirc", "û"),
+    ("Ugrave", "Ù"),
+    ("ugrave", "ù"),
+    ("uml", "¨"),
+    ("upsih", "ϒ"),
+    ("Upsilon", "Υ"),
+    ("upsilon", "υ"),
+    ("weierp", "℘"),
+    ("Xi", "Ξ"),
+    ("xi", "ξ"),
+    ("Yacute", "Ý"),
+    ("yacute", "ý"),
+    ("yen", "¥"),
+    ("Yuml", "Ÿ"),
+    ("yuml", "ÿ"),
+    ("Zeta", "Ζ"),
+    ("zeta", "ζ"),
+    ("zwj", "\u{200d}"),
+    ("zwnj", "\u{200c}"),
+    // Names org adds, mostly the LaTeX ones
+    ("leq", "≤"),
+    ("geq", "≥"),
+    ("neq", "≠"),
+    ("to", "→"),
+    ("gets", "←"),
+    ("rightarrow", "→"),
+    ("leftarrow", "←"),
+    ("uparrow", "↑"),
+    ("downarrow", "↓"),
+    ("leftrightarrow", "↔"),
+    ("Rightarrow", "⇒"),
+    ("Leftarrow", "⇐"),
+    ("Leftrightarrow", "⇔"),
+    ("infty", "∞"),
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("cdots", "⋯"),
+    ("cdot", "⋅"),
+    ("mp", "∓"),
+    ("approx", "≈"),
+    ("in", "∈"),
+    ("emptyset", "∅"),
+    ("partial", "∂"),
+    ("exists", "∃"),
+    ("neg", "¬"),
+    ("wedge", "∧"),
+    ("vee", "∨"),
+    ("angle", "∠"),
+    ("aleph", "ℵ"),
+    ("ell", "ℓ"),
+    ("hbar", "ℏ"),
+    ("varepsilon", "ε"),
+    ("vartheta", "ϑ"),
+    ("varpi", "ϖ"),
+    ("varsigma", "ς"),
+    ("varphi", "φ"),
+    ("dag", "†"),
+    ("ddag", "‡"),
+    ("bullet", "•"),
+    ("textbullet", "•"),
+    ("S", "§"),
+    ("P", "¶"),
+    ("vert", "|"),
+    ("dollar", "$"),
+    ("backslash", "\\"),
+    ("plus", "+"),
+    ("star", "*"),
+    ("ast", "*"),
+    ("under", "_"),
+    ("slash", "/"),
+    ("check", "✓"),
+    ("checkmark", "✓"),
+    ("smiley", "☺"),
+    ("blacksmile", "☻"),
+    ("sad", "☹"),
+    ("frowny", "☹"),
+    ("EUR", "€"),
+    ("EURdig", "€"),
+    ("EURhv", "€"),
+    ("EURcr", "€"),
+    ("EURtm", "€"),
+];
+
+/// Compiled regexes used while walking through a line
+/// Created once, parse_inline is called for every line of a file
+struct Patterns {
+    link: Regex,
+    footnote: Regex,
+    active_time: Regex,
+    inactive_time: Regex,
+    radio_target: Regex,
+    target: Regex,
+    macro_call: Regex,
+    latex_inline: Regex,
+    latex_display: Regex,
+    latex_dollars: Regex,
+    latex_dollar: Regex,
+    backslash: Regex,
+}
+
+impl Patterns {
+    fn new() -> Patterns {
+        Patterns {
+            link: Regex::new(r"^\[\[([^\]]+)\](?:\[(.+?)\])?\]").unwrap(),
+            footnote: Regex::new(r"^\[fn:([\w-]*)(:|\])").unwrap(),
+            active_time: Regex::new(r"^<\d{4}-\d{2}-\d{2}[^>]*>(?:--<\d{4}-\d{2}-\d{2}[^>]*>)?").unwrap(),
+            inactive_time: Regex::new(r"^\[\d{4}-\d{2}-\d{2}[^\]]*\](?:--\[\d{4}-\d{2}-\d{2}[^\]]*\])?").unwrap(),
+            radio_target: Regex::new(r"^<<<([^<>\n]+)>>>").unwrap(),
+            target: Regex::new(r"^<<([^<>\n]+)>>").unwrap(),
+            macro_call: Regex::new(r"^\{\{\{([A-Za-z][\w-]*)(?:\((.*?)\))?\}\}\}").unwrap(),
+            latex_inline: Regex::new(r"^\\\(.*?\\\)").unwrap(),
+            latex_display: Regex::new(r"^\\\[.*?\\\]").unwrap(),
+            latex_dollars: Regex::new(r"^\$\$.+?\$\$").unwrap(),
+            latex_dollar: Regex::new(r"^\$[^\s$](?:[^$]*[^\s$])?\$").unwrap(),
+            backslash: Regex::new(r"^\\([A-Za-z]+)((?:\[[^\]]*\]|\{[^}]*\})*)").unwrap(),
+        }
+    }
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(Patterns::new)
+}
+
+/// Parses the text of a line into its inline objects
+pub fn parse_inline(input: &str) -> Vec<InlineObject> {
+    parse_with(input, patterns())
+}
+
+fn parse_with(input: &str, patterns: &Patterns) -> Vec<InlineObject> {
+    let mut result = Vec::new();
+    let mut plain = String::new();
+    let mut pos = 0;
+    let mut previous = None;
+    while pos < input.len() {
+        match next_object(input, pos, previous, patterns) {
+            Some((object, length)) => {
+                if !plain.is_empty() {
+                    result.push(InlineObject::Plain(std::mem::take(&mut plain)));
+                }
+                result.push(object);
+                pos += length;
+                previous = input[..pos].chars().next_back();
+            }
+            None => {
+                let c = input[pos..].chars().next().unwrap();
+                plain.push(c);
+                pos += c.len_utf8();
+                previous = Some(c);
+            }
+        }
+    }
+    if !plain.is_empty() {
+        result.push(InlineObject::Plain(plain));
+    }
+    result
+}
+
+/// Tries to parse an object at the start of input[pos..], returns the object and how many bytes it took
+fn next_object(
+    input: &str,
+    pos: usize,
+    previous: Option<char>,
+    patterns: &Patterns,
+) -> Option<(InlineObject, usize)> {
+    let rest = &input[pos..];
+    let first = rest.chars().next()?;
+    match first {
+        '[' => {
+            if let Some(caps) = patterns.link.captures(rest) {
+                let description = caps.get(2).map(|d| parse_with(d.as_str(), patterns));
+                return Some((
+                    InlineObject::Link { target: caps[1].to_string(), description },
+                    caps[0].len(),
+                ));
+            }
+            if let Some(caps) = patterns.footnote.captures(rest) {
+                let label = Some(caps[1].to_string()).filter(|l| !l.is_empty());
+                let (definition, padding, length) = match &caps[2] {
+                    "]" => (None, Default::default(), caps[0].len()),
+                    // Inline definitions can hold links, so the closing bracket is the one that balances the opening
+                    _ => {
+                        let end = caps[0].len() + closing_bracket(&rest[caps[0].len()..])?;
+                        let written = &rest[caps[0].len()..end];
+                        let text = written.trim();
+                        let before = written[..written.len() - written.trim_start().len()].to_string();
+                        let after = written[written.trim_end().len()..].to_string();
+                        (Some(parse_with(text, patterns)), (before, after), end + 1)
+                    }
+                };
+                if label.is_none() && definition.is_none() {
+                    return None;
+                }
+                return Some((InlineObject::FootnoteReference { label, definition, padding }, length));
+            }
+            if let Some(mat) = patterns.inactive_time.find(rest) {
+                return Some((timestamp(mat.as_str()), mat.len()));
+            }
+            None
+        }
+        '<' => {
+            if let Some(caps) = patterns.radio_target.captures(rest) {
+                return Some((InlineObject::RadioTarget(caps[1].to_string()), caps[0].len()));
+            }
+            if let Some(caps) = patterns.target.captures(rest) {
+                return Some((InlineObject::Target(caps[1].to_string()), caps[0].len()));
+            }
+            if let Some(mat) = patterns.active_time.find(rest) {
+                return Some((timestamp(mat.as_str()), mat.len()));
+            }
+            None
+        }
+        '{' => {
+            let caps = patterns.macro_call.captures(rest)?;
+            let written: Vec<String> = match caps.get(2) {
+                Some(args) => args.as_str().split(',').map(|a| a.to_string()).collect(),
+                None => Vec::new(),
+            };
+            let arguments = written.iter().map(|a| a.trim().to_string()).collect();
+            Some((
+                InlineObject::Macro { name: caps[1].to_string(), arguments, written },
+                caps[0].len(),
+            ))
+        }
+        '\\' => {
+            for re in [&patterns.latex_inline, &patterns.latex_display] {
+                if let Some(mat) = re.find(rest) {
+                    return Some((InlineObject::LatexFragment(mat.as_str().to_string()), mat.len()));
+                }
+            }
+            let caps = patterns.backslash.captures(rest)?;
+            let name = &caps[1];
+            let arguments = &caps[2];
+            if entity_utf8(name).is_some() && (arguments.is_empty() || arguments == "{}") {
+                return Some((InlineObject::Entity { name: name.to_string(), raw: caps[0].to_string() }, caps[0].len()));
+            }
+            Some((InlineObject::LatexFragment(caps[0].to_string()), caps[0].len()))
+        }
+        '$' => {
+            if let Some(mat) = patterns.latex_dollars.find(rest) {
+                return Some((InlineObject::LatexFragment(mat.as_str().to_string()), mat.len()));
+            }
+            let mat = patterns.latex_dollar.find(rest)?;
+            let after = rest[mat.len()..].chars().next();
+            if after.is_some_and(|c| c.is_alphanumeric()) {
+                return None;
+            }
+            Some((InlineObject::LatexFragment(mat.as_str().to_string()), mat.len()))
+        }
+        '*' | '/' | '_' | '+' | '=' | '~' => markup(rest, first, previous, patterns),
+        _ => None,
+    }
+}
+
+/// The byte offset of the ] that closes a [ opened before text, None if it is never closed
+fn closing_bracket(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (offset, c) in text.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth == 0 => return Some(offset),
+            ']' => depth -= 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+fn timestamp(raw: &str) -> InlineObject {
+    InlineObject::Timestamp {
+        raw: raw.to_string(),
+        date: ParsedDateTime::parse(raw),
+    }
+}
+
+/// Emphasis markers are only valid at word boundaries, like in org
+fn markup(
+    rest: &str,
+    marker: char,
+    previous: Option<char>,
+    patterns: &Patterns,
+) -> Option<(InlineObject, usize)> {
+    if let Some(previous) = previous {
+        if !(previous.is_whitespace() || "-({'\"".contains(previous)) {
+            return None;
+        }
+    }
+    let body_start = marker.len_utf8();
+    let first = rest[body_start..].chars().next()?;
+    if first.is_whitespace() {
+        return None;
+    }
+    let mut last: Option<char> = None;
+    for (offset, c) in rest[body_start..].char_indices() {
+        if c == '\n' {
+            return None;
+        }
+        if c == marker && offset > 0 && !last.is_some_and(|l| l.is_whitespace()) {
+            let end = body_start + offset + c.len_utf8();
+            let after = rest[end..].chars().next();
+            if after.is_none_or(|a| a.is_whitespace() || "-.,;:!?')}\"\\[".contains(a)) {
+                let body = &rest[body_start..body_start + offset];
+                let object = match marker {
+                    '*' => InlineObject::Bold(parse_with(body, patterns)),
+                    '/' => InlineObject::Italic(parse_with(body, patterns)),
+                    '_' => InlineObject::Underline(parse_with(body, patterns)),
+                    '+' => InlineObject::StrikeThrough(parse_with(body, patterns)),
+                    '=' => InlineObject::Verbatim(body.to_string()),
+                    _ => InlineObject::Code(body.to_string()),
+                };
+                return Some((object, end));
+            }
+        }
+        last = Some(c);
+    }
+    None
+}
+
+/// Returns the UTF-8 representation of an entity, None if the entity is unknown
+pub fn entity_utf8(name: &str) -> Option<&'static str> {
+    ENTITIES
+        .iter()
+        .find(|(entity, _)| *entity == name)
+        .map(|(_, utf8)| *utf8)
+}
+
+/// Creates the org String of a list of inline objects
+pub fn build_inline(objects: &[InlineObject]) -> String {
+    objects.iter().map(|obj| obj.build()).collect()
+}
+
+/// Collects all timestamps that could be parsed, including the ones nested in markup or link descriptions
+pub fn collect_timestamps(objects: &[InlineObject]) -> Vec<ParsedDateTime> {
+    let mut result = Vec::new();
+    for object in objects {
+        match object {
+            InlineObject::Timestamp { date: Some(date), .. } => result.push(date.clone()),
+            _ => {
+                if let Some(children) = object.children() {
+                    result.extend(collect_timestamps(children));
+                }
+            }
+        }
+    }
+    result
+}
+
+impl InlineObject {
+    /// The parsed contents of container objects, None for objects that can't contain others
+    pub fn children(&self) -> Option<&Vec<InlineObject>> {
+        match self {
+            InlineObject::Bold(children)
+            | InlineObject::Italic(children)
+            | InlineObject::Underline(children)
+            | InlineObject::StrikeThrough(children) => Some(children),
+            InlineObject::Link { description, .. } => description.as_ref(),
+            InlineObject::FootnoteReference { definition, .. } => definition.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Mutable access to the parsed contents of container objects
+    pub fn children_mut(&mut self) -> Option<&mut Vec<InlineObject>> {
+        match self {
+            InlineObject::Bold(children)
+            | InlineObject::Italic(children)
+            | InlineObject::Underline(children)
+            | InlineObject::StrikeThrough(children) => Some(children),
+            InlineObject::Link { description, .. } => description.as_mut(),
+            InlineObject::FootnoteReference { definition, .. } => definition.as_mut(),
+            _ => None,
+        }
+    }
+
+    /// Creates the org String representation of the object
+    pub fn build(&self) -> String {
+        match self {
+            InlineObject::Plain(text) => text.to_owned(),
+            InlineObject::Bold(children) => format!("*{}*", build_inline(children)),
+            InlineObject::Italic(children) => format!("/{}/", build_inline(children)),
+            InlineObject::Underline(children) => format!("_{}_", build_inline(children)),
+            InlineObject::StrikeThrough(children) => format!("+{}+", build_inline(children)),
+            InlineObject::Verbatim(text) => format!("={text}="),
+            InlineObject::Code(text) => format!("~{text}~"),
+            InlineObject::Link { target, description } => match description {
+                Some(description) => format!("[[{target}][{}]]", build_inline(description)),
+                None => format!("[[{target}]]"),
+            },
+            InlineObject::Timestamp { raw, .. } => raw.to_owned(),
+            InlineObject::FootnoteReference { label, definition, padding: (before, after) } => {
+                let label = label.clone().unwrap_or_default();
+                match definition {
+                    Some(definition) => format!("[fn:{label}:{before}{}{after}]", build_inline(definition)),
+                    None => format!("[fn:{label}]"),
+                }
+            }
+            // Objects are written as they were read unless they were changed
+            InlineObject::Entity { name, raw } => match raw.strip_prefix('\\').map(|raw| raw.trim_end_matches("{}")) {
+                Some(written) if written == name => raw.to_owned(),
+                _ => format!("\\{name}{{}}"),
+            },
+            InlineObject::LatexFragment(text) => text.to_owned(),
+            InlineObject::Macro { name, arguments, written } => match written.iter().map(|a| a.trim()).eq(arguments) {
+                _ if arguments.is_empty() => format!("{{{{{{{name}}}}}}}"),
+                true => format!("{{{{{{{name}({})}}}}}}", written.join(",")),
+                false => format!("{{{{{{{name}({})}}}}}}", arguments.join(",")),
+            },
+            InlineObject::RadioTarget(text) => format!("<<<{text}>>>"),
+            InlineObject::Target(text) => format!("<<{text}>>"),
+        }
+    }
+
+    /// Text without any markup, used where only the readable text matters
+    pub fn plain_text(&self) -> String {
+        match self {
+            InlineObject::Plain(text)
+            | InlineObject::Verbatim(text)
+            | InlineObject::Code(text)
+            | InlineObject::RadioTarget(text)
+            | InlineObject::LatexFragment(text) => text.to_owned(),
+            InlineObject::Link { target, description } => match description {
+                Some(description) => description.iter().map(|d| d.plain_text()).collect(),
+                None => target.to_owned(),
+            },
+            InlineObject::Timestamp { raw, .. } => raw.to_owned(),
+            InlineObject::Entity { name, .. } => entity_utf8(name).unwrap_or_default().to_string(),
+            InlineObject::FootnoteReference { .. } | InlineObject::Target(_) | InlineObject::Macro { .. } => {
+                "".to_string()
+            }
+            _ => self
+                .children()
+                .map(|children| children.iter().map(|c| c.plain_text()).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use serde::Deserialize;
+
+use crate::{object::Object, structs};
+
+/// The JSON Schema of the documents to_json writes and from_json reads
+pub const SCHEMA: &str = include_str!("../schema/org-ast.schema.json");
+
+/// Changes whenever the layout of the JSON changes in a way older readers can't handle
+pub const VERSION: u32 = 1;
+
+#[derive(Deserialize)]
+struct Document {
+    version: u32,
+    children: Vec<Object>,
+}
+
+impl structs::File {
+    /// The tree of the file as JSON, see SCHEMA
+    pub fn to_json(&self) -> String {
+        let document = serde_json::json!({
+            "version": VERSION,
+            "children": self.children,
+        });
+        serde_json::to_string_pretty(&document).expect("the tree is always valid JSON")
+    }
+
+    /// Reads a tree written by to_json, or by any other tool following SCHEMA
+    /// The planning of headings is taken from their INFO child, like when parsing org
+    pub fn from_json(text: &str) -> Result<structs::File, String> {
+        let document: Document = serde_json::from_str(text).map_err(|error| error.to_string())?;
+        if document.version != VERSION {
+            return Err(format!("can't read version {} documents, only version {VERSION}", document.version));
+        }
+        let mut file = structs::File::new();
+        file.children = document.children;
+        for child in &mut file.children {
+            sync(child);
+        }
+        Ok(file)
+    }
+}
+
+fn sync(obj: &mut Object) {
+    obj.sync_planning();
+    for child in obj.children_mut() {
+        sync(child);
+    }
+}
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+
+use crate::{
+    export::{self, Element, List, ListKind, Row},
+    inline::{self, InlineObject},
+    link::{self, Link, LinkType},
+    structs::{self, TodoStates},
+    time_management::InfoType,
+};
+
+/// Packages every export loads, #+LATEX_HEADER lines come after them
+pub const DEFAULT_PACKAGES: &str = "\\usepackage[utf8]{inputenc}
+\\usepackage[T1]{fontenc}
+\\usepackage{graphicx}
+\\usepackage{longtable}
+\\usepackage{amssymb}
+\\usepackage[normalem]{ulem}
+\\usepackage{hyperref}";
+
+/// How a file is exported to LaTeX
+#[derive(Debug, Clone)]
+pub struct LatexOptions {
+    /// The document class, #+LATEX_CLASS in the file wins
+    pub class: String,
+    /// Source blocks with minted instead of verbatim, pdflatex then needs -shell-escape
+    pub minted: bool,
+    /// Headings up to this level go into the table of contents, 0 leaves it out
+    /// #+OPTIONS: toc:nil or toc:2 in the file wins
+    pub toc_depth: u32,
+    /// Write the planning lines below the headings
+    pub planning: bool,
+}
+
+impl Default for LatexOptions {
+    fn default() -> Self {
+        LatexOptions {
+            class: "article".to_string(),
+            minted: false,
+            toc_depth: 3,
+            planning: true,
+        }
+    }
+}
+
+/// Turns elements into LaTeX, knows the link abbreviations, the heading anchors and the footnote definitions
+struct Renderer<'a> {
+    options: &'a LatexOptions,
+    abbreviations: HashMap<String, String>,
+    anchors: HashMap<String, String>,
+    footnotes: HashMap<String, String>,
+    /// Classes with chapters start sectioning one level higher
+    chapters: bool,
+}
+
+impl structs::File {
+    /// A whole document, ready for pdflatex
+    pub fn to_latex(&self, options: &LatexOptions) -> String {
+        let class = self.keyword("LATEX_CLASS").pop().unwrap_or(options.class.clone());
+        let class_options = self.keyword("LATEX_CLASS_OPTIONS").pop().unwrap_or_default();
+        let toc_depth = self
+            .keyword("OPTIONS")
+            .iter()
+            .flat_map(|options| options.split_whitespace())
+            .find_map(|option| option.strip_prefix("toc:"))
+            .map(|toc| toc.parse().unwrap_or(if toc == "nil" { 0 } else { options.toc_depth }))
+            .unwrap_or(options.toc_depth);
+        let elements = self.elements();
+        let mut renderer = Renderer {
+            options,
+            abbreviations: link::abbreviations(self),
+            anchors: export::heading_anchors(&elements),
+            footnotes: footnotes(&elements),
+            chapters: class == "report" || class == "book",
+        };
+        let mut latex = format!("\\documentclass{class_options}{{{class}}}\n{DEFAULT_PACKAGES}\n");
+        if options.minted {
+            latex.push_str("\\usepackage{minted}\n");
+        }
+        for header in self.keyword("LATEX_HEADER") {
+            latex.push_str(&format!("{header}\n"));
+        }
+        let title = self.keyword("TITLE").pop();
+        latex.push_str(&format!("\\author{{{}}}\n", renderer.inline(&self.keyword("AUTHOR").pop().unwrap_or_default())));
+        let date = self.keyword("DATE").pop().map(|date| renderer.inline(&date));
+        latex.push_str(&format!("\\date{{{}}}\n", date.unwrap_or("\\today".to_string())));
+        latex.push_str(&format!("\\title{{{}}}\n", renderer.inline(&title.clone().unwrap_or_default())));
+        latex.push_str("\\begin{document}\n\n");
+        if title.is_some() {
+            latex.push_str("\\maketitle\n");
+        }
+        if toc_depth > 0 {
+            latex.push_str(&format!("\\setcounter{{tocdepth}}{{{toc_depth}}}\n\\tableofcontents\n\n"));
+        }
+        latex.push_str(&renderer.elements(&elements));
+        latex.push_str("\n\\end{document}\n");
+        latex
+    }
+}
+
+/// The LaTeX of the elements alone, without the preamble
+pub fn render(elements: &[Element], options: &LatexOptions) -> String {
+    let mut renderer = Renderer {
+        options,
+        abbreviations: HashMap::new(),
+        anchors: export::heading_anchors(elements),
+        footnotes: footnotes(elements),
+        chapters: options.class == "report" || options.class == "book",
+    };
+    renderer.elements(elements)
+}
+
+/// The footnote definitions by label, LaTeX wants their text where they are referenced
+fn footnotes(elements: &[Element]) -> HashMap<String, String> {
+    elements
+        .iter()
+        .filter_map(|element| match element {
+            Element::FootnoteDefinition { label, text } => Some((label.clone(), text.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Escapes the characters LaTeX gives a meaning
+pub fn escape(text: &str) -> String {
+    let mut result = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' => result.push_str("\\textbackslash{}"),
+            '~' => result.push_str("\\textasciitilde{}"),
+            '^' => result.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                result.push('\\');
+                result.push(c);
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// The LaTeX of a line of org text, with its markup, links and timestamps
+/// LaTeX fragments like \(x^2\) or \cite{key} are written as they are
+pub fn inline_latex(text: &str, abbreviations: &HashMap<String, String>) -> String {
+    objects_latex(&inline::parse_inline(text), abbreviations, &HashMap::new(), &HashMap::new())
+}
+
+/// anchors are the labels of the headings by title, links to headings point to them
+fn objects_latex(
+    objects: &[InlineObject],
+    abbreviations: &HashMap<String, String>,
+    anchors: &HashMap<String, String>,
+    footnotes: &HashMap<String, String>,
+) -> String {
+    objects.iter().map(|object| object_latex(object, abbreviations, anchors, footnotes)).collect()
+}
+
+fn object_latex(
+    object: &InlineObject,
+    abbreviations: &HashMap<String, String>,
+    anchors: &HashMap<String, String>,
+    footnotes: &HashMap<String, String>,
+) -> String {
+    let children = |children: &[InlineObject]| objects_latex(children, abbreviations, anchors, footnotes);
+    match object {
+        InlineObject::Plain(text) => escape(text),
+        InlineObject::Bold(objects) => format!("\\textbf{{{}}}", children(objects)),
+        InlineObject::Italic(objects) => format!("\\emph{{{}}}", children(objects)),
+        InlineObject::Underline(objects) => format!("\\uline{{{}}}", children(objects)),
+        InlineObject::StrikeThrough(objects) => format!("\\sout{{{}}}", children(objects)),
+        InlineObject::Verbatim(text) | InlineObject::Code(text) => format!("\\texttt{{{}}}", escape(text)),
+        InlineObject::Link { target, description } => {
+            let link = Link::parse(target, None, abbreviations);
+            let is_image = [".png", ".jpg", ".jpeg", ".pdf", ".eps"]
+                .iter()
+                .any(|extension| link.target.to_lowercase().ends_with(extension));
+            let text = match description {
+                Some(description) => children(description),
+                None => escape(target),
+            };
+            match &link.link_type {
+                LinkType::File { path, .. } if is_image && description.is_none() => {
+                    format!("\\includegraphics[width=.9\\linewidth]{{{path}}}")
+                }
+                LinkType::External { .. } | LinkType::File { .. } => format!("\\href{{{}}}{{{text}}}", url(&link.target)),
+                LinkType::Id(id) => format!("\\hyperref[{}]{{{text}}}", export::id_anchor(id, anchors)),
+                LinkType::CustomId(id) => format!("\\hyperref[{id}]{{{text}}}"),
+                LinkType::Heading(heading) | LinkType::Fuzzy(heading) => {
+                    format!("\\hyperref[{}]{{{text}}}", export::heading_anchor(heading, anchors))
+                }
+            }
+        }
+        InlineObject::Timestamp { raw, .. } => format!("\\textit{{{}}}", escape(raw)),
+        InlineObject::FootnoteReference { label, definition, .. } => {
+            let text = match (definition, label.as_ref().and_then(|label| footnotes.get(label))) {
+                (Some(definition), _) => children(definition),
+                (None, Some(text)) => objects_latex(&inline::parse_inline(text), abbreviations, anchors, &HashMap::new()),
+                (None, None) => String::new(),
+            };
+            format!("\\footnote{{{text}}}")
+        }
+        InlineObject::Entity { name, .. } => entity(name),
+        InlineObject::LatexFragment(text) => text.clone(),
+        InlineObject::Macro { .. } => String::new(),
+        InlineObject::RadioTarget(text) => format!("\\label{{{}}}{}", export::slug(text), escape(text)),
+        InlineObject::Target(text) => format!("\\label{{{}}}", export::slug(text)),
+    }
+}
+
+/// Entities that are math commands of the same name in LaTeX
+const MATH: &[&str] = &[
+    "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa", "lambda", "mu", "nu", "xi", "pi", "rho",
+    "sigma", "tau", "upsilon", "phi", "chi", "psi", "omega", "Gamma", "Delta", "Theta", "Lambda", "Xi", "Pi", "Sigma", "Upsilon",
+    "Phi", "Psi", "Omega", "varepsilon", "vartheta", "varpi", "varsigma", "varphi", "times", "div", "pm", "mp", "le", "ge", "ne",
+    "leq", "geq", "neq", "approx", "equiv", "cong", "sim", "cap", "cup", "forall", "exists", "in", "ni", "notin", "emptyset",
+    "int", "sum", "prod", "nabla", "partial", "infty", "oplus", "otimes", "perp", "prime", "neg", "wedge", "vee", "angle",
+    "aleph", "ell", "hbar", "cdot", "cdots", "ldots", "star", "ast", "to", "gets", "rightarrow", "leftarrow", "uparrow",
+    "downarrow", "leftrightarrow", "Rightarrow", "Leftarrow", "Leftrightarrow",
+];
+
+/// Entities that are text, the math commands of the same name as math, other letters and signs of Latin-1 as UTF-8
+/// Anything else would stop pdflatex and is left out
+fn entity(name: &str) -> String {
+    let text = match name {
+        "nbsp" => "~",
+        "ndash" => "--",
+        "mdash" => "---",
+        "hellip" => "\\ldots{}",
+        "laquo" => "\\guillemotleft{}",
+        "raquo" => "\\guillemotright{}",
+        "copy" => "\\textcopyright{}",
+        "lsquo" => "`",
+        "rsquo" => "'",
+        "ldquo" => "``",
+        "rdquo" => "''",
+        "euro" | "EUR" => "\\texteuro{}",
+        "bull" | "bullet" | "textbullet" => "\\textbullet{}",
+        "dagger" | "dag" => "\\dag{}",
+        "Dagger" | "ddag" => "\\ddag{}",
+        "trade" => "\\texttrademark{}",
+        "dots" => "\\dots{}",
+        "larr" => "\\(\\leftarrow\\)",
+        "rarr" => "\\(\\rightarrow\\)",
+        "uarr" => "\\(\\uparrow\\)",
+        "darr" => "\\(\\downarrow\\)",
+        "harr" => "\\(\\leftrightarrow\\)",
+        "rArr" => "\\(\\Rightarrow\\)",
+        "lArr" => "\\(\\Leftarrow\\)",
+        _ if MATH.contains(&name) => return format!("\\(\\{name}\\)"),
+        _ => {
+            return inline::entity_utf8(name)
+                .filter(|utf8| utf8.chars().all(|c| u32::from(c) <= 0xff))
+                .map(escape)
+                .unwrap_or_default()
+        }
+    };
+    text.to_string()
+}
+
+/// % and # end or break the argument of \href
+fn url(target: &str) -> String {
+    target.replace('\\', "\\\\").replace('%', "\\%").replace('#', "\\#")
+}
+
+fn todo_latex(todo: &TodoStates) -> String {
+    format!("\\textbf{{{}}}", todo.build())
+}
+
+impl Renderer<'_> {
+    fn elements(&mut self, elements: &[Element]) -> String {
+        let latex: Vec<String> = elements.iter().map(|element| self.element(element)).filter(|latex| !latex.is_empty()).collect();
+        let mut latex = latex.join("\n\n");
+        latex.push('\n');
+        latex
+    }
+
+    fn inline(&self, text: &str) -> String {
+        objects_latex(&inline::parse_inline(text), &self.abbreviations, &self.anchors, &self.footnotes)
+    }
+
+    fn element(&mut self, element: &Element) -> String {
+        match element {
+            Element::Heading(heading) => {
+                let commands = ["chapter", "section", "subsection", "subsubsection", "paragraph", "subparagraph"];
+                let first = if self.chapters { 0 } else { 1 };
+                let command = commands[(first + heading.level as usize - 1).min(commands.len() - 1)];
+                let mut parts = Vec::new();
+                if let Some(todo) = &heading.todo {
+                    parts.push(todo_latex(todo));
+                }
+                if let Some(priority) = &heading.priority {
+                    parts.push(format!("\\framebox{{{}}}", escape(priority.build().trim_matches(['[', ']']))));
+                }
+                parts.push(self.inline(&heading.title));
+                if !heading.tags.is_empty() {
+                    let tags: Vec<String> = heading.tags.iter().map(|tag| escape(tag)).collect();
+                    parts.push(format!("\\hfill{{}}\\textsc{{{}}}", tags.join(":")));
+                }
+                format!("\\{command}{{{}}}\n\\label{{{}}}", parts.join(" "), heading.anchor)
+            }
+            Element::Planning(info) if self.options.planning => {
+                let parts: Vec<String> = info
+                    .iter()
+                    .map(|info| {
+                        let keyword = match info {
+                            InfoType::DEADLINE { .. } => "DEADLINE:",
+                            InfoType::SCHEDULED { .. } => "SCHEDULED:",
+                            InfoType::CLOSED { .. } => "CLOSED:",
+                        };
+                        format!("\\textbf{{{keyword}}} \\textit{{{}}}", escape(&info.date().build()))
+                    })
+                    .collect();
+                format!("\\noindent{}", parts.join(" "))
+            }
+            Element::Keyword { key, value } if key == "LATEX" => value.clone(),
+            Element::Planning(_) | Element::Keyword { .. } | Element::FootnoteDefinition { .. } => String::new(),
+            Element::Paragraph(lines) => lines.iter().map(|line| self.inline(line)).collect::<Vec<_>>().join("\n"),
+            Element::List(list) => self.list(list),
+            Element::Table(rows) => self.table(rows),
+            Element::Block { name, parameters, lines } => self.block(name, parameters, lines),
+            Element::Rule => "\\noindent\\rule{\\linewidth}{0.5pt}".to_string(),
+        }
+    }
+
+    fn list(&self, list: &List) -> String {
+        let environment = match list.kind {
+            ListKind::Unordered => "itemize",
+            ListKind::Ordered => "enumerate",
+            ListKind::Description => "description",
+        };
+        let mut latex = format!("\\begin{{{environment}}}\n");
+        for item in &list.items {
+            let label = match (&item.term, item.checkbox) {
+                (Some(term), _) => format!("[{{{}}}] ", self.inline(term)),
+                (None, Some(true)) => "[{$\\boxtimes$}] ".to_string(),
+                (None, Some(false)) => "[{$\\square$}] ".to_string(),
+                (None, None) => " ".to_string(),
+            };
+            let mut text = String::new();
+            if let Some(todo) = &item.todo {
+                text.push_str(&(todo_latex(todo) + " "));
+            }
+            text.push_str(&self.inline(&item.text));
+            latex.push_str(&format!("\\item{label}{text}\n"));
+            if let Some(sublist) = &item.sublist {
+                latex.push_str(&self.list(sublist));
+                latex.push('\n');
+            }
+        }
+        latex.push_str(&format!("\\end{{{environment}}}"));
+        latex
+    }
+
+    /// A rule after the header rows becomes a line below them, like in the org table
+    fn table(&self, rows: &[Row]) -> String {
+        let columns = rows
+            .iter()
+            .map(|row| match row {
+                Row::Cells(cells) => cells.len(),
+                Row::Rule => 0,
+            })
+            .max()
+            .unwrap_or(0);
+        let mut latex = format!("\\begin{{center}}\n\\begin{{tabular}}{{{}}}\n", "l".repeat(columns.max(1)));
+        for row in rows {
+            match row {
+                Row::Rule => latex.push_str("\\hline\n"),
+                Row::Cells(cells) => {
+                    let cells: Vec<String> = cells.iter().map(|cell| self.inline(cell)).collect();
+                    latex.push_str(&format!("{} \\\\\n", cells.join(" & ")));
+                }
+            }
+        }
+        latex.push_str("\\end{tabular}\n\\end{center}");
+        latex
+    }
+
+    fn block(&self, name: &str, parameters: &str, lines: &[String]) -> String {
+        let language = parameters.split_whitespace().next().unwrap_or_default();
+        let raw = lines.join("\n");
+        match name {
+            "src" if self.options.minted && !language.is_empty() => format!("\\begin{{minted}}{{{language}}}\n{raw}\n\\end{{minted}}"),
+            "src" | "example" => format!("\\begin{{verbatim}}\n{raw}\n\\end{{verbatim}}"),
+            "export" if language.eq_ignore_ascii_case("latex") => raw,
+            "latex" => raw,
+            "export" | "comment" => String::new(),
+            "verse" => format!(
+                "\\begin{{verse}}\n{}\n\\end{{verse}}",
+                lines.iter().map(|line| self.inline(line.trim())).collect::<Vec<_>>().join("\\\\\n")
+            ),
+            // quote, center and special blocks become environments of the same name
+            _ => format!("\\begin{{{name}}}\n{}\n\\end{{{name}}}", self.paragraphs(lines)),
+        }
+    }
+
+    /// Text of a block, empty lines separate paragraphs
+    fn paragraphs(&self, lines: &[String]) -> String {
+        lines
+            .split(|line| line.trim().is_empty())
+            .filter(|paragraph| !paragraph.is_empty())
+            .map(|paragraph| paragraph.iter().map(|line| self.inline(line.trim())).collect::<Vec<_>>().join("\n"))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
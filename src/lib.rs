@@ -1,9 +1,30 @@
-mod builder;
-mod parser;
-mod structs;
-mod object;
-mod object_types;
-mod time_management;
+pub mod agenda;
+pub mod archive;
+pub mod builder;
+pub mod edit;
+pub mod export;
+pub mod filter;
+pub mod footnote;
+pub mod habit;
+pub mod html;
+pub mod history;
+pub mod ical;
+pub mod inline;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod latex;
+pub mod link;
+pub mod markdown;
+pub mod parser;
+pub mod refile;
+pub mod structs;
+pub mod tasks;
+pub mod terminal;
+pub mod object;
+pub mod outline;
+pub mod object_types;
+pub mod time_management;
+pub mod traversal;
 
 //  let lines: Vec<String> = input.lines().map(|line| line.to_string()).collect();
 
@@ -16,11 +37,10 @@ mod tests {
     use std::{
         fs::File,
         io::{BufRead, BufReader},
-        str::FromStr,
     };
 
     use chrono::NaiveDate;
-    use structs::Object;
+    use object::Object;
 
     use super::*;
 
@@ -57,17 +77,1184 @@ mod tests {
 
     #[test]
     fn time_parse() {
-        let time = structs::ParsedDateTime::parse("<2024-07-12 Fri>").unwrap();
+        let time = time_management::ParsedDateTime::parse("<2024-07-12 Fri>").unwrap();
         println!("{:#?}", time);
-        let time = structs::ParsedDateTime::parse("<2024-07-12 Fri .+ld>").unwrap();
+        let time = time_management::ParsedDateTime::parse("<2024-07-12 Fri .+ld>").unwrap();
         println!("{:#?}", time);
         panic!("Stop");
     }
     #[test]
     fn info_parse() {
-        let info = structs::ObjectTypes::new_info(
+        object_types::ObjectTypes::new_info(
             "DEADLINE: <2024-07-13 Sat> SCHEDULED: <2024-07-12 Fri>".to_string(),
         );
         panic!("Stop");
     }
+
+    #[test]
+    fn inline_markup() {
+        use inline::InlineObject;
+        let objects = inline::parse_inline("Some *bold /and italic/* text with =code= and [[https://orgmode.org][a link]]");
+        assert_eq!(objects[0], InlineObject::Plain("Some ".to_string()));
+        assert_eq!(
+            objects[1],
+            InlineObject::Bold(vec![
+                InlineObject::Plain("bold ".to_string()),
+                InlineObject::Italic(vec![InlineObject::Plain("and italic".to_string())]),
+            ])
+        );
+        assert_eq!(objects[3], InlineObject::Verbatim("code".to_string()));
+        assert!(matches!(&objects[5], InlineObject::Link { target, .. } if target == "https://orgmode.org"));
+        assert_eq!(
+            inline::build_inline(&objects),
+            "Some *bold /and italic/* text with =code= and [[https://orgmode.org][a link]]"
+        );
+        // Markers inside words are not markup
+        assert_eq!(inline::parse_inline("a*b*c 2/3/4").len(), 1);
+    }
+
+    #[test]
+    fn inline_objects_in_lines() {
+        use inline::InlineObject;
+        let text = object_types::ObjectTypes::new_text(
+            "Meeting <2024-07-12 Fri> see[fn:1] \\alpha {{{date(%Y)}}} <<here>> $x^2$".to_string(),
+        );
+        let objects = text.inline_objects();
+        assert_eq!(inline::collect_timestamps(&objects).len(), 1);
+        assert!(objects.contains(&InlineObject::FootnoteReference { label: Some("1".to_string()), definition: None, padding: Default::default() }));
+        assert!(objects.contains(&InlineObject::Entity { name: "alpha".to_string(), raw: "\\alpha".to_string() }));
+        assert!(objects.contains(&InlineObject::Macro { name: "date".to_string(), arguments: vec!["%Y".to_string()], written: vec!["%Y".to_string()] }));
+        assert!(objects.contains(&InlineObject::Target("here".to_string())));
+        assert!(objects.contains(&InlineObject::LatexFragment("$x^2$".to_string())));
+        // Building writes back what was read
+        let written = "A\\mdash{}B {{{kbd(C-c , x)}}} caf\\eacute{} see[fn:: [[https://orgmode.org][org]] docs] end[fn:tight:no space]";
+        let objects = inline::parse_inline(written);
+        assert_eq!(inline::build_inline(&objects), written);
+        assert!(objects.contains(&InlineObject::Entity { name: "eacute".to_string(), raw: "\\eacute{}".to_string() }));
+        assert!(matches!(&objects[7], InlineObject::FootnoteReference { label: None, definition: Some(definition), .. } if definition.len() == 2));
+        let mut changed = objects.clone();
+        changed[3] = InlineObject::Macro { name: "kbd".to_string(), arguments: vec!["C-x".to_string()], written: vec!["C-c ".to_string(), " x".to_string()] };
+        assert_eq!(changed[3].build(), "{{{kbd(C-x)}}}");
+
+        let heading = object_types::ObjectTypes::new_heading("* TODO Read ~the~ +old+ _book_");
+        let objects = heading.inline_objects();
+        assert_eq!(objects[1], InlineObject::Code("the".to_string()));
+        assert_eq!(objects[3], InlineObject::StrikeThrough(vec![InlineObject::Plain("old".to_string())]));
+        assert_eq!(objects[5], InlineObject::Underline(vec![InlineObject::Plain("book".to_string())]));
+    }
+
+    #[test]
+    fn link_graph() {
+        let lines = |text: &str| text.lines().map(|l| l.to_string()).collect::<Vec<String>>();
+        let notes = structs::File::parse(
+            structs::FileContext::Todo,
+            lines("#+LINK: gh https://github.com/%s\n* Rust :lang:\n:PROPERTIES:\n:ID: rust-id\n:END:\nSee [[*Tools]] and [[gh:rust-lang/rust][the repo]]\n* Tools\n<<cargo>> and [[missing]]"),
+        );
+        let tasks = structs::File::parse(
+            structs::FileContext::Todo,
+            lines("* TODO Learn [[id:rust-id][Rust]]\n[[file:notes.org::cargo]] [[file:notes.org::*Nothing]] [[id:gone]]"),
+        );
+        let graph = link::LinkGraph::build(&[("dir/notes.org".to_string(), &notes), ("dir/tasks.org".to_string(), &tasks)]);
+        assert_eq!(graph.edges().len(), 7);
+        let external = &graph.edges()[1];
+        assert_eq!(external.link.target, "https://github.com/rust-lang/rust");
+        assert_eq!(external.resolution, link::Resolution::External);
+
+        let rust = link::LinkNode { file: "dir/notes.org".to_string(), path: vec!["Rust".to_string()] };
+        let backlinks = graph.backlinks(&rust);
+        assert_eq!(backlinks.len(), 1);
+        assert_eq!(backlinks[0].source.path, vec!["Learn [[id:rust-id][Rust]]"]);
+
+        let tools = link::LinkNode { file: "dir/notes.org".to_string(), path: vec!["Tools".to_string()] };
+        assert_eq!(graph.backlinks(&tools).len(), 2);
+
+        let broken: Vec<String> = graph.broken_links().iter().map(|edge| edge.link.target.clone()).collect();
+        assert_eq!(broken, vec!["missing", "file:notes.org::*Nothing", "id:gone"]);
+
+        // Headings with the same title are different nodes, %h is URL-encoded
+        let same = structs::File::parse(
+            structs::FileContext::Todo,
+            lines("#+LINK: ddg https://duckduckgo.com/?q=%h\n* Work\n** Notes\n[[ddg:org mode/links]]\n* Home\n** Notes\n[[*Work]]"),
+        );
+        let graph = link::LinkGraph::build(&[("same.org".to_string(), &same)]);
+        assert_eq!(graph.edges()[0].link.target, "https://duckduckgo.com/?q=org%20mode%2Flinks");
+        let home = link::LinkNode { file: "same.org".to_string(), path: vec!["Home".to_string(), "Notes".to_string()] };
+        assert_eq!(graph.links_from(&home).len(), 1);
+        assert_eq!(graph.edges()[1].source.to_string(), "same.org::*Home/Notes");
+    }
+
+    #[test]
+    fn first_child_siblings() {
+        let lines = vec!["* First", ":PROPERTIES:", ":ID: first", ":END:", "Body", "** Sub", "* Second"];
+        let file = structs::File::parse(structs::FileContext::Todo, lines.iter().map(|line| line.to_string()).collect());
+        assert_eq!(file.children.len(), 2);
+        let first = &file.children[0];
+        // The lines after the drawer start are its siblings, not its children
+        assert_eq!(first.children().len(), 5);
+        assert!(first.children()[0].children().is_empty());
+        assert_eq!(first.property("ID"), Some("first"));
+        assert_eq!(file.find_path(&["First", "Sub"]).unwrap().outline_path(), vec!["First", "Sub"]);
+        assert_eq!(file.build(), lines);
+    }
+
+    #[test]
+    fn unclosed_blocks() {
+        let lines = vec!["* Notes", "#+begin_quote", "never ended", "* Next", "- item", "#+begin_src sh", "* not a heading", "#+end_src", "* Last"];
+        let file = structs::File::parse(structs::FileContext::Todo, lines.iter().map(|line| line.to_string()).collect());
+        assert_eq!(file.build(), lines);
+        assert!(file.find_path(&["Next"]).is_some() && file.find_path(&["Last"]).is_some());
+        assert!(file.find_path(&["not a heading"]).is_none());
+        assert!(matches!(file.find_path(&["Next"]).unwrap().object.children()[0].object_type(), object_types::ObjectTypes::ListElement { .. }));
+
+        // Exports don't put the headings after an unclosed block into it
+        let elements = file.elements();
+        assert!(matches!(&elements[1], export::Element::Paragraph(lines) if lines[0] == "#+begin_quote"));
+        assert_eq!(export::headings(&elements, 3).len(), 3);
+        let html = file.to_html(&html::HtmlOptions::default());
+        assert!(html.contains("<h2 id=\"next\">Next</h2>") && html.contains("<h2 id=\"last\">Last</h2>"));
+    }
+
+    #[test]
+    fn line_kinds() {
+        let lines = vec!["* Heading", "*bold* at the start", "  * indented star", "-----", "- item", "  - nested", "    - deeper", "- next", "* Last"];
+        let file = structs::File::parse(structs::FileContext::Todo, lines.iter().map(|line| line.to_string()).collect());
+        assert_eq!(file.build(), lines);
+        // Only stars followed by whitespace at the start of the line make a heading
+        assert_eq!(file.children.len(), 2);
+        let heading = &file.children[0];
+        assert!(matches!(heading.children()[0].object_type(), object_types::ObjectTypes::Text { .. }));
+        assert!(matches!(heading.children()[1].object_type(), object_types::ObjectTypes::Text { .. }));
+        // A rule is no list item
+        assert!(matches!(heading.children()[2].object_type(), object_types::ObjectTypes::Text { .. }));
+        // List items nest by their indentation, the value is the indentation plus one
+        let item = &heading.children()[3];
+        assert!(matches!(item.object_type(), object_types::ObjectTypes::ListElement { value: 1, .. }));
+        assert!(matches!(item.children()[0].object_type(), object_types::ObjectTypes::ListElement { value: 3, .. }));
+        assert!(matches!(item.children()[0].children()[0].object_type(), object_types::ObjectTypes::ListElement { value: 5, .. }));
+        assert!(matches!(heading.children()[4].object_type(), object_types::ObjectTypes::ListElement { value: 1, .. }));
+    }
+
+    #[test]
+    fn property_drawers() {
+        let lines = vec!["* Project", "  :PROPERTIES:", "  :ORDERED:", "  :ID:       project-id", "  :CUSTOM_ID: project", "  :END:"];
+        let mut file = structs::File::parse(structs::FileContext::Todo, lines.iter().map(|line| line.to_string()).collect());
+        assert_eq!(file.build(), lines);
+        let project = file.heading_mut(&["Project"]).unwrap();
+        assert_eq!(project.property("ID"), Some("project-id"));
+        assert_eq!(project.property("CUSTOM_ID"), Some("project"));
+        project.set_property("CUSTOM_ID", "renamed");
+        assert_eq!(file.build()[4], ":CUSTOM_ID: renamed");
+        assert_eq!(file.build()[3], "  :ID:       project-id");
+    }
+
+    #[test]
+    fn footnotes() {
+        let lines = "* Paper\nFirst[fn:3] and second[fn:: inline note] and named[fn:src]\nAgain[fn:3] and lost[fn:9]\n\n[fn:3] The definition\n[fn:src] Source\n[fn:7] Never used"
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        let mut file = structs::File::parse(structs::FileContext::Todo, lines);
+        assert_eq!(file.footnote_definition("3"), Some("The definition".to_string()));
+        assert_eq!(file.footnotes()[0].references, 2);
+        assert_eq!(file.footnotes()[1].definition, Some("inline note".to_string()));
+
+        let report = file.orphaned_footnotes();
+        assert_eq!(report.undefined_references, vec!["9"]);
+        assert_eq!(report.unreferenced_definitions, vec!["7"]);
+
+        file.renumber_footnotes();
+        let built: Vec<String> = file.children.iter().flat_map(|child| child.build()).collect();
+        assert_eq!(built[1], "First[fn:1] and second[fn:2: inline note] and named[fn:src]");
+        assert_eq!(built[2], "Again[fn:1] and lost[fn:3]");
+        assert_eq!(built[4], "[fn:1] The definition");
+        assert_eq!(built[6], "[fn:4] Never used");
+
+        // Only labels change, the rest of the line is kept as written
+        let lines = "Text\\mdash{}more[fn:5] {{{kbd(C-c , x)}}} =[fn:5]= *see[fn:: [[https://orgmode.org][org]]]*\n[fn:5]"
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        let mut file = structs::File::parse(structs::FileContext::Todo, lines);
+        file.renumber_footnotes();
+        assert_eq!(file.build(), vec!["Text\\mdash{}more[fn:1] {{{kbd(C-c , x)}}} =[fn:5]= *see[fn:2: [[https://orgmode.org][org]]]*", "[fn:1]"]);
+    }
+
+    #[test]
+    fn repeater_kinds() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let updated = |timestamp: &str| {
+            let mut date = time_management::ParsedDateTime::parse(timestamp).unwrap();
+            date.update_on(today);
+            date.build()
+        };
+        assert_eq!(updated("<2024-01-31 Wed +1m>"), "<2024-02-29 Thu +1m>");
+        assert_eq!(updated("<2023-03-20 Mon +1y>"), "<2024-03-20 Wed +1y>");
+        // Every kind of repeater moves one interval on from the old date
+        assert_eq!(updated("<2024-03-01 Fri +1w>"), "<2024-03-08 Fri +1w>");
+        assert_eq!(updated("<2024-03-01 Fri ++1w>"), "<2024-03-08 Fri ++1w>");
+        assert_eq!(updated("<2024-03-01 Fri .+2d>"), "<2024-03-03 Sun .+2d>");
+        assert_eq!(updated("<2024-03-25 Mon +1w>"), "<2024-03-25 Mon +1w>");
+        // Tokens that are not understood don't make the whole timestamp unreadable
+        let date = time_management::ParsedDateTime::parse("<2024-03-01 Fri 10:00 +1w foo>").unwrap();
+        assert_eq!(date.build(), "<2024-03-01 Fri 10:00 +1w>");
+    }
+
+    #[test]
+    fn planning_line() {
+        let lines = "* DONE Report\nSCHEDULED: <2024-07-12 Fri> CLOSED: [2024-07-14 Sun 18:05] DEADLINE: <2024-07-13 Sat> WAITING: x"
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        let mut file = structs::File::parse(structs::FileContext::Todo, lines);
+        let heading = &mut file.children[0];
+        assert_eq!(
+            heading.build()[1],
+            "DEADLINE: <2024-07-13 Sat> SCHEDULED: <2024-07-12 Fri> CLOSED: [2024-07-14 Sun 18:05] WAITING: x"
+        );
+        match heading.object_type() {
+            object_types::ObjectTypes::Heading { deadline, scheduled, closed, .. } => {
+                assert_eq!(deadline.as_ref().unwrap().date(), NaiveDate::from_ymd_opt(2024, 7, 13).unwrap());
+                assert_eq!(scheduled.as_ref().unwrap().date(), NaiveDate::from_ymd_opt(2024, 7, 12).unwrap());
+                let closed = closed.as_ref().unwrap();
+                assert!(!closed.is_active());
+                assert_eq!(closed.time(), chrono::NaiveTime::from_hms_opt(18, 5, 0));
+            }
+            _ => panic!("Not a heading"),
+        }
+
+        let closed = time_management::ParsedDateTime::parse("[2024-07-20 Sat 09:00]").unwrap();
+        heading.set_planning(time_management::InfoType::CLOSED { date: closed });
+        assert_eq!(
+            heading.build()[1],
+            "DEADLINE: <2024-07-13 Sat> SCHEDULED: <2024-07-12 Fri> CLOSED: [2024-07-20 Sat 09:00] WAITING: x"
+        );
+        let mut plain = object::Object::new(object_types::ObjectTypes::new_heading("* TODO Plain"));
+        let deadline = time_management::ParsedDateTime::parse("<2024-08-01 Thu>").unwrap();
+        plain.set_planning(time_management::InfoType::DEADLINE { date: deadline });
+        assert_eq!(plain.build(), vec!["* TODO Plain", "DEADLINE: <2024-08-01 Thu>"]);
+    }
+
+    #[test]
+    fn completion_logging() {
+        let lines = "* LOOP Latein\n- [X] Anki\n* DONE Brief abgeben\n:PROPERTIES:\n:ID: brief\n:END:\n* LOOP Untouched\n- [ ] Duolingo"
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        let mut file = structs::File::parse(structs::FileContext::Todo, lines);
+        let mut log = structs::CompletionLog::new(true, true, true);
+        log.time = NaiveDate::from_ymd_opt(2024, 7, 12).unwrap().and_hms_opt(10, 30, 45).unwrap();
+        file.update_loop(&log);
+        file.log_completion(&structs::TodoStates::DONE, &log);
+        let built: Vec<String> = file.children.iter().flat_map(|child| child.build()).collect();
+        assert_eq!(
+            built,
+            vec![
+                "* LOOP Latein",
+                ":PROPERTIES:",
+                ":LAST_REPEAT: [2024-07-12 Fri 10:30]",
+                ":END:",
+                ":LOGBOOK:",
+                "- State \"DONE\"       from \"LOOP\"       [2024-07-12 Fri 10:30]",
+                ":END:",
+                "- [ ] Anki",
+                "* DONE Brief abgeben",
+                "CLOSED: [2024-07-12 Fri 10:30]",
+                ":PROPERTIES:",
+                ":ID: brief",
+                ":END:",
+                ":LOGBOOK:",
+                "- State \"DONE\"       from \"TODO\"       [2024-07-12 Fri 10:30]",
+                ":END:",
+                "* LOOP Untouched",
+                "- [ ] Duolingo",
+            ]
+        );
+        // Parsing it again keeps the logbook as it is
+        let mut file = structs::File::parse(structs::FileContext::Todo, built);
+        assert_eq!(file.children[1].logbook().len(), 1);
+        file.log_completion(&structs::TodoStates::DONE, &log);
+        assert_eq!(file.children[1].logbook().len(), 1);
+
+        // Without CLOSED, the note alone keeps headings that stay in the file from being logged on every run
+        let notes_only = structs::CompletionLog::new(false, true, false);
+        let lines = vec!["* DONE Stays :noarchive:".to_string()];
+        let mut file = structs::File::parse(structs::FileContext::Todo, lines);
+        file.log_completion(&structs::TodoStates::DONE, &notes_only);
+        file.log_completion(&structs::TodoStates::DONE, &notes_only);
+        assert_eq!(file.children[0].logbook().len(), 1);
+    }
+
+    #[test]
+    fn agenda_views() {
+        let lines = "* TODO [#A] Write report\nDEADLINE: <2024-07-20 Sat -10d>\n* TODO Call bank\nSCHEDULED: <2024-07-10 Wed>\n* Team meeting <2024-07-08 Mon 10:00 +1w>\n* DONE Old\nDEADLINE: <2024-07-01 Mon>\n* Notes\nLunch <2024-07-16 Tue>"
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        let file = structs::File::parse(structs::FileContext::Todo, lines);
+        let mut agenda = agenda::Agenda::new();
+        agenda.add_file("work.org", &file);
+        let today = NaiveDate::from_ymd_opt(2024, 7, 15).unwrap();
+
+        let day: Vec<&str> = agenda.day(today).iter().map(|item| item.entry.heading.as_str()).collect();
+        assert_eq!(day, vec!["Team meeting"]);
+        let overdue: Vec<&str> = agenda.overdue(today).iter().map(|entry| entry.heading.as_str()).collect();
+        assert_eq!(overdue, vec!["Call bank"]);
+        let upcoming = agenda.upcoming_deadlines(today);
+        assert_eq!(upcoming.len(), 1);
+        assert_eq!(upcoming[0].date, NaiveDate::from_ymd_opt(2024, 7, 20).unwrap());
+        // Outside of the 10 day warning period the deadline does not show up yet
+        assert!(agenda.upcoming_deadlines(NaiveDate::from_ymd_opt(2024, 7, 5).unwrap()).is_empty());
+
+        let (start, end) = agenda::AgendaView::Week.range(today);
+        let meetings: usize = (0..=(end - start).num_days())
+            .map(|offset| agenda.day(start + chrono::Duration::days(offset)).len())
+            .sum();
+        assert_eq!(meetings, 3);
+        let month = agenda.render(agenda::AgendaView::Month, today);
+        assert_eq!(month.matches("Team meeting").count(), 4);
+        assert!(month.contains("Sched. 5x:"));
+    }
+
+    #[test]
+    fn query_matches() {
+        let lines = "* Work :work:\n** NEXT [#A] Fix bug\nDEADLINE: <2024-07-18 Thu>\n** TODO Plan :home:\n** NEXT Review\n:PROPERTIES:\n:EFFORT: 3\n:END:\n* Home :home:\n** NEXT Clean"
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        let file = structs::File::parse(structs::FileContext::Todo, lines);
+        let today = NaiveDate::from_ymd_opt(2024, 7, 15).unwrap();
+        let titles = |query: &str| -> Vec<String> {
+            let query = filter::Query::parse_at(query, today).unwrap();
+            file.query(&query).iter().map(|found| found.path.join("/")).collect()
+        };
+        assert_eq!(titles("+work-home"), vec!["Work", "Work/Fix bug", "Work/Review"]);
+        assert_eq!(titles(r#"+work+PRIORITY="A"+DEADLINE<="<+1w>""#), vec!["Work/Fix bug"]);
+        assert!(titles(r#"+work+DEADLINE<="<+1d>""#).is_empty());
+        assert_eq!(titles(r#"TODO="NEXT"-work"#), vec!["Home/Clean"]);
+        assert_eq!(titles("EFFORT>2|LEVEL=1/-NEXT"), vec!["Work", "Home"]);
+        // A - right after the operator belongs to the number
+        assert_eq!(titles("EFFORT>-1-home"), vec!["Work/Review"]);
+        assert_eq!(titles("{^ho}/NEXT"), vec!["Home/Clean"]);
+        assert!(filter::Query::parse("PRIORITY=A").is_err());
+    }
+
+    #[test]
+    fn tree_traversal() {
+        use object_types::ObjectTypes;
+        use traversal::{VisitAction, Visitor, VisitorMut};
+        let lines = "* Projects\n** TODO Website :web:\nSome text\n*** DONE Draft\n* Inbox\n** TODO Call"
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        let mut file = structs::File::parse(structs::FileContext::Todo, lines);
+        let title = |obj: &object::Object| match obj.object_type() {
+            ObjectTypes::Heading { text, .. } => link::heading_title(text),
+            ObjectTypes::Text { text } => text.clone(),
+            _ => String::new(),
+        };
+        let depth: Vec<String> = file.iter().map(title).collect();
+        assert_eq!(depth, vec!["Projects", "Website", "Some text", "Draft", "Inbox", "Call"]);
+        let breadth: Vec<String> = file.iter_breadth_first().map(title).collect();
+        assert_eq!(breadth, vec!["Projects", "Inbox", "Website", "Call", "Some text", "Draft"]);
+
+        let draft = file.find(|obj| title(obj) == "Draft").unwrap();
+        assert_eq!(draft.outline_path(), vec!["Projects", "Website", "Draft"]);
+        assert_eq!(title(draft.parent_heading().unwrap()), "Website");
+        assert_eq!(file.find_path(&["Inbox", "Call"]).unwrap().ancestors.len(), 1);
+        let todos = file.find_all(|obj| matches!(obj.object_type(), ObjectTypes::Heading { todo: Some(structs::TodoStates::TODO), .. }));
+        assert_eq!(todos.len(), 2);
+        assert_eq!(file.children[0].find_all(traversal::is_heading).len(), 3);
+
+        struct Titles(Vec<String>);
+        impl Visitor for Titles {
+            fn visit(&mut self, obj: &object::Object, path: &[&object::Object]) -> VisitAction {
+                if !traversal::is_heading(obj) {
+                    return VisitAction::Continue;
+                }
+                self.0.push(format!("{}{}", "-".repeat(path.len()), link::heading_title(&obj.object_type().build())));
+                match obj.object_type().tags().is_empty() {
+                    true => VisitAction::Continue,
+                    false => VisitAction::SkipChildren,
+                }
+            }
+        }
+        let mut titles = Titles(Vec::new());
+        file.accept(&mut titles);
+        assert_eq!(titles.0, vec!["* Projects", "-** TODO Website", "* Inbox", "-** TODO Call"]);
+
+        struct Prefix;
+        impl VisitorMut for Prefix {
+            fn visit(&mut self, obj: &mut object::Object, path: &[String]) -> VisitAction {
+                if let ObjectTypes::Heading { text, .. } = obj.object_type_mut() {
+                    if !path.is_empty() {
+                        *text = format!("{} {}", path.join("/"), text);
+                    }
+                }
+                VisitAction::Continue
+            }
+        }
+        file.accept_mut(&mut Prefix);
+        assert_eq!(file.find_path(&["Inbox", "Inbox Call"]).unwrap().outline_path().len(), 2);
+        assert!(file.find_path(&["Projects", "Projects Website", "Projects/Projects Website Draft"]).is_some());
+    }
+
+    #[test]
+    fn tree_editing() {
+        let lines = "* Projects\n** TODO Website\nSome text\n*** DONE Draft\n* Inbox\n** TODO Call\n** Shop"
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        let mut file = structs::File::parse(structs::FileContext::Todo, lines);
+        let text = |file: &structs::File| -> Vec<String> { file.children.iter().flat_map(|c| c.build()).collect() };
+
+        file.insert_heading(&["Projects", "Website"], object::Object::heading("NEXT Deploy :ops:")).unwrap();
+        assert_eq!(file.find_path(&["Projects", "Website", "Deploy"]).unwrap().object.build(), vec!["*** NEXT Deploy :ops:"]);
+        assert!(file.insert_heading(&["Nowhere"], object::Object::heading("Lost")).is_err());
+
+        file.move_subtree(&["Projects", "Website"], &["Inbox", "Call"]).unwrap();
+        assert_eq!(
+            text(&file),
+            vec!["* Projects", "* Inbox", "** TODO Call", "*** TODO Website", "Some text", "**** DONE Draft", "**** NEXT Deploy :ops:", "** Shop"]
+        );
+        assert!(file.move_subtree(&["Inbox"], &["Inbox", "Call"]).is_err());
+        let before = text(&file);
+        assert!(file.move_subtree(&["Inbox", "Call"], &["Inbox", "Nowhere"]).is_err());
+        assert!(file.move_subtree(&["Nowhere"], &["Inbox"]).is_err());
+        assert_eq!(text(&file), before);
+
+        file.promote(&["Inbox", "Call", "Website"]).unwrap();
+        assert_eq!(file.find_path(&["Inbox", "Website", "Draft"]).unwrap().object.level(), Some(3));
+        assert!(file.promote(&["Inbox"]).is_err());
+        file.demote(&["Inbox", "Website"]).unwrap();
+        assert!(file.find_path(&["Inbox", "Call", "Website", "Deploy"]).is_some());
+        assert!(file.demote(&["Projects"]).is_err());
+        assert!(file.demote(&[]).is_err() && file.promote(&[]).is_err());
+
+        let cut = file.cut(&["Inbox", "Call"]).unwrap();
+        assert!(file.delete(&["Inbox", "Shop"]));
+        assert!(!file.delete(&["Inbox", "Shop"]));
+        file.insert_heading(&[], cut).unwrap();
+        assert_eq!(
+            text(&file),
+            vec!["* Projects", "* Inbox", "* TODO Call", "** TODO Website", "Some text", "*** DONE Draft", "*** NEXT Deploy :ops:"]
+        );
+        let mut heading = object::Object::heading("Top");
+        assert!(heading.promote().is_err());
+        heading.demote().unwrap();
+        assert_eq!(heading.build(), vec!["** Top"]);
+
+        // Later siblings move up when an earlier heading is taken out
+        let lines = "* A\n* B\n** C".lines().map(|l| l.to_string()).collect();
+        let mut file = structs::File::parse(structs::FileContext::Todo, lines);
+        file.move_subtree(&["A"], &["B", "C"]).unwrap();
+        assert_eq!(text(&file), vec!["* B", "** C", "*** A"]);
+    }
+
+    #[test]
+    fn refiling() {
+        let parse = |text: &str| structs::File::parse(structs::FileContext::Todo, text.lines().map(|l| l.to_string()).collect());
+        let mut inbox = parse("* Inbox\n** TODO Fix server :work:\n:PROPERTIES:\n:ID: abc\n:END:\n*** Logs\n** TODO Buy milk :home:\n** Read book\n* Home");
+        let mut files = std::collections::HashMap::new();
+        files.insert("work.org".to_string(), parse("* Work\n** Inbox"));
+
+        assert_eq!(inbox.select(&refile::Selector::Id("abc".to_string())), vec![vec!["Inbox", "Fix server"]]);
+        assert_eq!(refile::Target::parse("::Home"), refile::Target { file: None, path: vec!["Home".to_string()] });
+        let rules = refile::parse_rules("# inbox rules\n+work -> work.org::Work/Inbox\n+home -> Home\n").unwrap();
+        assert!(refile::parse_rules("+work work.org").is_err());
+
+        let refiled = refile::apply_rules(&mut inbox, &rules, &mut files).unwrap();
+        assert_eq!(refiled.len(), 2);
+        assert_eq!(inbox.build(), vec!["* Inbox", "** Read book", "* Home", "** TODO Buy milk :home:"]);
+        assert_eq!(
+            files["work.org"].build(),
+            vec!["* Work", "** Inbox", "*** TODO Fix server :work:", ":PROPERTIES:", ":ID: abc", ":END:", "**** Logs"]
+        );
+        // Already in place, nothing moves on the second run
+        assert!(refile::apply_rules(&mut inbox, &rules, &mut files).unwrap().is_empty());
+
+        // The first matching rule wins, the heading is not moved on by the next one
+        let mut tasks = parse("* Inbox\n** TODO Task :work:urgent:\n* Work\n* Urgent");
+        let rules = refile::parse_rules("+work -> Work\n+urgent -> Urgent").unwrap();
+        let refiled = refile::apply_rules(&mut tasks, &rules, &mut files).unwrap();
+        assert_eq!(refiled.len(), 1);
+        assert_eq!(tasks.build(), vec!["* Inbox", "* Work", "** TODO Task :work:urgent:", "* Urgent"]);
+
+        // A target in the source file itself is a move inside it, not a second copy of the file
+        let dir = std::env::temp_dir().join(format!("org-parse-refile-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let path = dir.join("sub/inbox.org");
+        std::fs::write(&path, "* Inbox\n** TODO Call\n* Done\n").unwrap();
+        let source = path.to_string_lossy().to_string();
+        let target = refile::Target::parse("../sub/inbox.org::Done").local_to(&source);
+        assert_eq!(target, refile::Target { file: None, path: vec!["Done".to_string()] });
+        assert!(refile::Target::parse("other.org::Done").local_to(&source).file.is_some());
+        let mut file = structs::File::open(structs::FileContext::Todo, &source).unwrap();
+        let paths = file.select(&refile::Selector::Path(vec!["Inbox".to_string(), "Call".to_string()]));
+        refile::refile(&mut file, &paths, &target.path(), None).unwrap();
+        file.save(&source).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "* Inbox\n* Done\n** TODO Call\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn archiving() {
+        let parse = |text: &str| structs::File::parse(structs::FileContext::Todo, text.lines().map(|l| l.to_string()).collect());
+        let mut file = parse("#+ARCHIVE: %s_archive::* Archived\n* Projects :work:\n** DONE Website\nCLOSED: [2024-07-10 Wed 10:00]\n\n** TODO Other\n* Misc\n:PROPERTIES:\n:ARCHIVE: ::* Done here\n:CATEGORY: misc\n:END:\n** DONE Done here\n** DONE Thing\n* Done here\n** DONE Old");
+        let time = NaiveDate::from_ymd_opt(2024, 7, 15).unwrap().and_hms_opt(9, 30, 0).unwrap();
+        let archived = file.archive(&structs::TodoStates::DONE, "notes/todo.org", time);
+        // Only the heading at the path of the location stays, not everything with its title
+        assert_eq!(archived.len(), 3);
+        assert_eq!(archived[1].subtree.property("ARCHIVE_OLPATH"), Some("Misc"));
+        let archived: Vec<archive::Archived> = archived.into_iter().filter(|item| item.subtree.build()[0] != "** DONE Done here").collect();
+
+        let location = archive::ArchiveLocation::parse("%s_archive::* Archived", "notes/todo.org");
+        assert_eq!(archived[0].location, Some(location.clone()));
+        assert_eq!(location.file.as_deref(), Some("todo.org_archive"));
+        assert_eq!(
+            archived[0].subtree.build(),
+            vec![
+                "** DONE Website",
+                "CLOSED: [2024-07-10 Wed 10:00]",
+                ":PROPERTIES:",
+                ":ARCHIVE_TIME: 2024-07-15 Mon 09:30",
+                format!(":ARCHIVE_FILE: {}", std::path::absolute("notes/todo.org").unwrap().display()).as_str(),
+                ":ARCHIVE_OLPATH: Projects",
+                ":ARCHIVE_CATEGORY: todo",
+                ":ARCHIVE_TODO: DONE",
+                ":ARCHIVE_ITAGS: work",
+                ":END:",
+                "",
+            ]
+        );
+        assert_eq!(archived[1].subtree.property("ARCHIVE_CATEGORY"), Some("misc"));
+
+        let mut archive_file = archive::new_archive_file("notes/todo.org");
+        let mut archived = archived.into_iter();
+        let website = archived.next().unwrap();
+        archive_file.add_archived(&location, website.subtree).unwrap();
+        assert_eq!(archive_file.build()[3], "Archived entries from file notes/todo.org");
+        assert_eq!(archive_file.find_path(&["Archived", "Website"]).unwrap().object.level(), Some(2));
+
+        let thing = archived.next().unwrap();
+        let here = thing.location.clone().unwrap();
+        assert_eq!(here.file, None);
+        file.add_archived(&here, thing.subtree).unwrap();
+        assert!(file.find_path(&["Done here", "Thing"]).is_some());
+        assert!(file.find_path(&["Projects", "Website"]).is_none());
+    }
+
+    #[test]
+    fn restoring() {
+        let lines = "TIMESTAMP:1720000000000\n* DONE Old thing\nsome text\nTIMESTAMP:1721000000000\n* DONE Website\n:PROPERTIES:\n:ARCHIVE_TIME: 2024-07-15 Mon 09:30\n:ARCHIVE_FILE: todo.org\n:ARCHIVE_OLPATH: Projects/Web\n:ARCHIVE_TODO: DONE\n:END:\n** TODO Deploy\n** DONE Homage\n:PROPERTIES:\n:ARCHIVE_TIME: 2024-07-15 Mon 09:30\n:ARCHIVE_OLPATH: Lesen\n:END:"
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        let mut history = history::History::parse(lines).unwrap();
+        let items = history.items();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items.iter().map(|item| item.title()).collect::<Vec<_>>(), vec!["Old thing", "Website", "Homage"]);
+        assert_eq!(items[1].time, NaiveDate::from_ymd_opt(2024, 7, 15).unwrap().and_hms_opt(9, 30, 0));
+        assert!(items[0].time.is_some());
+        assert_eq!(items[1].outline_path(), vec!["Projects", "Web"]);
+        assert_eq!(items[1].source(), Some("todo.org"));
+        assert!(items[2].contains("homage") && items[1].contains("deploy") && !items[0].contains("deploy"));
+
+        let mut file = structs::File::parse(structs::FileContext::Todo, "* Projects\n** Other".lines().map(|l| l.to_string()).collect());
+        let website = history.take(1).unwrap();
+        assert_eq!(file.restore(website).unwrap(), vec!["Projects", "Web"]);
+        assert_eq!(file.build(), vec!["* Projects", "** Other", "** Web", "*** DONE Website", "**** TODO Deploy"]);
+        assert_eq!(
+            history.build(),
+            vec!["TIMESTAMP:1720000000000", "* DONE Old thing", "some text", "TIMESTAMP:1721000000000", "** DONE Homage", ":PROPERTIES:", ":ARCHIVE_TIME: 2024-07-15 Mon 09:30", ":ARCHIVE_OLPATH: Lesen", ":END:"]
+        );
+    }
+
+    #[test]
+    fn archive_policy() {
+        let lines = "* Projects\n** TODO Website\n*** DONE Draft\nCLOSED: [2024-07-01 Mon 10:00]\n** DONE Old\nCLOSED: [2024-07-01 Mon 10:00]\n** DONE Recent\nCLOSED: [2024-07-12 Fri 10:00]\n** DONE Fresh\n** DONE Noted\n:LOGBOOK:\n- State \"DONE\"       from \"TODO\"       [2024-07-10 Wed 10:00]\n:END:\n* Someday :noarchive:\n** DONE Keep\nCLOSED: [2024-07-01 Mon 10:00]"
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        let mut file = structs::File::parse(structs::FileContext::Todo, lines);
+        let time = NaiveDate::from_ymd_opt(2024, 7, 15).unwrap().and_hms_opt(9, 30, 0).unwrap();
+        let mut policy = archive::ArchivePolicy::new(&structs::TodoStates::DONE);
+        policy.add_delay("DONE=7").unwrap();
+        policy.keep_under_active = true;
+        assert!(policy.add_delay("DONE").is_err() && policy.add_delay("WAIT=3").is_err());
+        assert!(policy.add_delay("TODO=3").is_err() && policy.add_delay("NEXT=0").is_err() && policy.add_delay("LOOP=1").is_err());
+
+        let archived = file.archive_with_policy(&policy, "todo.org", time);
+        let titles: Vec<String> = archived.iter().map(|item| item.subtree.build()[0].clone()).collect();
+        assert_eq!(titles, vec!["** DONE Old"]);
+        assert!(file.find_path(&["Projects", "Website", "Draft"]).is_some());
+        assert!(file.find_path(&["Someday", "Keep"]).is_some());
+        // Without CLOSED the State note counts, without either the heading waits and archiving doesn't write CLOSED
+        assert!(file.find_path(&["Projects", "Noted"]).is_some());
+        assert_eq!(file.find_path(&["Projects", "Fresh"]).unwrap().object.build(), vec!["** DONE Fresh"]);
+
+        let later = NaiveDate::from_ymd_opt(2024, 7, 22).unwrap().and_hms_opt(9, 30, 0).unwrap();
+        let archived = file.archive_with_policy(&policy, "todo.org", later);
+        assert_eq!(archived.len(), 2);
+        policy.keep_under_active = false;
+        assert_eq!(file.archive_with_policy(&policy, "todo.org", later).len(), 1);
+    }
+
+    #[test]
+    fn history_store() {
+        let lines = "TIMESTAMP:1720000000000\n* DONE Old thing\n* [2024-07-15 Mon 09:30] todo.org\n:PROPERTIES:\n:RUN_TIME: 2024-07-15 Mon 09:30\n:RUN_ARCHIVED: 2\n:END:\n** DONE Website :web:work:\nCLOSED: [2024-07-08 Mon 10:00]\n:PROPERTIES:\n:ARCHIVE_ITAGS: work\n:END:\n** DONE Say \"hi\""
+            .lines()
+            .map(|l| l.to_string())
+            .collect::<Vec<String>>();
+        let history = history::History::parse(lines.clone()).unwrap();
+        assert_eq!(history.build(), lines);
+        let items = history.items();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[1].completed(), NaiveDate::from_ymd_opt(2024, 7, 8));
+        assert_eq!(items[2].completed(), NaiveDate::from_ymd_opt(2024, 7, 15));
+        assert_eq!(items[1].tags(), vec!["work", "web"]);
+
+        let july = history.completions(NaiveDate::from_ymd_opt(2024, 7, 5).unwrap(), NaiveDate::from_ymd_opt(2024, 7, 31).unwrap());
+        assert_eq!(july.len(), 2);
+        let weeks: Vec<(String, usize)> = history::group(&july, history::Grouping::Week).into_iter().map(|(week, items)| (week, items.len())).collect();
+        assert_eq!(weeks, vec![("2024-W28".to_string(), 1), ("2024-W29".to_string(), 1)]);
+        let tags: Vec<String> = history::group(&july, history::Grouping::Tag).into_iter().map(|(tag, _)| tag).collect();
+        assert_eq!(tags, vec!["web", "work"]);
+
+        // Restoring an item of a run leaves one less in its RUN_ARCHIVED
+        let mut history = history;
+        history.take(1).unwrap();
+        assert_eq!(history.build()[5], ":RUN_ARCHIVED: 1");
+        assert_eq!(history.items().len(), 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn history_jsonl() {
+        let lines = "* [2024-07-15 Mon 09:30] todo.org\n:PROPERTIES:\n:RUN_TIME: 2024-07-15 Mon 09:30\n:END:\n** DONE Website :web:\nCLOSED: [2024-07-08 Mon 10:00]\n** DONE Say \"hi\""
+            .lines()
+            .map(|l| l.to_string())
+            .collect::<Vec<String>>();
+        let history = history::History::parse(lines).unwrap();
+        let items = history.items();
+        let json: Vec<String> = items.iter().map(|item| item.json()).collect();
+        assert!(json[1].contains(r#""title":"Say \"hi\"""#));
+        let from_json = history::History::parse(json.clone()).unwrap();
+        assert_eq!(from_json.build(), json);
+        assert_eq!(from_json.items()[0].subtree.build(), items[0].subtree.build());
+        assert_eq!(from_json.items()[0].time, items[0].time);
+
+        // Characters outside the BMP are escaped as surrogate pairs by other writers
+        let escaped = history::History::parse(vec![r#"{"archived":"2024-07-15T09:30","lines":["* DONE Party \ud83c\udf89"]}"#.to_string()]).unwrap();
+        assert_eq!(escaped.items()[0].title(), "Party \u{1f389}");
+
+        // A broken line is an error instead of getting lost on the next save
+        let mut broken = json.clone();
+        broken.insert(1, "{\"archived\":".to_string());
+        assert!(history::History::parse(broken).unwrap_err().starts_with("line 2:"));
+        assert!(history::History::parse(vec![r#"{"title":"No lines"}"#.to_string()]).is_err());
+    }
+
+    #[cfg(not(feature = "serde"))]
+    #[test]
+    fn history_jsonl_without_serde() {
+        let json = vec![r#"{"archived":"2024-07-15T09:30","lines":["* DONE Party"]}"#.to_string()];
+        assert!(history::History::parse(json).is_err());
+        assert_eq!(history::History::open("history.jsonl").unwrap_err().kind(), std::io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn habits() {
+        let lines = "* TODO Spanish\nSCHEDULED: <2024-07-16 Tue .+1d/3d>\n:PROPERTIES:\n:STYLE: habit\n:LAST_REPEAT: [2024-07-15 Mon 08:00]\n:END:\n:LOGBOOK:\n- State \"DONE\"       from \"TODO\"       [2024-07-15 Mon 08:00]\n- State \"DONE\"       from \"TODO\"       [2024-07-14 Sun 08:00]\n- State \"DONE\"       from \"TODO\"       [2024-07-10 Wed 08:00]\n:END:\n* TODO Not a habit\nSCHEDULED: <2024-07-16 Tue +1d>"
+            .lines()
+            .map(|l| l.to_string())
+            .collect::<Vec<String>>();
+        let file = structs::File::parse(structs::FileContext::Todo, lines.clone());
+        assert_eq!(file.build(), lines);
+        let habits = file.habits();
+        assert_eq!(habits.len(), 1);
+        let habit = &habits[0];
+        assert_eq!(habit.repeater.deadline_days(), 3);
+        assert_eq!(habit.completions.len(), 3);
+
+        let today = NaiveDate::from_ymd_opt(2024, 7, 16).unwrap();
+        let stats = habit.stats(today);
+        assert_eq!((stats.current_streak, stats.longest_streak, stats.total), (2, 2, 3));
+        let graph = habit.graph(NaiveDate::from_ymd_opt(2024, 7, 10).unwrap(), NaiveDate::from_ymd_opt(2024, 7, 20).unwrap(), today);
+        assert_eq!(habit::ascii_graph(&graph, today), "*--=**!-=##");
+        assert!(habit::html_graph(&graph, today).contains("<span class=\"habit-due habit-today\" title=\"2024-07-16\">"));
+        assert_eq!(habit.stats(NaiveDate::from_ymd_opt(2024, 7, 30).unwrap()).current_streak, 0);
+    }
+
+    #[test]
+    fn html_export() {
+        let lines = "#+TITLE: Notes\n* TODO [#A] Plan :work:\nSome /text/ with [[*Plan][a link]].\n- [X] done\n  - nested\n- open\n\n| a | b |\n|---+---|\n| 1 | <2> |\n#+begin_src rust\n* not a heading\n#+end_src\n** Plan"
+            .lines()
+            .map(|l| l.to_string())
+            .collect::<Vec<String>>();
+        let file = structs::File::parse(structs::FileContext::Todo, lines.clone());
+        assert_eq!(file.build(), lines);
+        let elements = file.elements();
+        assert!(matches!(&elements[3], export::Element::List(list) if list.items[0].sublist.is_some() && list.items[0].checkbox == Some(true)));
+        assert!(matches!(&elements[4], export::Element::Table(rows) if rows.len() == 3));
+        assert!(matches!(&elements[5], export::Element::Block { name, lines, .. } if name == "src" && lines[0] == "* not a heading"));
+
+        let html = file.to_html(&html::HtmlOptions {
+            template: "<h1>{{title}}</h1>{{toc}}{{content}}".to_string(),
+            ..Default::default()
+        });
+        assert!(html.starts_with("<h1>Notes</h1><nav id=\"table-of-contents\">"));
+        assert!(html.contains("<h2 id=\"plan\"><span class=\"todo TODO\">TODO</span> <span class=\"priority\">[#A]</span> Plan <span class=\"tags\"><span class=\"tag work\">work</span></span></h2>"));
+        assert!(html.contains("Some <i>text</i> with <a href=\"#plan\">a link</a>."));
+        assert!(html.contains("<input type=\"checkbox\" class=\"on\" disabled checked> done"));
+        assert!(html.contains("<tr><th>a</th><th>b</th></tr>\n<tr><td>1</td><td>&lt;2&gt;</td></tr>"));
+        assert!(html.contains("<pre class=\"src src-rust\"><code class=\"language-rust\">* not a heading</code></pre>"));
+        assert!(html.contains("<h3 id=\"plan-2\">Plan</h3>"));
+
+        // Links to a heading with a CUSTOM_ID point to it, placeholders in the title are not replaced
+        let lines = "#+TITLE: All {{toc}}\n* Setup\n:PROPERTIES:\n:CUSTOM_ID: install\n:ID: 42\n:END:\n* Use\nSee [[*Setup]] and [[Setup][setup]].\n[[id:42][Again]]";
+        let file = structs::File::parse(structs::FileContext::Todo, lines.lines().map(|l| l.to_string()).collect());
+        let html = file.to_html(&html::HtmlOptions {
+            template: "<h1>{{title}}</h1>{{toc}}{{content}}".to_string(),
+            ..Default::default()
+        });
+        assert!(html.starts_with("<h1>All {{toc}}</h1><nav id=\"table-of-contents\">"));
+        assert!(html.contains("See <a href=\"#install\">*Setup</a> and <a href=\"#install\">setup</a>."));
+        // id: links find the heading by its ID too
+        assert!(html.contains("<a href=\"#install\">Again</a>"));
+        // Entities are escaped like any other text
+        assert_eq!(html::inline_html("a \\lt{}b\\gt{} \\amp \\alpha", &std::collections::HashMap::new()), "a &lt;b&gt; &amp; α");
+    }
+
+    #[test]
+    fn markdown_export() {
+        let lines = "* TODO [#B] Write *docs* :work:\nDEADLINE: <2024-07-20 Sat>\nSee [[https://example.com][the site]] and =a_b=.\n- [ ] first\n  - [X] nested\n** Table\n| name | value |\n|------+-------|\n| x    | 1     |\n#+begin_src sh\necho hi\n#+end_src"
+            .lines()
+            .map(|l| l.to_string())
+            .collect::<Vec<String>>();
+        let file = structs::File::parse(structs::FileContext::Todo, lines);
+        let expected = "# **TODO** **[#B]** Write **docs** `work`\n\n*DEADLINE:* `<2024-07-20 Sat>`\n\nSee [the site](https://example.com) and `a_b`.\n\n- [ ] first\n  - [x] nested\n\n## Table\n\n| name | value |\n| --- | --- |\n| x | 1 |\n\n```sh\necho hi\n```\n";
+        assert_eq!(file.to_markdown(&markdown::MarkdownOptions::default()), expected);
+
+        let badges = markdown::MarkdownOptions {
+            todo_style: markdown::TodoStyle::Badge,
+            planning: false,
+        };
+        let markdown = file.to_markdown(&badges);
+        assert!(markdown.starts_with("# ![TODO](https://img.shields.io/badge/-TODO-red) ![B]("));
+        assert!(!markdown.contains("DEADLINE"));
+    }
+
+    #[test]
+    fn markdown_import() {
+        let text = "Notes from **them**\n\n## **TODO** Release\n- [ ] write `cargo publish`\n  - [x] bump [version](https://semver.org)\n\nTeam\n====\n| a | b |\n|---|---|\n| 1 | 2 |\n```rust\n* not a heading\n```";
+        let imported = structs::File::from_markdown(text);
+        let expected = vec![
+            "Notes from *them*",
+            "",
+            "** TODO Release",
+            "- [ ] write ~cargo publish~",
+            "  - [X] bump [[https://semver.org][version]]",
+            "",
+            "* Team",
+            "| a | b |",
+            "|---+---|",
+            "| 1 | 2 |",
+            "#+begin_src rust",
+            "* not a heading",
+            "#+end_src",
+        ];
+        assert_eq!(imported.build(), expected);
+        assert!(matches!(imported.children[1].object_type(), object_types::ObjectTypes::Heading { todo: Some(structs::TodoStates::TODO), .. }));
+
+        let mut file = structs::File::parse(structs::FileContext::Todo, vec!["* Inbox".to_string()]);
+        file.merge(&["Inbox"], imported, "tasks.md").unwrap();
+        let built = file.build();
+        assert_eq!(&built[..4], ["* Inbox", "** tasks.md", "Notes from *them*", ""]);
+        assert!(built.contains(&"** TODO Release".to_string()));
+        assert!(built.contains(&"** Team".to_string()));
+        assert!(file.to_markdown(&markdown::MarkdownOptions::default()).contains("- [ ] write `cargo publish`\n  - [x] bump [version](https://semver.org)"));
+
+        // Escaped characters are no markup
+        assert_eq!(markdown::inline_to_org(r"\[x\](y) but [a \] b](c)"), "[x](y) but [[c][a ] b]]");
+    }
+
+    #[test]
+    fn terminal_render() {
+        let lines = vec![
+            "* TODO [#A] Release :work:",
+            "DEADLINE: <2024-07-12 Fri>",
+            ":LOGBOOK:",
+            "- State \"DONE\" from \"TODO\" [2024-07-01 Mon]",
+            ":END:",
+            "Some text",
+            "- item",
+            "  - nested",
+            "** NEXT Notes",
+            "Body",
+            "*** Deep",
+            "* DONE Old",
+            "DEADLINE: <2024-01-01 Mon>",
+        ];
+        let file = structs::File::parse(structs::FileContext::Todo, lines.iter().map(|line| line.to_string()).collect());
+        let mut options = terminal::TerminalOptions {
+            today: NaiveDate::from_ymd_opt(2024, 7, 15).unwrap(),
+            depth: Some(2),
+            ..Default::default()
+        };
+        let expected = [
+            "* TODO [#A] Release :work:",
+            "  DEADLINE: <2024-07-12 Fri> (3 d. ago)",
+            "  Some text",
+            "  - item",
+            "    - nested",
+            "  * NEXT Notes ...",
+            "* DONE Old",
+            "  DEADLINE: <2024-01-01 Mon>",
+        ];
+        assert_eq!(file.render_terminal(&options), expected.join("\n") + "\n");
+        options.depth = None;
+        assert!(file.render_terminal(&options).contains("  * NEXT Notes\n    Body\n    * Deep\n"));
+        let subtree = file.children[0].children().iter().find(|child| child.level() == Some(2)).unwrap();
+        assert_eq!(subtree.render_terminal(&options), "* NEXT Notes\n  Body\n  * Deep\n");
+
+        options.style = terminal::Style { color: true };
+        let colored = file.render_terminal(&options);
+        assert!(colored.contains("\x1b[1m\x1b[31mDEADLINE: <2024-07-12 Fri> (3 d. ago)\x1b[0m"));
+        assert_eq!(terminal::strip_colors(&colored), file.render_terminal(&terminal::TerminalOptions { style: terminal::Style::default(), ..options }));
+    }
+
+    #[test]
+    fn latex_export() {
+        let lines = vec![
+            "#+TITLE: Report",
+            "#+LATEX_CLASS: report",
+            "#+LATEX_HEADER: \\usepackage{booktabs}",
+            "* TODO [#A] Results :work:",
+            "We grew by 5% thanks to *hard* work_and luck, see [[https://example.com/a#b][the site]] and \\(x^2\\).[fn:1]",
+            "#+LATEX: \\clearpage",
+            "- [X] done",
+            "  1. nested",
+            "- term :: meaning",
+            "| a | b |",
+            "|---+---|",
+            "| 1 | 2 |",
+            "#+begin_src rust",
+            "fn main() {}",
+            "#+end_src",
+            "** Details",
+            ":PROPERTIES:",
+            ":CUSTOM_ID: more",
+            ":ID: details-id",
+            ":END:",
+            "See [[*Details]] or [[id:details-id][this]] at the caf\\eacute{} for \\alpha and \\Alpha.",
+            "[fn:1] A footnote.",
+        ];
+        let file = structs::File::parse(structs::FileContext::Todo, lines.iter().map(|line| line.to_string()).collect());
+        let latex = file.to_latex(&latex::LatexOptions::default());
+        assert!(latex.starts_with("\\documentclass{report}\n"));
+        assert!(latex.contains("\\usepackage{booktabs}\n\\author{}\n\\date{\\today}\n\\title{Report}\n\\begin{document}\n\n\\maketitle\n\\setcounter{tocdepth}{3}"));
+        assert!(latex.contains("\\chapter{\\textbf{TODO} \\framebox{\\#A} Results \\hfill{}\\textsc{work}}\n\\label{results}"));
+        assert!(latex.contains("5\\% thanks to \\textbf{hard} work\\_and luck, see \\href{https://example.com/a\\#b}{the site} and \\(x^2\\).\\footnote{A footnote.}"));
+        assert!(latex.contains("\n\n\\clearpage\n\n"));
+        assert!(latex.contains("\\begin{itemize}\n\\item[{$\\boxtimes$}] done\n\\begin{enumerate}\n\\item nested\n\\end{enumerate}\n\\item[{term}] meaning\n\\end{itemize}"));
+        assert!(latex.contains("\\begin{tabular}{ll}\na & b \\\\\n\\hline\n1 & 2 \\\\\n\\end{tabular}"));
+        assert!(latex.contains("\\begin{verbatim}\nfn main() {}\n\\end{verbatim}"));
+        assert!(latex.contains("\\section{Details}\n\\label{more}"));
+        // Links point to the label of the heading, text entities are text and math ones math
+        assert!(latex.contains("See \\hyperref[more]{*Details} or \\hyperref[more]{this} at the café for \\(\\alpha\\) and ."));
+        assert!(latex.ends_with("\\end{document}\n"));
+
+        let options = latex::LatexOptions {
+            minted: true,
+            ..Default::default()
+        };
+        let minted = file.to_latex(&options);
+        assert!(minted.contains("\\usepackage{minted}"));
+        assert!(minted.contains("\\begin{minted}{rust}\nfn main() {}\n\\end{minted}"));
+    }
+
+    #[test]
+    fn icalendar_export() {
+        let lines = vec![
+            "* TODO [#A] Release :work:",
+            "DEADLINE: <2024-07-12 Fri 10:00 +1w -2d> SCHEDULED: <2024-07-08 Mon>",
+            ":PROPERTIES:",
+            ":ID: release",
+            ":LOCATION: Room 4, upstairs",
+            ":END:",
+            "Call with the team <2024-07-09 Tue 14:00-15:00>",
+            "** DONE Tag the release and write a very long announcement for everyone on the mailing list",
+            "CLOSED: [2024-07-10 Wed 09:15] DEADLINE: <2024-07-10 Wed>",
+            "* Birthday",
+            "<2024-08-01 Thu +1y>",
+        ];
+        let file = structs::File::parse(structs::FileContext::Todo, lines.iter().map(|line| line.to_string()).collect());
+        let now = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap().and_hms_opt(8, 0, 0).unwrap();
+        // The times of the file are two hours ahead of UTC
+        let zone = chrono::FixedOffset::east_opt(2 * 3600).unwrap();
+        let ics = file.to_icalendar("work.org", now, &zone);
+        assert_eq!(ics, file.to_icalendar("work.org", now, &zone));
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.contains("UID:DL-release\r\nDTSTAMP:20240701T080000Z\r\nSUMMARY:DL: Release\r\nCATEGORIES:work,work\r\nLOCATION:Room 4\\, upstairs\r\nDTSTART:20240712T100000\r\nRRULE:FREQ=WEEKLY\r\nBEGIN:VALARM"));
+        assert!(ics.contains("TRIGGER:-P2D"));
+        assert!(ics.contains("UID:SC-release\r\n"));
+        assert!(ics.contains("DTSTART:20240709T140000\r\nDTEND:20240709T150000"));
+        assert!(ics.contains("UID:TODO-release\r\n"));
+        assert!(ics.contains("STATUS:NEEDS-ACTION\r\nPRIORITY:1"));
+        assert!(ics.contains("STATUS:COMPLETED\r\nCOMPLETED:20240710T071500Z"));
+        assert!(!ics.contains("SUMMARY:DL: Tag"));
+        assert!(ics.contains("DTSTART;VALUE=DATE:20240801\r\nDTEND;VALUE=DATE:20240802\r\nRRULE:FREQ=YEARLY"));
+        assert!(ics.contains("\r\n the mailing list"));
+        assert!(ics.split("\r\n").all(|line| line.len() <= 75));
+    }
+
+    #[test]
+    fn icalendar_import() {
+        let ics = [
+            "BEGIN:VCALENDAR",
+            "VERSION:2.0",
+            "BEGIN:VTIMEZONE",
+            "TZID:Europe/Berlin",
+            "BEGIN:DAYLIGHT",
+            "TZOFFSETTO:+0200",
+            "DTSTART:19700329T020000",
+            "RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU",
+            "END:DAYLIGHT",
+            "BEGIN:STANDARD",
+            "TZOFFSETTO:+0100",
+            "DTSTART:19701025T030000",
+            "RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU",
+            "END:STANDARD",
+            "END:VTIMEZONE",
+            "BEGIN:VEVENT",
+            "UID:sync@example.com",
+            "SUMMARY:Team sync\\, weekly",
+            "DTSTART;TZID=Europe/Berlin:20240709T140000",
+            "DTEND;TZID=Europe/Berlin:20240709T150000",
+            "RRULE:FREQ=WEEKLY;BYDAY=TU",
+            "LOCATION:Room 4",
+            "CATEGORIES:work",
+            "DESCRIPTION:Agenda:\\n* numbers",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "UID:call@example.com",
+            "SUMMARY:Winter call",
+            "DTSTART;TZID=Europe/Berlin:20240109T140000",
+            "DURATION:PT30M",
+            "RRULE:FREQ=MONTHLY;COUNT=3",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "UID:trip@example.com",
+            "SUMMARY:Trip",
+            "DTSTART;VALUE=DATE:20240801",
+            "DTEND;VALUE=DATE:20240804",
+            "END:VEVENT",
+            "BEGIN:VTODO",
+            "UID:report@example.com",
+            "SUMMARY:Write a report that is long enough to be folded by the calendar that wrote it",
+            "DUE:20240712T100000Z",
+            "PRIORITY:1",
+            "STATUS:IN-PROCESS",
+            "BEGIN:VALARM",
+            "TRIGGER:-P2D",
+            "END:VALARM",
+            "END:VTODO",
+            "END:VCALENDAR",
+        ]
+        .join("\r\n");
+        let folded = ics.replace("to be folded", "to be\r\n  folded");
+        let imported = structs::File::from_icalendar(&folded, &chrono::Utc).unwrap();
+        let expected = vec![
+            "* Team sync, weekly :work:",
+            ":PROPERTIES:",
+            ":UID: sync@example.com",
+            ":LOCATION: Room 4",
+            ":END:",
+            "<2024-07-09 Tue 12:00-13:00 +1w>",
+            "Agenda:",
+            ",* numbers",
+            "* Winter call",
+            ":PROPERTIES:",
+            ":UID: call@example.com",
+            ":RRULE: FREQ=MONTHLY;COUNT=3",
+            ":END:",
+            "<2024-01-09 Tue 13:00-13:30>",
+            "* Trip",
+            ":PROPERTIES:",
+            ":UID: trip@example.com",
+            ":END:",
+            "<2024-08-01 Thu>--<2024-08-03 Sat>",
+            "* NEXT [#A] Write a report that is long enough to be folded by the calendar that wrote it",
+            "DEADLINE: <2024-07-12 Fri 10:00 -2d>",
+            ":PROPERTIES:",
+            ":UID: report@example.com",
+            ":END:",
+        ];
+        assert_eq!(imported.build(), expected);
+        assert!(structs::File::from_icalendar("BEGIN:VCALENDAR\nBEGIN:VEVENT\nEND:VCALENDAR", &chrono::Utc).is_err());
+
+        let lines = vec!["* Calendar", "** Old sync", ":PROPERTIES:", ":UID: sync@example.com", ":END:", "*** My notes", "* Trip", ":PROPERTIES:", ":UID: trip@example.com", ":END:"];
+        let mut file = structs::File::parse(structs::FileContext::Todo, lines.iter().map(|line| line.to_string()).collect());
+        file.merge_icalendar(&["Calendar"], imported).unwrap();
+        let again = structs::File::from_icalendar(&ics, &chrono::Utc).unwrap();
+        file.merge_icalendar(&["Calendar"], again).unwrap();
+        let built = file.build();
+        assert_eq!(&built[..3], ["* Calendar", "** Team sync, weekly :work:", ":PROPERTIES:"]);
+        assert!(built.contains(&"*** My notes".to_string()));
+        assert_eq!(built.iter().filter(|line| line.starts_with(":UID:")).count(), 4);
+        assert!(built.contains(&"* Trip".to_string()));
+
+        let cancel = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nUID:trip@example.com\nSUMMARY:Trip\nSTATUS:CANCELLED\nEND:VEVENT\nEND:VCALENDAR";
+        file.merge_icalendar(&["Calendar"], structs::File::from_icalendar(cancel, &chrono::Utc).unwrap()).unwrap();
+        assert!(!file.build().iter().any(|line| line.contains("trip@example.com")));
+    }
+
+    #[test]
+    fn todo_txt_roundtrip() {
+        let lines = vec![
+            "* Website Redesign",
+            "** TODO [#A] Call the designer :phone:",
+            "DEADLINE: <2024-07-12 Fri +1w> SCHEDULED: <2024-07-08 Mon>",
+            ":PROPERTIES:",
+            ":ID: designer",
+            ":END:",
+            "Notes about the call",
+            "** DONE Buy the domain",
+            "CLOSED: [2024-07-01 Mon]",
+            "** Someday",
+            "* NEXT Water the plants",
+        ];
+        let mut file = structs::File::parse(structs::FileContext::Todo, lines.iter().map(|line| line.to_string()).collect());
+        let todo_txt = file.to_todo_txt();
+        assert_eq!(
+            todo_txt,
+            "(A) Call the designer +Website-Redesign @phone due:2024-07-12 t:2024-07-08 id:designer\n\
+             x 2024-07-01 Buy the domain +Website-Redesign\n\
+             Water the plants state:NEXT\n"
+        );
+        let task = tasks::Task::parse_todo_txt("x 2024-07-10 2024-07-01 Read https://example.com pri:D +Reading @home").unwrap();
+        assert_eq!(task.todo, structs::TodoStates::DONE);
+        assert_eq!(task.priority, Some(structs::Priority::C));
+        assert_eq!(task.description, "Read https://example.com");
+        assert_eq!(task.closed.unwrap().date(), NaiveDate::from_ymd_opt(2024, 7, 10).unwrap());
+        assert!(tasks::Task::parse_todo_txt("   ").is_none());
+
+        let phone = "x 2024-07-11 Call the designer +Website-Redesign @phone due:2024-07-12 id:designer\n\
+                     (B) Send the invoice +Website-Redesign due:2024-07-20\n\
+                     Water the plants state:NEXT\n\
+                     Buy the domain +Website-Redesign\n";
+        file.merge_tasks(&[], structs::File::from_todo_txt(phone)).unwrap();
+        let expected = vec![
+            "* Website Redesign",
+            "** DONE Call the designer :phone:",
+            "DEADLINE: <2024-07-12 Fri +1w> SCHEDULED: <2024-07-08 Mon> CLOSED: [2024-07-11 Thu]",
+            ":PROPERTIES:",
+            ":ID: designer",
+            ":END:",
+            "Notes about the call",
+            "** DONE Buy the domain",
+            "CLOSED: [2024-07-01 Mon]",
+            "** Someday",
+            "** TODO [#B] Send the invoice",
+            "DEADLINE: <2024-07-20 Sat>",
+            "** TODO Buy the domain",
+            "* NEXT Water the plants",
+        ];
+        assert_eq!(file.build(), expected);
+    }
+
+    #[test]
+    fn outline_export() {
+        let lines = [
+            "#+TITLE: Launch",
+            "* Plan",
+            "** TODO [#A] Design & review :ux:",
+            "See [[*Build]] first.",
+            "** DONE Build",
+            "CLOSED: [2024-07-01 Mon]",
+            "*** NEXT Tests",
+            "* Notes",
+        ];
+        let file = structs::File::parse(structs::FileContext::Todo, lines.iter().map(|line| line.to_string()).collect());
+        let mut options = outline::OutlineOptions::default();
+        let opml = file.to_opml("launch.org", &options);
+        assert!(opml.contains("<title>Launch</title>"));
+        assert!(opml.contains("<outline text=\"TODO [#A] Design &amp; review\" _note=\"See [[*Build]] first.\" category=\"ux\"/>"));
+        assert!(opml.contains("<outline text=\"DONE Build\" _complete=\"true\">"));
+        assert_eq!(
+            file.to_mermaid("launch.org", &options),
+            "mindmap\n  root((\"Launch\"))\n    n0[\"Plan\"]\n      n1[\"TODO [#A] Design & review\"]\n      n2[\"DONE Build\"]\n        n3[\"NEXT Tests\"]\n    n4[\"Notes\"]\n"
+        );
+        options.colors = true;
+        options.links = true;
+        let dot = file.to_dot("launch.org", &options);
+        assert!(dot.contains("  n1 [label=\"TODO [#A] Design & review\", fillcolor=\"#f4cccc\", tooltip=\"ux\"];"));
+        assert!(dot.contains("  n2 -> n3;"));
+        assert!(dot.contains("  n1 -> n2 [style=dashed, constraint=false];"));
+        let mermaid = file.to_mermaid("launch.org", &options);
+        assert!(mermaid.starts_with("flowchart LR"));
+        assert!(mermaid.contains("  n1 -.-> n2") && mermaid.contains("  class n3 next"));
+        options.depth = Some(1);
+        assert!(!file.to_dot("launch.org", &options).contains("n2"));
+
+        // Links start at the heading they are in, even when another heading has the same title
+        let lines = ["* Week 1", ":PROPERTIES:", ":CUSTOM_ID: w1", ":END:", "** Notes", "* Week 2", "** Notes", "See [[#w1]]."];
+        let file = structs::File::parse(structs::FileContext::Todo, lines.iter().map(|line| line.to_string()).collect());
+        let dot = file.to_dot("weeks.org", &outline::OutlineOptions { links: true, ..Default::default() });
+        assert!(dot.contains("  n3 -> n0 [style=dashed, constraint=false];"));
+        assert!(!dot.contains("  n1 -> n0 [style=dashed"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn taskwarrior_roundtrip() {
+        let lines = vec![
+            "* Home",
+            "** NEXT [#B] Fix the bike :errand:",
+            "DEADLINE: <2024-07-12 Fri 18:30>",
+            ":PROPERTIES:",
+            ":ID: 5f0c9e1e-3b1a-4a0e-9d55-0c2b3f0b6a11",
+            ":END:",
+        ];
+        let file = structs::File::parse(structs::FileContext::Todo, lines.iter().map(|line| line.to_string()).collect());
+        let json = file.to_taskwarrior(&chrono::Utc);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value[0]["uuid"], "5f0c9e1e-3b1a-4a0e-9d55-0c2b3f0b6a11");
+        assert_eq!(value[0]["priority"], "M");
+        assert_eq!(value[0]["project"], "Home");
+        assert_eq!(value[0]["tags"], serde_json::json!(["errand", "next"]));
+        assert_eq!(value[0]["due"], "20240712T183000Z");
+        let imported = structs::File::from_taskwarrior(&json, &chrono::Utc).unwrap();
+        assert_eq!(imported.build(), lines);
+        let deleted = "{\"description\": \"Gone\", \"status\": \"deleted\"}";
+        assert!(structs::File::from_taskwarrior(deleted, &chrono::Utc).unwrap().children.is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_roundtrip() {
+        let lines = vec![
+            "* TODO [#A] Release :work:",
+            "DEADLINE: <2024-07-12 Fri 10:00 +1w -2d>",
+            ":PROPERTIES:",
+            ":ID: release",
+            ":END:",
+            "- [ ] write notes",
+            "",
+            "** DONE Tag",
+            "CLOSED: [2024-07-10 Wed]",
+        ];
+        let file = structs::File::parse(structs::FileContext::Todo, lines.iter().map(|line| line.to_string()).collect());
+        let json = file.to_json();
+        assert!(json.contains("\"type\": \"Heading\""));
+        let imported = structs::File::from_json(&json).unwrap();
+        assert_eq!(imported.build(), file.build());
+        let object_types::ObjectTypes::Heading { deadline, priority, .. } = imported.children[0].object_type() else {
+            panic!("not a heading");
+        };
+        assert_eq!(deadline.as_ref().map(|deadline| deadline.date()), NaiveDate::from_ymd_opt(2024, 7, 12));
+        assert_eq!(priority, &Some(structs::Priority::A));
+        assert!(structs::File::from_json("{\"version\": 2, \"children\": []}").is_err());
+        assert!(serde_json::from_str::<serde_json::Value>(json::SCHEMA).is_ok());
+    }
 }
@@ -0,0 +1,412 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Component, Path, PathBuf},
+    sync::LazyLock,
+};
+
+use regex::Regex;
+
+use crate::{
+    inline::{self, InlineObject},
+    object::Object,
+    object_types::ObjectTypes,
+    structs,
+};
+
+static RE_PROTOCOL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^([A-Za-z][\w+-]*):(.*)$").unwrap());
+static RE_NAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*#\+(?i:name):\s*(.+?)\s*$").unwrap());
+
+/// What kind of location a link points to
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkType {
+    /// file:path or file:path::search, where search can be *heading, #custom-id or a target
+    File { path: String, search: Option<String> },
+    /// id:..., pointing to a heading with that :ID: property in any file
+    Id(String),
+    /// http, https, mailto, ... everything with a protocol that can't be checked locally
+    External { protocol: String, path: String },
+    /// [[*Heading]] in the same file
+    Heading(String),
+    /// [[#custom-id]] in the same file
+    CustomId(String),
+    /// [[target]], pointing to a <<target>>, a #+NAME or a heading with that title in the same file
+    Fuzzy(String),
+}
+
+/// A link found in an org file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    /// The target after #+LINK abbreviations where expanded
+    pub target: String,
+    pub link_type: LinkType,
+    pub description: Option<String>,
+}
+
+impl Link {
+    /// Creates a Link from the target and description of a [[target][description]]
+    /// abbreviations are the #+LINK: definitions of the file, as name -> replacement
+    pub fn parse(target: &str, description: Option<String>, abbreviations: &HashMap<String, String>) -> Link {
+        let target = expand_abbreviation(target.trim(), abbreviations);
+        let link_type = if let Some(path) = target.strip_prefix("file:") {
+            file_link(path)
+        } else if let Some(id) = target.strip_prefix("id:") {
+            LinkType::Id(id.trim().to_string())
+        } else if let Some(heading) = target.strip_prefix('*') {
+            LinkType::Heading(heading.trim().to_string())
+        } else if let Some(custom_id) = target.strip_prefix('#') {
+            LinkType::CustomId(custom_id.trim().to_string())
+        } else if ["/", "./", "../", "~/"].iter().any(|prefix| target.starts_with(prefix)) {
+            file_link(&target)
+        } else if let Some(caps) = RE_PROTOCOL.captures(&target) {
+            LinkType::External {
+                protocol: caps[1].to_string(),
+                path: caps[2].to_string(),
+            }
+        } else {
+            LinkType::Fuzzy(target.clone())
+        };
+        Link {
+            target,
+            link_type,
+            description,
+        }
+    }
+
+    /// Creates the org String representation of the link
+    pub fn build(&self) -> String {
+        match &self.description {
+            Some(description) => format!("[[{}][{}]]", self.target, description),
+            None => format!("[[{}]]", self.target),
+        }
+    }
+}
+
+fn file_link(path: &str) -> LinkType {
+    match path.split_once("::") {
+        Some((path, search)) => LinkType::File {
+            path: path.to_string(),
+            search: Some(search.to_string()),
+        },
+        None => LinkType::File {
+            path: path.to_string(),
+            search: None,
+        },
+    }
+}
+
+/// Replaces abbreviations defined with #+LINK: name replacement
+/// %s in the replacement is substituted with the rest of the link, %h with it URL-encoded, otherwise the rest is appended
+fn expand_abbreviation(target: &str, abbreviations: &HashMap<String, String>) -> String {
+    let (name, rest) = match target.split_once(':') {
+        Some((name, rest)) => (name, rest),
+        None => (target, ""),
+    };
+    match abbreviations.get(name) {
+        Some(replacement) if replacement.contains("%s") => replacement.replace("%s", rest),
+        Some(replacement) if replacement.contains("%h") => replacement.replace("%h", &hexify(rest)),
+        Some(replacement) => format!("{replacement}{rest}"),
+        None => target.to_string(),
+    }
+}
+
+/// Percent-encodes everything but letters, digits and -_.~ like url-hexify-string, which org uses for %h
+fn hexify(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// The #+LINK: abbreviations of a file
+pub fn abbreviations(file: &structs::File) -> HashMap<String, String> {
+    file.keyword("LINK")
+        .into_iter()
+        .filter_map(|value| {
+            let (name, replacement) = value.split_once(char::is_whitespace)?;
+            Some((name.to_string(), replacement.trim().to_string()))
+        })
+        .collect()
+}
+
+/// The title of a heading, as links reference it: without tags and statistic cookies
+pub fn heading_title(text: &str) -> String {
+    let re_tags = Regex::new(r"\s+:[\w@#%:]+:\s*$").unwrap();
+    let re_cookie = Regex::new(r"\s*\[\d*(/\d*|%)\]").unwrap();
+    let text = re_tags.replace(text, "");
+    re_cookie.replace_all(&text, "").trim().to_string()
+}
+
+/// A place links can come from and point to: a file, or a heading inside of it
+/// Headings are told apart by their outline path, so two headings with the same title are different nodes
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LinkNode {
+    pub file: String,
+    /// The titles from the top down to the heading, empty for the file itself
+    pub path: Vec<String>,
+}
+
+impl fmt::Display for LinkNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.path.is_empty() {
+            true => write!(f, "{}", self.file),
+            false => write!(f, "{}::*{}", self.file, self.path.join("/")),
+        }
+    }
+}
+
+/// Where a link ended up pointing to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    Resolved(LinkNode),
+    /// Links with a protocol like https, which are not checked
+    External,
+    Broken,
+}
+
+/// A link from the place it was written to what it resolved to
+#[derive(Debug, Clone)]
+pub struct LinkEdge {
+    pub source: LinkNode,
+    pub link: Link,
+    pub resolution: Resolution,
+}
+
+/// Everything inside one file a link could point to
+#[derive(Debug, Default)]
+struct Anchors {
+    /// Outline paths of all headings, in the order of the file
+    headings: Vec<Vec<String>>,
+    custom_ids: HashMap<String, Vec<String>>,
+    /// <<targets>>, <<<radio targets>>> and #+NAME: with the path of the heading they are under
+    targets: HashMap<String, Vec<String>>,
+}
+
+/// All links of a set of org files, with the information where they point to
+#[derive(Debug, Default)]
+pub struct LinkGraph {
+    edges: Vec<LinkEdge>,
+}
+
+impl LinkGraph {
+    /// Resolves all links of the given files, each file is given with the path it was read from
+    /// id: links are resolved across all files, file: links relative to the file they are written in
+    pub fn build(files: &[(String, &structs::File)]) -> LinkGraph {
+        let mut anchors: HashMap<PathBuf, (String, Anchors)> = HashMap::new();
+        let mut ids: HashMap<String, LinkNode> = HashMap::new();
+        let mut links: Vec<(LinkNode, Link)> = Vec::new();
+        for (path, file) in files {
+            let abbreviations = abbreviations(file);
+            let mut file_anchors = Anchors::default();
+            for child in &file.children {
+                collect(child, path, &[], &abbreviations, &mut file_anchors, &mut ids, &mut links);
+            }
+            anchors.insert(normalize(Path::new(path)), (path.clone(), file_anchors));
+        }
+        let edges = links
+            .into_iter()
+            .map(|(source, link)| {
+                let resolution = resolve(&source, &link, &anchors, &ids);
+                LinkEdge {
+                    source,
+                    link,
+                    resolution,
+                }
+            })
+            .collect();
+        LinkGraph { edges }
+    }
+
+    /// All links in the graph
+    pub fn edges(&self) -> &Vec<LinkEdge> {
+        &self.edges
+    }
+
+    /// Links written in the given file or heading
+    pub fn links_from(&self, node: &LinkNode) -> Vec<&LinkEdge> {
+        self.edges.iter().filter(|edge| &edge.source == node).collect()
+    }
+
+    /// Links pointing to the given file or heading
+    /// Asking for a file without path also returns the links pointing to its headings
+    pub fn backlinks(&self, node: &LinkNode) -> Vec<&LinkEdge> {
+        self.edges
+            .iter()
+            .filter(|edge| match &edge.resolution {
+                Resolution::Resolved(target) => {
+                    target == node || (node.path.is_empty() && target.file == node.file)
+                }
+                _ => false,
+            })
+            .collect()
+    }
+
+    /// Links that point to a file, heading, id or target that does not exist
+    pub fn broken_links(&self) -> Vec<&LinkEdge> {
+        self.edges
+            .iter()
+            .filter(|edge| edge.resolution == Resolution::Broken)
+            .collect()
+    }
+}
+
+fn collect(
+    obj: &Object,
+    path: &str,
+    outline: &[String],
+    abbreviations: &HashMap<String, String>,
+    anchors: &mut Anchors,
+    ids: &mut HashMap<String, LinkNode>,
+    links: &mut Vec<(LinkNode, Link)>,
+) {
+    let mut current = outline.to_vec();
+    if let ObjectTypes::Heading { text, .. } = obj.object_type() {
+        current.push(heading_title(text));
+        anchors.headings.push(current.clone());
+        if let Some(id) = obj.property("ID") {
+            ids.insert(
+                id.to_string(),
+                LinkNode {
+                    file: path.to_string(),
+                    path: current.clone(),
+                },
+            );
+        }
+        if let Some(custom_id) = obj.property("CUSTOM_ID") {
+            anchors.custom_ids.insert(custom_id.to_string(), current.clone());
+        }
+    }
+    if let ObjectTypes::Text { text } = obj.object_type() {
+        if let Some(caps) = RE_NAME.captures(text) {
+            anchors.targets.insert(caps[1].to_string(), current.clone());
+        }
+    }
+    let source = LinkNode {
+        file: path.to_string(),
+        path: current.clone(),
+    };
+    collect_inline(&obj.object_type().inline_objects(), &source, abbreviations, anchors, links);
+    for child in obj.children() {
+        collect(child, path, &current, abbreviations, anchors, ids, links);
+    }
+}
+
+fn collect_inline(
+    objects: &[InlineObject],
+    source: &LinkNode,
+    abbreviations: &HashMap<String, String>,
+    anchors: &mut Anchors,
+    links: &mut Vec<(LinkNode, Link)>,
+) {
+    for object in objects {
+        match object {
+            InlineObject::Link { target, description } => {
+                let description = description.as_ref().map(|d| inline::build_inline(d));
+                links.push((source.clone(), Link::parse(target, description, abbreviations)));
+            }
+            InlineObject::Target(target) | InlineObject::RadioTarget(target) => {
+                anchors.targets.insert(target.clone(), source.path.clone());
+            }
+            _ => (),
+        }
+        if let Some(children) = object.children() {
+            collect_inline(children, source, abbreviations, anchors, links);
+        }
+    }
+}
+
+fn resolve(
+    source: &LinkNode,
+    link: &Link,
+    anchors: &HashMap<PathBuf, (String, Anchors)>,
+    ids: &HashMap<String, LinkNode>,
+) -> Resolution {
+    let own_file = normalize(Path::new(&source.file));
+    match &link.link_type {
+        LinkType::External { .. } => Resolution::External,
+        LinkType::Id(id) => match ids.get(id) {
+            Some(node) => Resolution::Resolved(node.clone()),
+            None => Resolution::Broken,
+        },
+        LinkType::Heading(_) | LinkType::CustomId(_) | LinkType::Fuzzy(_) => match anchors.get(&own_file) {
+            Some((file, file_anchors)) => search(file, file_anchors, &link.target),
+            None => Resolution::Broken,
+        },
+        LinkType::File { path, search: query } => {
+            let base = Path::new(&source.file).parent().unwrap_or(Path::new(""));
+            let target = normalize(&base.join(expand_home(path)));
+            match (anchors.get(&target), query) {
+                (Some((file, file_anchors)), Some(query)) => search(file, file_anchors, query),
+                (Some((file, _)), None) => Resolution::Resolved(LinkNode {
+                    file: file.clone(),
+                    path: Vec::new(),
+                }),
+                (None, _) if target.exists() => Resolution::Resolved(LinkNode {
+                    file: target.to_string_lossy().to_string(),
+                    path: Vec::new(),
+                }),
+                (None, _) => Resolution::Broken,
+            }
+        }
+    }
+}
+
+/// Searches a file for *heading, #custom-id, a line number or a target/heading title, like org does with file:path::search
+/// A title goes to the first heading with it
+fn search(file: &str, anchors: &Anchors, query: &str) -> Resolution {
+    let found = |path: Vec<String>| {
+        Resolution::Resolved(LinkNode {
+            file: file.to_string(),
+            path,
+        })
+    };
+    let heading = |title: &str| {
+        let title = heading_title(title);
+        match anchors.headings.iter().find(|path| path.last() == Some(&title)) {
+            Some(path) => found(path.clone()),
+            None => Resolution::Broken,
+        }
+    };
+    if let Some(title) = query.strip_prefix('*') {
+        return heading(title);
+    }
+    if let Some(custom_id) = query.strip_prefix('#') {
+        return match anchors.custom_ids.get(custom_id) {
+            Some(path) => found(path.clone()),
+            None => Resolution::Broken,
+        };
+    }
+    if query.chars().all(|c| c.is_ascii_digit()) {
+        return found(Vec::new());
+    }
+    if let Some(path) = anchors.targets.get(query) {
+        return found(path.clone());
+    }
+    heading(query)
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Removes . and .. from a path without touching the file system, so paths of files that were not read in can still be compared
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                if !result.pop() {
+                    result.push("..");
+                }
+            }
+            other => result.push(other.as_os_str()),
+        }
+    }
+    result
+}
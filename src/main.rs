@@ -1,72 +1,623 @@
-mod structs;
-mod parser;
-mod builder;
-mod filter;
-
-use clap::{Parser};
-use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-};
-use std::fs::OpenOptions;
-use std::io::{self, Write};
-use std::time::{SystemTime, UNIX_EPOCH};
-use std::iter::Map;
-use std::io::Lines;
-
-mod object;
-mod object_types;
-mod time_management;
+use clap::{Parser, Subcommand};
+use org_parse::{agenda, archive, export, filter, habit, html, history, latex, link, markdown, object, outline, refile, structs, terminal};
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
     /// Name of the person to greet
+    #[arg(long, required = true)]
+    file: Option<String>,
+
+    #[arg(long, required = true)]
+    history: Option<String>,
+
+    #[arg(long, required = true)]
+    context: Option<structs::FileContext>,
+
+    /// Insert CLOSED: into DONE headings that get archived
     #[arg(long)]
-    file: String,
+    log_closed: bool,
 
+    /// Add State "DONE" notes into the LOGBOOK of archived and looped headings
     #[arg(long)]
-    history: String,
+    log_notes: bool,
+
+    /// Set the LAST_REPEAT property of looped headings
+    #[arg(long)]
+    log_repeat: bool,
+
+    /// Archive DONE only this many days after CLOSED, like DONE=7, DONE is the only done keyword
+    #[arg(long, value_name = "KEYWORD=DAYS")]
+    archive_after: Vec<String>,
 
+    /// Keep DONE headings below a heading that stays, until it gets archived too
     #[arg(long)]
-    context: structs::FileContext,
+    keep_under_active: bool,
+
+    /// How archived items are added to the history file
+    #[arg(long, value_enum, default_value_t)]
+    history_format: history::HistoryFormat,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Lists the links of org files and checks where they point to
+    Links {
+        /// Only print links that don't resolve, exits with an error if there are any
+        #[arg(long)]
+        broken: bool,
+
+        /// The org files, id: and file: links are resolved across all of them
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+    /// Shows what is scheduled, due and overdue
+    Agenda {
+        /// Show the whole week instead of a single day
+        #[arg(long, conflicts_with = "month")]
+        week: bool,
+
+        /// Show the whole month instead of a single day
+        #[arg(long)]
+        month: bool,
+
+        /// The day the agenda is made for, today if not given (YYYY-MM-DD)
+        #[arg(long)]
+        date: Option<chrono::NaiveDate>,
+
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+    /// Prints the headings matching a tags/property match like +work-home+PRIORITY="A"/NEXT
+    Query {
+        /// The match, in the syntax org uses for tags views
+        query: String,
+
+        /// How many levels of each match are shown unfolded, 0 prints only the outline paths
+        #[arg(long, default_value_t = 1)]
+        depth: u32,
+
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+    /// Moves headings under another heading, in the same or in another org file
+    Refile {
+        /// The org file the headings are taken from
+        file: String,
+
+        /// The heading with this ID property
+        #[arg(long, group = "select")]
+        id: Option<String>,
+
+        /// The heading with this outline path, like Inbox/Call mom
+        #[arg(long, group = "select")]
+        path: Option<String>,
+
+        /// All headings matching a tags/property match like +work
+        #[arg(long = "match", group = "select")]
+        query: Option<String>,
+
+        /// Where they go: Projects/Website in the same file, work.org::Inbox in another one
+        #[arg(long, requires = "select")]
+        to: Option<String>,
+
+        /// File with one rule per line, like: +work -> work.org::Inbox
+        #[arg(long, conflicts_with_all = ["select", "to"], required_unless_present = "to")]
+        rules: Option<String>,
+    },
+    /// Lists the archived items of a history or archive file, numbered for restore
+    Archived {
+        history: String,
+
+        /// Only items archived on this day (YYYY-MM-DD)
+        #[arg(long)]
+        date: Option<chrono::NaiveDate>,
+
+        /// Only items containing this text, case insensitive
+        #[arg(long)]
+        search: Option<String>,
+    },
+    /// Reports what got completed, from a history or archive file
+    History {
+        history: String,
+
+        /// Group the completed items by day, week, month or tag
+        #[arg(long, value_enum, default_value = "day")]
+        by: history::Grouping,
+
+        /// First day of the report, 30 days before --to if not given (YYYY-MM-DD)
+        #[arg(long)]
+        from: Option<chrono::NaiveDate>,
+
+        /// Last day of the report, today if not given (YYYY-MM-DD)
+        #[arg(long)]
+        to: Option<chrono::NaiveDate>,
+
+        /// Only items with this tag, inherited tags count too
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// Moves archived items from a history or archive file back to where they where archived from
+    Restore {
+        history: String,
+
+        /// The numbers archived lists the items with
+        #[arg(required_unless_present = "search")]
+        numbers: Vec<usize>,
+
+        /// Restore every item containing this text instead
+        #[arg(long, conflicts_with = "numbers")]
+        search: Option<String>,
+
+        /// The org file to restore into, by default the file the item was archived from
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// Shows the consistency graph and streaks of the habits (:STYLE: habit or LOOP headings)
+    Habits {
+        /// Days shown before today
+        #[arg(long, default_value_t = 21)]
+        days: i64,
+
+        /// Days shown after today
+        #[arg(long, default_value_t = 7)]
+        future: i64,
+
+        /// Write the graphs as html instead of text
+        #[arg(long)]
+        html: bool,
+
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+    /// Converts an org file to another format
+    Export {
+        file: String,
+
+        #[arg(long, value_enum)]
+        format: export::ExportFormat,
+
+        /// Where the result goes, printed if not given
+        #[arg(long, short)]
+        output: Option<String>,
+
+        /// A file used as template instead of the built in one
+        #[arg(long)]
+        template: Option<String>,
+
+        /// Headings up to this level go into the table of contents, 0 for none
+        #[arg(long)]
+        toc_depth: Option<u32>,
+
+        /// OPML, DOT and Mermaid: headings up to this level, all of them if not given
+        #[arg(long)]
+        depth: Option<u32>,
+
+        /// Markdown: TODO keywords and priorities as badges instead of bold text
+        #[arg(long)]
+        badges: bool,
+
+        /// LaTeX: source blocks with minted instead of verbatim
+        #[arg(long)]
+        minted: bool,
+
+        /// DOT and Mermaid: nodes colored by TODO state
+        #[arg(long)]
+        colors: bool,
+
+        /// DOT and Mermaid: edges for links between headings
+        #[arg(long)]
+        links: bool,
+    },
+    /// Reads a file of another format as org, printed or merged into an org file
+    Import {
+        file: String,
+
+        #[arg(long, value_enum)]
+        format: export::ImportFormat,
+
+        /// The org file the imported headings are added to
+        #[arg(long)]
+        into: Option<String>,
+
+        /// The heading they go below, like Inbox/Team, the end of the file if not given
+        #[arg(long, requires = "into")]
+        under: Option<String>,
+    },
 }
 
 fn main() {
-    /// Scripting for org files
-    /// Short explanation what is happening
+    // Scripting for org files
+    // Short explanation what is happening
     let args = Args::parse(); // Read in the Arguments
 
-    let mut lines: Vec<String> = vec![format!("File context: {}", args.context.build())]; // Creates the Vec<String> with the specific context
-    lines.extend(lines_from_file(&args.file)); // Reads in the org file
-    let mut org_file = OpenOptions::new().write(true).truncate(true).open(&args.file).expect("No such file found"); //Open the org file, so that it can be rewritten
-    let mut history_file = OpenOptions::new().append(true).open(args.history).expect("No history file found");// Opens the history file
+    match args.command {
+        Some(Command::Links { broken, files }) => links(&files, broken),
+        Some(Command::Agenda { week, month, date, files }) => {
+            let view = match (week, month) {
+                (true, _) => agenda::AgendaView::Week,
+                (_, true) => agenda::AgendaView::Month,
+                _ => agenda::AgendaView::Day,
+            };
+            agenda(&files, view, date.unwrap_or_else(|| chrono::Local::now().date_naive()));
+        }
+        Some(Command::Query { query, depth, files }) => query_files(&query, depth, &files),
+        Some(Command::Refile { file, id, path, query, to, rules }) => {
+            let selector = match (id, path, query) {
+                (Some(id), _, _) => Some(refile::Selector::Id(id)),
+                (_, Some(path), _) => Some(refile::Selector::Path(refile::split_path(&path))),
+                (_, _, Some(query)) => Some(refile::Selector::Query(filter::Query::parse(&query).unwrap_or_else(|error| fail(&error)))),
+                _ => None,
+            };
+            match (selector, to, rules) {
+                (Some(selector), Some(to), _) => refile_headings(&file, &selector, &refile::Target::parse(&to)),
+                (_, _, Some(rules)) => refile_by_rules(&file, &rules),
+                _ => fail("give --to together with --id, --path or --match, or give --rules"),
+            }
+        }
+        Some(Command::Archived { history, date, search }) => list_archived(&history, date, search.as_deref()),
+        Some(Command::History { history, by, from, to, tag }) => {
+            let to = to.unwrap_or_else(|| chrono::Local::now().date_naive());
+            let from = from.unwrap_or(to - chrono::Duration::days(30));
+            report(&history, by, from, to, tag.as_deref());
+        }
+        Some(Command::Restore { history, numbers, search, to }) => restore(&history, &numbers, search.as_deref(), to.as_deref()),
+        Some(Command::Habits { days, future, html, files }) => habits(&files, days, future, html),
+        Some(Command::Import { file, format, into, under }) => import_file(&file, format, into.as_deref(), under.as_deref()),
+        Some(Command::Export { file, format, output, template, toc_depth, depth, badges, minted, colors, links }) => {
+            let outline = outline::OutlineOptions { depth, colors, links };
+            export_file(&file, format, output.as_deref(), template.as_deref(), toc_depth, badges, minted, &outline)
+        }
+        None => clean(
+            &args.file.expect("--file is required"),
+            &args.history.expect("--history is required"),
+            args.context.expect("--context is required"),
+            &structs::CompletionLog::new(args.log_closed, args.log_notes, args.log_repeat),
+            &archive_policy(&args.archive_after, args.keep_under_active),
+            args.history_format,
+        ),
+    }
+}
 
-    let mut context = structs::Context::new(); // Creates new context Object
-    context.parse(lines);// Parses the raw lines into ObjectTypes -> The program now knows what they are
-    let mut file = structs::File::new(); // Creates a virtual org file
-    file.add_children(object::Object::parse(context)); // Parses the context into the org file, so that a tree structure gets created
-    file.update_loop(); // Updtes all the Headers with the LOOP state
-    let mut done_vector = Vec::new();
-    let mut cleaned_vector = Vec::new();
-    for obj in file.children { // goes through the virtual org file and seperates it into two piles, the ones under a DONE Header and the rest
-        cleaned_vector.append(&mut obj.build_seperate_todo(&structs::TodoStates::DONE, &mut done_vector));
+/// Removes all DONE items from the org file into their archive location and updates the LOOP items
+/// DONE items go where #+ARCHIVE: or an :ARCHIVE: property says, if neither is set into the history file
+fn clean(file_name: &str, history: &str, file_context: structs::FileContext, log: &structs::CompletionLog, policy: &archive::ArchivePolicy, format: history::HistoryFormat) {
+    let mut file = structs::File::open(file_context, file_name).expect("No such file found"); // Reads the org file into a virtual org file with a tree structure
+    std::fs::metadata(history).expect("No history file found"); // The history file has to exist before anything gets archived
+
+    file.update_loop(log); // Updtes all the Headers with the LOOP state
+    file.log_completion(&structs::TodoStates::DONE, log); // Records when the DONE Headers got archived, if logging is turned on
+    let archived = file.archive_with_policy(policy, file_name, log.time); // Cuts out the DONE Headers the policy lets go, with their outline context as properties
+
+    let mut done_items = Vec::new();
+    let mut archive_files: Vec<(String, structs::File)> = Vec::new();
+    for item in archived {
+        let Some(location) = item.location else {
+            done_items.push(item.subtree);
+            continue;
+        };
+        let archive_file = match &location.file {
+            None => &mut file,
+            Some(name) => {
+                let name = relative_to(file_name, name);
+                let position = archive_files.iter().position(|(path, _)| *path == name);
+                let position = position.unwrap_or_else(|| {
+                    let archive = structs::File::open(structs::FileContext::Todo, &name)
+                        .unwrap_or_else(|_| archive::new_archive_file(file_name));
+                    archive_files.push((name, archive));
+                    archive_files.len() - 1
+                });
+                &mut archive_files[position].1
+            }
+        };
+        archive_file.add_archived(&location, item.subtree).unwrap_or_else(|error| fail(&error));
+    }
+    for (name, archive_file) in &archive_files {
+        archive_file.save(name).expect("Could not write archive file");
+    }
+
+    history::append_run(history, format, log.time, file_name, done_items).expect("Could not write to history file"); // Adds a heading for this run with the DONE items below it to the history file
+    file.save(file_name).expect("Could not write to org file"); // Writes the rest back to the org file
+}
+
+/// DONE headings get archived right away, unless the arguments say otherwise
+fn archive_policy(archive_after: &[String], keep_under_active: bool) -> archive::ArchivePolicy {
+    let mut policy = archive::ArchivePolicy::new(&structs::TodoStates::DONE);
+    for delay in archive_after {
+        policy.add_delay(delay).unwrap_or_else(|error| fail(&error));
+    }
+    policy.keep_under_active = keep_under_active;
+    policy
+}
+
+/// Prints the links of all files, or only the broken ones
+fn links(files: &[String], broken: bool) {
+    let parsed: Vec<(String, structs::File)> = files
+        .iter()
+        .map(|path| (path.clone(), structs::File::open(structs::FileContext::Todo, path).expect("no such file")))
+        .collect();
+    let graph = link::LinkGraph::build(&parsed.iter().map(|(path, file)| (path.clone(), file)).collect::<Vec<_>>());
+    let edges = match broken {
+        true => graph.broken_links(),
+        false => graph.edges().iter().collect(),
+    };
+    for edge in &edges {
+        let target = match &edge.resolution {
+            link::Resolution::Resolved(node) => node.to_string(),
+            link::Resolution::External => "external".to_string(),
+            link::Resolution::Broken => "BROKEN".to_string(),
+        };
+        println!("{}: {} -> {}", edge.source, edge.link.build(), target);
+    }
+    if broken && !edges.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+/// Prints the agenda of all files
+fn agenda(files: &[String], view: agenda::AgendaView, date: chrono::NaiveDate) {
+    let mut agenda = agenda::Agenda::new();
+    for path in files {
+        let file = structs::File::open(structs::FileContext::Todo, path).expect("no such file");
+        agenda.add_file(path, &file);
+    }
+    println!("{}", agenda.render_styled(view, date, &terminal::Style::detect()));
+}
+
+/// Prints every heading matching the query below its outline path, depth levels of it unfolded
+fn query_files(query: &str, depth: u32, files: &[String]) {
+    let query = filter::Query::parse(query).unwrap_or_else(|error| fail(&error));
+    let options = terminal::TerminalOptions {
+        style: terminal::Style::detect(),
+        depth: Some(depth),
+        ..Default::default()
+    };
+    for path in files {
+        let file = structs::File::open(structs::FileContext::Todo, path).expect("no such file");
+        for found in file.query(&query) {
+            println!("{}", options.style.dim(&format!("{path}: {}", found.path.join(" / "))));
+            if depth > 0 {
+                print!("{}", found.object.render_terminal(&options));
+            }
+        }
+    }
+}
+
+/// Moves the selected headings of file to the target
+fn refile_headings(file_name: &str, selector: &refile::Selector, target: &refile::Target) {
+    let target = &target.clone().local_to(file_name);
+    let mut file = structs::File::open(structs::FileContext::Todo, file_name).expect("no such file");
+    let paths = file.select(selector);
+    if paths.is_empty() {
+        fail("no heading selected");
+    }
+    match &target.file {
+        Some(name) => {
+            let other_name = relative_to(file_name, name);
+            let mut other = structs::File::open(structs::FileContext::Todo, &other_name).expect("no such target file");
+            refile::refile(&mut file, &paths, &target.path(), Some(&mut other)).unwrap_or_else(|error| fail(&error));
+            other.save(&other_name).expect("Could not write target file");
+        }
+        None => refile::refile(&mut file, &paths, &target.path(), None).unwrap_or_else(|error| fail(&error)),
+    }
+    file.save(file_name).expect("Could not write org file");
+    for path in paths {
+        println!("{} -> {target}", path.join("/"));
+    }
+}
+
+/// Refiles the headings of file following the rules of a rules file
+fn refile_by_rules(file_name: &str, rules_file: &str) {
+    let rules = std::fs::read_to_string(rules_file).expect("no such rules file");
+    let rules = refile::parse_rules(&rules).unwrap_or_else(|error| fail(&error));
+    let rules: Vec<refile::Rule> = rules
+        .into_iter()
+        .map(|rule| refile::Rule { target: rule.target.local_to(file_name), ..rule })
+        .collect();
+    let mut file = structs::File::open(structs::FileContext::Todo, file_name).expect("no such file");
+    let mut others = std::collections::HashMap::new();
+    for name in rules.iter().filter_map(|rule| rule.target.file.clone()) {
+        let other = structs::File::open(structs::FileContext::Todo, &relative_to(file_name, &name)).expect("no such target file");
+        others.entry(name).or_insert(other);
+    }
+    let refiled = refile::apply_rules(&mut file, &rules, &mut others).unwrap_or_else(|error| fail(&error));
+    for (name, other) in &others {
+        other.save(&relative_to(file_name, name)).expect("Could not write target file");
+    }
+    file.save(file_name).expect("Could not write org file");
+    for item in refiled {
+        println!("{} -> {}", item.path.join("/"), item.target);
+    }
+}
+
+/// Prints the archived items with their number, when they where archived and where they came from
+fn list_archived(history_name: &str, date: Option<chrono::NaiveDate>, search: Option<&str>) {
+    let history = history::History::open(history_name).unwrap_or_else(|error| fail(&format!("can't read the history: {error}")));
+    for item in history.items() {
+        if date.is_some_and(|date| item.time.map(|time| time.date()) != Some(date)) {
+            continue;
+        }
+        if search.is_some_and(|search| !item.contains(search)) {
+            continue;
+        }
+        let time = item.time.map(|time| time.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default();
+        let mut path = item.outline_path();
+        path.push(item.title());
+        println!("{:>4}  {time:<16}  {}  {}", item.number + 1, item.source().unwrap_or("?"), path.join("/"));
     }
+}
 
+/// Prints the completed items between from and to, grouped
+fn report(history_name: &str, grouping: history::Grouping, from: chrono::NaiveDate, to: chrono::NaiveDate, tag: Option<&str>) {
+    let history = history::History::open(history_name).unwrap_or_else(|error| fail(&format!("can't read the history: {error}")));
+    let mut completions = history.completions(from, to);
+    if let Some(tag) = tag {
+        completions.retain(|item| item.tags().iter().any(|other| other == tag));
+    }
+    println!("Completed from {from} to {to}: {}", completions.len());
+    for (group, items) in history::group(&completions, grouping) {
+        println!("{group} ({})", items.len());
+        for item in items {
+            let mut path = item.outline_path();
+            path.push(item.title());
+            let date = item.completed().map(|date| date.to_string()).unwrap_or_default();
+            println!("  {date}  {:<4}  {}  {}", item.todo().unwrap_or_default(), item.source().unwrap_or("?"), path.join("/"));
+        }
+    }
+}
 
-    let unix_time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_millis();
-    writeln!(history_file, "TIMESTAMP:{}", unix_time).expect("Could not write to history file"); // Appends the unix time stamp to the History file
-    for line in done_vector { // Writes done to history file
-        writeln!(history_file, "{}", line).expect("Could not write line to history file");
+/// Prints every habit with its graph from days before until future days after today, and its streaks
+fn habits(files: &[String], days: i64, future: i64, html: bool) {
+    let today = chrono::Local::now().date_naive();
+    let (from, to) = (today - chrono::Duration::days(days), today + chrono::Duration::days(future));
+    if html {
+        println!("<style>\n{}\n</style>", habit::HABIT_CSS);
+    }
+    for file_name in files {
+        let file = structs::File::open(structs::FileContext::Todo, file_name).expect("no such file");
+        for habit in file.habits() {
+            let graph = habit.graph(from, to, today);
+            let stats = habit.stats(today);
+            let streaks = format!(
+                "streak {}, longest {}, {} in the last 30 days",
+                stats.current_streak, stats.longest_streak, stats.last_30_days
+            );
+            if html {
+                println!("<p>{}<br>{}<br>{streaks}</p>", habit.title, habit::html_graph(&graph, today));
+            } else {
+                println!("{:<30} |{}|  {streaks}", habit.title, habit::ascii_graph(&graph, today));
+            }
+        }
     }
-    for line in cleaned_vector { // Writes rest to org file
-        writeln!(org_file, "{}", line).expect("Could not write to org file");
+}
+
+/// Writes the org file in another format, to output or the terminal
+#[allow(clippy::too_many_arguments)] // One per flag of the export command
+fn export_file(file_name: &str, format: export::ExportFormat, output: Option<&str>, template: Option<&str>, toc_depth: Option<u32>, badges: bool, minted: bool, outline: &outline::OutlineOptions) {
+    let file = structs::File::open(structs::FileContext::Todo, file_name).expect("no such file");
+    let exported = match format {
+        export::ExportFormat::Html => {
+            let mut options = html::HtmlOptions::default();
+            if let Some(template) = template {
+                options.template = std::fs::read_to_string(template).expect("no such template file");
+            }
+            if let Some(toc_depth) = toc_depth {
+                options.toc_depth = toc_depth;
+            }
+            file.to_html(&options)
+        }
+        export::ExportFormat::Markdown => {
+            let mut options = markdown::MarkdownOptions::default();
+            if badges {
+                options.todo_style = markdown::TodoStyle::Badge;
+            }
+            file.to_markdown(&options)
+        }
+        export::ExportFormat::Latex => {
+            let mut options = latex::LatexOptions {
+                minted,
+                ..Default::default()
+            };
+            if let Some(toc_depth) = toc_depth {
+                options.toc_depth = toc_depth;
+            }
+            file.to_latex(&options)
+        }
+        export::ExportFormat::Icalendar => file.to_icalendar(file_name, chrono::Utc::now().naive_utc(), &chrono::Local),
+        export::ExportFormat::TodoTxt => file.to_todo_txt(),
+        export::ExportFormat::Opml => file.to_opml(file_name, outline),
+        export::ExportFormat::Dot => file.to_dot(file_name, outline),
+        export::ExportFormat::Mermaid => file.to_mermaid(file_name, outline),
+        #[cfg(feature = "serde")]
+        export::ExportFormat::Taskwarrior => file.to_taskwarrior(&chrono::Local),
+        #[cfg(feature = "serde")]
+        export::ExportFormat::Json => file.to_json(),
+    };
+    match output {
+        Some(output) => std::fs::write(output, exported).expect("Could not write the export"),
+        None => print!("{exported}"),
+    }
+}
+
+/// Reads a file of another format, prints it as org or merges it into the org file into
+fn import_file(file_name: &str, format: export::ImportFormat, into: Option<&str>, under: Option<&str>) {
+    let text = std::fs::read_to_string(file_name).expect("no such file");
+    let imported = match format {
+        export::ImportFormat::Markdown => structs::File::from_markdown(&text),
+        export::ImportFormat::Icalendar => structs::File::from_icalendar(&text, &chrono::Local).unwrap_or_else(|error| fail(&error)),
+        export::ImportFormat::TodoTxt => structs::File::from_todo_txt(&text),
+        #[cfg(feature = "serde")]
+        export::ImportFormat::Taskwarrior => structs::File::from_taskwarrior(&text, &chrono::Local).unwrap_or_else(|error| fail(&error)),
+        #[cfg(feature = "serde")]
+        export::ImportFormat::Json => structs::File::from_json(&text).unwrap_or_else(|error| fail(&error)),
+    };
+    let Some(into) = into else {
+        println!("{}", imported.build().join("\n"));
+        return;
+    };
+    let mut file = structs::File::open(structs::FileContext::Todo, into).expect("no such org file");
+    let under = refile::split_path(under.unwrap_or_default());
+    let title = std::path::Path::new(file_name).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let under: Vec<&str> = under.iter().map(|title| title.as_str()).collect();
+    let merged = match format {
+        export::ImportFormat::Icalendar => file.merge_icalendar(&under, imported),
+        export::ImportFormat::TodoTxt => file.merge_tasks(&under, imported),
+        #[cfg(feature = "serde")]
+        export::ImportFormat::Taskwarrior => file.merge_tasks(&under, imported),
+        _ => file.merge(&under, imported, &title),
+    };
+    merged.unwrap_or_else(|error| fail(&error));
+    file.save(into).expect("Could not write org file");
+}
+
+/// Restores the items with the given numbers, or all items containing search, and removes them from the history
+fn restore(history_name: &str, numbers: &[usize], search: Option<&str>, to: Option<&str>) {
+    let mut history = history::History::open(history_name).unwrap_or_else(|error| fail(&format!("can't read the history: {error}")));
+    let mut chosen: Vec<usize> = match search {
+        Some(search) => history.items().iter().filter(|item| item.contains(search)).map(|item| item.number).collect(),
+        None => numbers.iter().map(|number| number.saturating_sub(1)).collect(),
+    };
+    chosen.sort();
+    chosen.dedup();
+    // Taking the last ones first keeps the numbers of the others the same
+    let mut taken: Vec<(usize, object::Object)> = chosen
+        .into_iter()
+        .rev()
+        .map(|number| (number, history.take(number).unwrap_or_else(|| fail(&format!("no archived item {}", number + 1)))))
+        .collect();
+    taken.reverse();
+    let mut files: Vec<(String, structs::File)> = Vec::new();
+    for (number, subtree) in taken {
+        let target = to
+            .map(|to| to.to_string())
+            .or_else(|| subtree.property("ARCHIVE_FILE").map(|file| file.to_string()))
+            .unwrap_or_else(|| fail(&format!("item {} does not know where it came from, use --to", number + 1)));
+        let position = files.iter().position(|(name, _)| *name == target).unwrap_or_else(|| {
+            let file = structs::File::open(structs::FileContext::Todo, &target).expect("no such file");
+            files.push((target.clone(), file));
+            files.len() - 1
+        });
+        let path = files[position].1.restore(subtree).unwrap_or_else(|error| fail(&error));
+        println!("{} -> {target}::{}", number + 1, path.join("/"));
+    }
+    for (name, file) in &files {
+        file.save(name).expect("Could not write org file");
+    }
+    history.save(history_name).expect("Could not write history file");
+}
+
+/// Target files are relative to the file the headings come from, like file: links
+fn relative_to(file_name: &str, other: &str) -> String {
+    match std::path::Path::new(file_name).parent() {
+        Some(dir) => dir.join(other).to_string_lossy().to_string(),
+        None => other.to_string(),
     }
 }
 
-fn lines_from_file(filename: &str) -> Map<Lines<BufReader<File>>, fn(Result<String, std::io::Error>) -> String> {
-    let file = File::open(filename).expect("no such file");
-    let buf = BufReader::new(file);
-    buf.lines()
-        .map(|l| l.expect("Could not parse line"))
+fn fail(error: &str) -> ! {
+    eprintln!("{error}");
+    std::process::exit(2);
 }
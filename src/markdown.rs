@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::{
+    export::{self, Element, List, ListKind, Row},
+    inline::{self, InlineObject},
+    link::{self, Link, LinkType},
+    object::Object,
+    structs::{self, Priority, TodoStates},
+    time_management::InfoType,
+};
+
+/// How TODO keywords and priorities are written
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TodoStyle {
+    /// **TODO** **[#A]**
+    #[default]
+    Prefix,
+    /// Shield images, like the badges at the top of READMEs
+    Badge,
+}
+
+/// How a file is exported to GitHub flavored Markdown
+#[derive(Debug, Clone)]
+pub struct MarkdownOptions {
+    pub todo_style: TodoStyle,
+    /// Write the planning lines below the headings
+    pub planning: bool,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        MarkdownOptions {
+            todo_style: TodoStyle::Prefix,
+            planning: true,
+        }
+    }
+}
+
+impl structs::File {
+    /// The file as GitHub flavored Markdown
+    pub fn to_markdown(&self, options: &MarkdownOptions) -> String {
+        render(&self.elements(), options, &link::abbreviations(self))
+    }
+}
+
+/// The Markdown of the elements, abbreviations are the #+LINK definitions used to expand links
+pub fn render(elements: &[Element], options: &MarkdownOptions, abbreviations: &HashMap<String, String>) -> String {
+    let blocks: Vec<String> = elements
+        .iter()
+        .map(|element| element_markdown(element, options, abbreviations))
+        .filter(|markdown| !markdown.is_empty())
+        .collect();
+    let mut markdown = blocks.join("\n\n");
+    markdown.push('\n');
+    markdown
+}
+
+fn element_markdown(element: &Element, options: &MarkdownOptions, abbreviations: &HashMap<String, String>) -> String {
+    let inline = |text: &str| inline_markdown(text, abbreviations);
+    match element {
+        Element::Heading(heading) => {
+            let mut parts = vec!["#".repeat(heading.level.clamp(1, 6) as usize)];
+            if let Some(todo) = &heading.todo {
+                parts.push(todo_markdown(todo, options.todo_style));
+            }
+            if let Some(priority) = &heading.priority {
+                parts.push(priority_markdown(priority, options.todo_style));
+            }
+            parts.push(inline(&heading.title));
+            parts.extend(heading.tags.iter().map(|tag| format!("`{tag}`")));
+            parts.join(" ")
+        }
+        Element::Planning(info) if options.planning => info
+            .iter()
+            .map(|info| {
+                let keyword = match info {
+                    InfoType::DEADLINE { .. } => "DEADLINE",
+                    InfoType::SCHEDULED { .. } => "SCHEDULED",
+                    InfoType::CLOSED { .. } => "CLOSED",
+                };
+                format!("*{keyword}:* `{}`", info.date().build())
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        Element::Planning(_) | Element::Keyword { .. } => String::new(),
+        Element::Paragraph(lines) => lines.iter().map(|line| inline(line)).collect::<Vec<_>>().join("\n"),
+        Element::List(list) => list_markdown(list, 0, options, abbreviations),
+        Element::Table(rows) => table_markdown(rows, abbreviations),
+        Element::Block { name, parameters, lines } => {
+            let language = parameters.split_whitespace().next().unwrap_or_default();
+            match name.as_str() {
+                "src" => format!("```{language}\n{}\n```", lines.join("\n")),
+                "example" => format!("```\n{}\n```", lines.join("\n")),
+                "export" if ["md", "markdown", "html"].contains(&language.to_lowercase().as_str()) => lines.join("\n"),
+                "export" => String::new(),
+                "quote" => lines
+                    .iter()
+                    .map(|line| format!("> {}", inline(line.trim())).trim_end().to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                // Two spaces at the end keep the line breaks
+                "verse" => lines.iter().map(|line| format!("{}  ", inline(line.trim()))).collect::<Vec<_>>().join("\n"),
+                _ => lines.iter().map(|line| inline(line.trim())).collect::<Vec<_>>().join("\n"),
+            }
+        }
+        Element::FootnoteDefinition { label, text } => format!("[^{label}]: {}", inline(text)),
+        Element::Rule => "---".to_string(),
+    }
+}
+
+fn list_markdown(list: &List, indent: usize, options: &MarkdownOptions, abbreviations: &HashMap<String, String>) -> String {
+    let mut lines = Vec::new();
+    for (number, item) in list.items.iter().enumerate() {
+        let bullet = match list.kind {
+            ListKind::Ordered => format!("{}.", number + 1),
+            _ => "-".to_string(),
+        };
+        let mut text = vec![bullet.clone()];
+        if let Some(checked) = item.checkbox {
+            text.push(if checked { "[x]" } else { "[ ]" }.to_string());
+        }
+        if let Some(todo) = &item.todo {
+            text.push(todo_markdown(todo, options.todo_style));
+        }
+        if let Some(term) = &item.term {
+            text.push(format!("**{}**:", inline_markdown(term, abbreviations)));
+        }
+        text.push(inline_markdown(&item.text, abbreviations));
+        lines.push(format!("{}{}", " ".repeat(indent), text.join(" ")));
+        if let Some(sublist) = &item.sublist {
+            // Nested lists have to start below the text of the item
+            lines.push(list_markdown(sublist, indent + bullet.len() + 1, options, abbreviations));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Pipe table, GitHub needs a header: the rows before the first rule, or else the first row
+fn table_markdown(rows: &[Row], abbreviations: &HashMap<String, String>) -> String {
+    let cells: Vec<&Vec<String>> = rows
+        .iter()
+        .filter_map(|row| match row {
+            Row::Cells(cells) => Some(cells),
+            Row::Rule => None,
+        })
+        .collect();
+    let Some(columns) = cells.iter().map(|row| row.len()).max() else {
+        return String::new();
+    };
+    let header_rows = match rows.iter().position(|row| *row == Row::Rule) {
+        Some(position) if position > 0 => position,
+        _ => 1,
+    };
+    let line = |row: &Vec<String>| {
+        let cells: Vec<String> = (0..columns)
+            .map(|column| inline_markdown(row.get(column).map(|cell| cell.as_str()).unwrap_or_default(), abbreviations).replace('|', "\\|"))
+            .collect();
+        format!("| {} |", cells.join(" | "))
+    };
+    let mut lines: Vec<String> = cells[..header_rows.min(cells.len())].iter().map(|row| line(row)).collect();
+    lines.push(format!("|{}", " --- |".repeat(columns)));
+    lines.extend(cells[header_rows.min(cells.len())..].iter().map(|row| line(row)));
+    lines.join("\n")
+}
+
+fn todo_markdown(todo: &TodoStates, style: TodoStyle) -> String {
+    let keyword = todo.build();
+    match style {
+        TodoStyle::Prefix => format!("**{keyword}**"),
+        TodoStyle::Badge => {
+            let color = match todo {
+                TodoStates::DONE => "green",
+                TodoStates::NEXT => "orange",
+                TodoStates::LOOP => "blue",
+                TodoStates::TODO => "red",
+            };
+            format!("![{keyword}](https://img.shields.io/badge/-{keyword}-{color})")
+        }
+    }
+}
+
+fn priority_markdown(priority: &Priority, style: TodoStyle) -> String {
+    match style {
+        TodoStyle::Prefix => format!("**{}**", priority.build()),
+        TodoStyle::Badge => {
+            let letter = priority.build().trim_start_matches("[#").trim_end_matches(']').to_string();
+            format!("![{0}](https://img.shields.io/badge/priority-{0}-lightgrey)", letter)
+        }
+    }
+}
+
+/// The Markdown of a line of org text
+pub fn inline_markdown(text: &str, abbreviations: &HashMap<String, String>) -> String {
+    objects_markdown(&inline::parse_inline(text), abbreviations)
+}
+
+fn objects_markdown(objects: &[InlineObject], abbreviations: &HashMap<String, String>) -> String {
+    objects.iter().map(|object| object_markdown(object, abbreviations)).collect()
+}
+
+fn object_markdown(object: &InlineObject, abbreviations: &HashMap<String, String>) -> String {
+    match object {
+        InlineObject::Plain(text) => escape(text),
+        InlineObject::Bold(children) => format!("**{}**", objects_markdown(children, abbreviations)),
+        InlineObject::Italic(children) => format!("*{}*", objects_markdown(children, abbreviations)),
+        // Markdown has no underline
+        InlineObject::Underline(children) => objects_markdown(children, abbreviations),
+        InlineObject::StrikeThrough(children) => format!("~~{}~~", objects_markdown(children, abbreviations)),
+        InlineObject::Verbatim(text) | InlineObject::Code(text) => code_span(text),
+        InlineObject::Link { target, description } => {
+            let link = Link::parse(target, None, abbreviations);
+            let url = url(&link);
+            let is_image = [".png", ".jpg", ".jpeg", ".gif", ".svg", ".webp"]
+                .iter()
+                .any(|extension| link.target.to_lowercase().ends_with(extension));
+            match description {
+                None if is_image => format!("![]({url})"),
+                Some(description) => format!("[{}]({url})", objects_markdown(description, abbreviations)),
+                None => format!("[{}]({url})", escape(target)),
+            }
+        }
+        InlineObject::Timestamp { raw, .. } => code_span(raw),
+        InlineObject::FootnoteReference { label, definition, .. } => match (label, definition) {
+            (Some(label), _) if !label.is_empty() => format!("[^{label}]"),
+            (_, Some(definition)) => format!(" ({})", objects_markdown(definition, abbreviations)),
+            _ => String::new(),
+        },
+        InlineObject::Entity { name, .. } => inline::entity_utf8(name).map(|utf8| utf8.to_string()).unwrap_or_else(|| format!("&{name};")),
+        InlineObject::LatexFragment(text) => text.clone(),
+        InlineObject::Macro { .. } => String::new(),
+        InlineObject::RadioTarget(text) => format!("<a id=\"{}\"></a>{}", export::slug(text), escape(text)),
+        InlineObject::Target(text) => format!("<a id=\"{}\"></a>", export::slug(text)),
+    }
+}
+
+/// Links to headings use the anchors GitHub gives headings, links to org files point to their Markdown export
+fn url(link: &Link) -> String {
+    let url = match &link.link_type {
+        LinkType::File { path, search } => {
+            let path = match path.strip_suffix(".org") {
+                Some(stem) => format!("{stem}.md"),
+                None => path.clone(),
+            };
+            match search {
+                Some(search) => format!("{path}#{}", export::slug(search.trim_start_matches(['*', '#']))),
+                None => path,
+            }
+        }
+        LinkType::Heading(heading) => format!("#{}", export::slug(heading)),
+        LinkType::CustomId(id) | LinkType::Id(id) => format!("#{id}"),
+        LinkType::Fuzzy(target) => format!("#{}", export::slug(target)),
+        LinkType::External { .. } => link.target.clone(),
+    };
+    url.replace(' ', "%20")
+}
+
+fn code_span(text: &str) -> String {
+    match text.contains('`') {
+        true => format!("`` {text} ``"),
+        false => format!("`{text}`"),
+    }
+}
+
+/// Escapes the characters Markdown would read as markup
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>' | '~') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The org lines of a Markdown document (CommonMark with the GFM task lists, tables and strikethrough)
+/// Headings become headings, lists org lists, fenced code src blocks, block quotes quote blocks
+/// **TODO** and **[#A]** at the start of a heading, like to_markdown writes them, become the keyword and priority again
+pub fn to_org_lines(text: &str) -> Vec<String> {
+    let re_atx = Regex::new(r"^ {0,3}(#{1,6})(?:\s+(.*?))?(?:\s+#+)?\s*$").unwrap();
+    let re_setext = Regex::new(r"^ {0,3}(=+|-+)\s*$").unwrap();
+    let re_fence = Regex::new(r"^ {0,3}(```+|~~~+)\s*([^`\s]*)").unwrap();
+    let re_item = Regex::new(r"^(\s*)([-*+]|\d+[.)])\s+(?:\[([ xX])\]\s+)?(.*)$").unwrap();
+    let re_rule = Regex::new(r"^ {0,3}([-*_])(?:\s*([-*_]))(?:\s*([-*_]))+\s*$").unwrap();
+    let re_delimiter = Regex::new(r"^\s*\|?\s*:?-+:?\s*(\|\s*:?-+:?\s*)*\|?\s*$").unwrap();
+    let re_footnote = Regex::new(r"^\[\^([\w-]+)\]:\s*(.*)$").unwrap();
+    let re_keyword = Regex::new(r"^(?:\*\*(TODO|NEXT|DONE|LOOP)\*\*\s+)?(?:\*\*(\[#[ABC]\])\*\*\s+)?(.*)$").unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    let mut org = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index];
+        index += 1;
+        if let Some(caps) = re_fence.captures(line) {
+            let fence = &caps[1];
+            let language = &caps[2];
+            org.push(match language.is_empty() {
+                true => "#+begin_example".to_string(),
+                false => format!("#+begin_src {language}"),
+            });
+            while index < lines.len() && !lines[index].trim_start().starts_with(fence) {
+                org.push(lines[index].to_string());
+                index += 1;
+            }
+            index += 1;
+            org.push(if language.is_empty() { "#+end_example" } else { "#+end_src" }.to_string());
+            continue;
+        }
+        let heading = match (re_atx.captures(line), lines.get(index).and_then(|next| re_setext.captures(next))) {
+            (Some(caps), _) => Some((caps[1].len(), caps.get(2).map(|text| text.as_str()).unwrap_or_default().to_string())),
+            (None, Some(caps)) if !line.trim().is_empty() && re_item.captures(line).is_none() && !line.trim_start().starts_with(['>', '|']) => {
+                index += 1;
+                Some((if caps[1].starts_with('=') { 1 } else { 2 }, line.trim().to_string()))
+            }
+            _ => None,
+        };
+        if let Some((level, text)) = heading {
+            let caps = re_keyword.captures(&text).unwrap();
+            let parts: Vec<String> = [caps.get(1), caps.get(2)]
+                .iter()
+                .flatten()
+                .map(|part| part.as_str().to_string())
+                .chain([inline_to_org(&caps[3])])
+                .collect();
+            org.push(format!("{} {}", "*".repeat(level), parts.join(" ")).trim_end().to_string());
+            continue;
+        }
+        if re_rule.is_match(line) {
+            org.push("-----".to_string());
+            continue;
+        }
+        if line.trim_start().starts_with('>') {
+            org.push("#+begin_quote".to_string());
+            index -= 1;
+            while index < lines.len() && lines[index].trim_start().starts_with('>') {
+                let quoted = lines[index].trim_start().trim_start_matches('>');
+                org.push(inline_to_org(quoted.strip_prefix(' ').unwrap_or(quoted)));
+                index += 1;
+            }
+            org.push("#+end_quote".to_string());
+            continue;
+        }
+        if line.trim_start().starts_with('|') {
+            match re_delimiter.is_match(line) {
+                true => {
+                    let columns = line.trim().trim_matches('|').split('|').count();
+                    org.push(format!("|{}|", vec!["---"; columns].join("+")));
+                }
+                false => {
+                    // Org tables can't have | inside a cell
+                    let cells: Vec<String> = split_row(line).iter().map(|cell| inline_to_org(cell).replace('|', "\\vert{}")).collect();
+                    org.push(format!("| {} |", cells.join(" | ")));
+                }
+            }
+            continue;
+        }
+        if let Some(caps) = re_item.captures(line) {
+            let bullet = match caps[2].chars().next() {
+                Some(c) if c.is_ascii_digit() => caps[2].to_string(),
+                _ => "-".to_string(),
+            };
+            let checkbox = match caps.get(3).map(|checkbox| checkbox.as_str()) {
+                Some(" ") => "[ ] ",
+                Some(_) => "[X] ",
+                None => "",
+            };
+            org.push(format!("{}{bullet} {checkbox}{}", &caps[1], inline_to_org(&caps[4])));
+            continue;
+        }
+        if let Some(caps) = re_footnote.captures(line) {
+            org.push(format!("[fn:{}] {}", &caps[1], inline_to_org(&caps[2])));
+            continue;
+        }
+        org.push(inline_to_org(line.trim_start()));
+    }
+    org
+}
+
+/// The cells of a table row, \| stays inside its cell
+fn split_row(line: &str) -> Vec<String> {
+    let inner = line.trim().trim_start_matches('|');
+    let inner = inner.strip_suffix('|').filter(|_| !inner.ends_with("\\|")).unwrap_or(inner);
+    let mut cells = vec![String::new()];
+    let mut chars = inner.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => cells.last_mut().unwrap().push(chars.next().unwrap()),
+            '|' => cells.push(String::new()),
+            _ => cells.last_mut().unwrap().push(c),
+        }
+    }
+    cells.iter().map(|cell| cell.trim().to_string()).collect()
+}
+
+/// Turns the Markdown markup of a line into org markup
+pub fn inline_to_org(text: &str) -> String {
+    let re_code = Regex::new(r"``\s?(.+?)\s?``|`([^`]+)`").unwrap();
+    let re_image = Regex::new(r"!\[[^\]]*\]\(([^)\s]+)(?:\s+[^)]*)?\)").unwrap();
+    let re_link = Regex::new(r"\[([^\]]+)\]\(([^)\s]+)(?:\s+[^)]*)?\)").unwrap();
+    let re_autolink = Regex::new(r"<((?:https?|ftp|mailto):[^>\s]+)>").unwrap();
+    let re_footnote = Regex::new(r"\[\^([\w-]+)\]").unwrap();
+    let re_bold = Regex::new(r"\*\*(\S(?:.*?\S)?)\*\*|__(\S(?:.*?\S)?)__").unwrap();
+    let re_italic = Regex::new(r"\*(\S(?:[^*]*?\S)?)\*|\b_(\S(?:[^_]*?\S)?)_\b").unwrap();
+    let re_strike = Regex::new(r"~~(\S(?:.*?\S)?)~~").unwrap();
+    let re_escape = Regex::new(r"\\([\\`*_{}\[\]()#+\-.!<>|~])").unwrap();
+    // Code spans are put aside first, nothing inside of them is markup, then the backslash escaped characters
+    let mut code = Vec::new();
+    let text = re_code.replace_all(text, |caps: &regex::Captures| {
+        code.push(format!("~{}~", caps.get(1).or(caps.get(2)).map(|m| m.as_str()).unwrap_or_default()));
+        format!("\u{0}{}\u{0}", code.len() - 1)
+    });
+    let text = re_escape.replace_all(&text, |caps: &regex::Captures| {
+        code.push(caps[1].to_string());
+        format!("\u{0}{}\u{0}", code.len() - 1)
+    });
+    let text = re_image.replace_all(&text, "[[$1]]");
+    let text = re_link.replace_all(&text, |caps: &regex::Captures| format!("[[{}][{}]]", &caps[2], &caps[1]));
+    let text = re_autolink.replace_all(&text, "[[$1]]");
+    let text = re_footnote.replace_all(&text, "[fn:$1]");
+    // Bold becomes \u{1} for now, so its stars are not read as italic
+    let text = re_bold.replace_all(&text, |caps: &regex::Captures| {
+        format!("\u{1}{}\u{1}", caps.get(1).or(caps.get(2)).map(|m| m.as_str()).unwrap_or_default())
+    });
+    let text = re_italic.replace_all(&text, |caps: &regex::Captures| {
+        format!("/{}/", caps.get(1).or(caps.get(2)).map(|m| m.as_str()).unwrap_or_default())
+    });
+    let text = re_strike.replace_all(&text, "+$1+");
+    let text = text.replace('\u{1}', "*");
+    let re_placeholder = Regex::new(r"\x00(\d+)\x00").unwrap();
+    re_placeholder
+        .replace_all(&text, |caps: &regex::Captures| code[caps[1].parse::<usize>().unwrap()].clone())
+        .to_string()
+}
+
+impl structs::File {
+    /// Reads a Markdown document into a File, as if it was written in org
+    pub fn from_markdown(text: &str) -> structs::File {
+        structs::File::parse(structs::FileContext::Todo, to_org_lines(text))
+    }
+
+    /// Adds the headings of an imported file below the heading at parent, or at the end if parent is empty
+    /// Anything before the first heading goes into a new heading with the title, so it does not end up below another heading
+    pub fn merge(&mut self, parent: &[&str], imported: structs::File, title: &str) -> Result<(), String> {
+        let mut loose = Object::heading(title);
+        let mut headings = Vec::new();
+        for child in imported.children {
+            match child.level() {
+                Some(_) => headings.push(child),
+                None => loose.add_child(child),
+            }
+        }
+        if !loose.children().is_empty() {
+            headings.insert(0, loose);
+        }
+        for heading in headings {
+            self.insert_heading(parent, heading)?;
+        }
+        Ok(())
+    }
+}
@@ -2,6 +2,7 @@ use crate::{object_types,object,  structs::{self, TodoStates}, time_management};
 
 /// This is the main Object information is stored in when parsed into a tree
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Object {
     /// Specifies the type of the parent
     object_type: object_types::ObjectTypes,
@@ -23,6 +24,32 @@ impl Object {
     pub fn get_children(self) -> Vec<Object> {
         return self.children;
     }
+    /// The type of this Object
+    pub fn object_type(&self) -> &object_types::ObjectTypes {
+        &self.object_type
+    }
+    /// The children underneath this Object
+    pub fn children(&self) -> &Vec<Object> {
+        &self.children
+    }
+    /// Mutable access to the type of this Object
+    pub fn object_type_mut(&mut self) -> &mut object_types::ObjectTypes {
+        &mut self.object_type
+    }
+    /// Mutable access to the children underneath this Object
+    pub fn children_mut(&mut self) -> &mut Vec<Object> {
+        &mut self.children
+    }
+    /// Looks up a property of the :PROPERTIES: drawer directly underneath this Object
+    /// Property names are case insensitive, like in org
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.children.iter().find_map(|child| match &child.object_type {
+            object_types::ObjectTypes::Property { name: key, value, .. } if key.eq_ignore_ascii_case(name) => {
+                Some(value.as_str())
+            }
+            _ => None,
+        })
+    }
     /// Creates a Vec<String> representation from Object
     /// I think it's called deserilisation
     pub fn build(&self) -> Vec<String> {
@@ -37,7 +64,7 @@ impl Object {
     pub fn build_seperate_todo(&self, todostate: &TodoStates, filtered_vec:&mut Vec<String> ) -> Vec<String> {
         let mut std_vec = Vec::new();
         match &self.object_type {
-            object_types::ObjectTypes::Heading { text, todo, deadline, scheduled, in_line_scedule, value, priority } => match todo {
+            object_types::ObjectTypes::Heading { text, todo, deadline, scheduled, closed, in_line_scedule, value, priority } => match todo {
                 Some(todo) if todo == todostate => {
                         filtered_vec.extend(self.build());
                         return Vec::new();
@@ -54,25 +81,31 @@ impl Object {
     /// Recursivly updtes all items that are or are under a Heading with the TodoState::LOOP
     /// If unsets potential set checkboxes
     /// and if a repeater and a scheduler is given, updates the repeater by how mutch the repeater is set to increase
-    pub fn update_loop(&mut self) {
+    /// When a checked checkbox got reset, the loop counts as done and is logged as described by log
+    pub fn update_loop(&mut self, log: &structs::CompletionLog) {
         match &mut self.object_type {
-            object_types::ObjectTypes::Heading { text, todo, deadline, scheduled, in_line_scedule, value, priority } => {
+            object_types::ObjectTypes::Heading { text, todo, deadline, scheduled, closed, in_line_scedule, value, priority } => {
                 if let Some(todo) = todo {
                     if todo == &structs::TodoStates::LOOP {
                         let mut did_update = false;
                         let mut date_sepcified = false;
-                        self.update_date(&mut did_update, &mut date_sepcified);
+                        let mut did_reset = false;
+                        self.update_date(&mut did_update, &mut date_sepcified, &mut did_reset);
+                        self.sync_planning();
+                        if did_reset {
+                            self.log_state_change(&structs::TodoStates::LOOP, &structs::TodoStates::DONE, true, log);
+                        }
                     }
                 }
             }
             _ => (),
         }
         for child in &mut self.children {
-            child.update_loop();
+            child.update_loop(log);
         }
     }
 
-    fn update_date(&mut self, did_update: &mut bool, date_specified: &mut bool) {
+    fn update_date(&mut self, did_update: &mut bool, date_specified: &mut bool, did_reset: &mut bool) {
         for child in self.children.iter_mut() {
             match &mut child.object_type {
                 object_types::ObjectTypes::INFO { info, text } => {
@@ -81,20 +114,197 @@ impl Object {
                         match information {
                             time_management::InfoType::DEADLINE { date } => *did_update = date.update() | *did_update,
                             time_management::InfoType::SCHEDULED { date } => *did_update = date.update() | *did_update,
+                            time_management::InfoType::CLOSED { .. } => (),
                         }
                     }
                 }
                 object_types::ObjectTypes::ListElement { text, todo, checkbox, value } => {
                     if !*date_specified || *did_update {
                         if let Some(checkbox) = checkbox {
+                            *did_reset |= *checkbox;
                             *checkbox = false;
                         }
                     }
                 }
                 _ => (),
             }
-            child.update_date(did_update, date_specified);
+            child.update_date(did_update, date_specified, did_reset);
+        }
+    }
+    /// Records that this heading changed from one state to another, what gets written depends on log
+    /// Repeating headings get LAST_REPEAT instead of CLOSED, as they are not finished for good
+    pub fn log_state_change(&mut self, from: &TodoStates, to: &TodoStates, repeating: bool, log: &structs::CompletionLog) {
+        if log.closed && !repeating {
+            self.set_planning(time_management::InfoType::CLOSED { date: log.timestamp() });
+        }
+        if log.state_notes {
+            self.add_logbook_entry(&log.state_note(to, from));
+        }
+        if log.last_repeat && repeating {
+            self.set_property("LAST_REPEAT", &log.timestamp().build());
+        }
+    }
+    /// Logs all headings with the todostate that have no CLOSED yet, this is used before the archiver moves them
+    /// The state they came from is not known anymore, so TODO is assumed
+    /// Headings whose newest LOGBOOK note already records the todostate are skipped, they stay in the file when the archive policy keeps them
+    pub fn log_completion(&mut self, todostate: &TodoStates, log: &structs::CompletionLog) {
+        if let object_types::ObjectTypes::Heading { todo: Some(todo), closed: None, .. } = &self.object_type {
+            if todo == todostate && log.enabled() && !self.logged(todostate) {
+                self.log_state_change(&TodoStates::TODO, todostate, false, log);
+            }
+        }
+        for child in &mut self.children {
+            child.log_completion(todostate, log);
+        }
+    }
+    /// Sets a property in the :PROPERTIES: drawer directly underneath this Object
+    /// The drawer is created after the planning line if it does not exist yet
+    pub fn set_property(&mut self, name: &str, value: &str) {
+        for child in self.children.iter_mut() {
+            if let object_types::ObjectTypes::Property { name: key, value: old, .. } = &mut child.object_type {
+                if key.eq_ignore_ascii_case(name) {
+                    *old = value.to_string();
+                    return;
+                }
+            }
+        }
+        let property = Object::new(object_types::ObjectTypes::Property {
+            name: name.to_string(),
+            value: value.to_string(),
+            raw: String::new(),
+        });
+        match self.drawer_position("PROPERTIES") {
+            Some((_, end)) => self.children.insert(end, property),
+            None => {
+                let index = self.planning_end();
+                self.insert_drawer(index, "PROPERTIES", vec![property]);
+            }
+        }
+    }
+    /// Removes a property from the :PROPERTIES: drawer directly underneath this Object and returns its value
+    /// The drawer goes away together with its last property
+    pub fn remove_property(&mut self, name: &str) -> Option<String> {
+        let (start, end) = self.drawer_position("PROPERTIES")?;
+        let index = self.children[start + 1..end].iter().position(|child| {
+            matches!(&child.object_type, object_types::ObjectTypes::Property { name: key, .. } if key.eq_ignore_ascii_case(name))
+        })?;
+        let removed = self.children.remove(start + 1 + index);
+        if end - start == 2 {
+            let end = self.children.remove(start + 1);
+            self.children.remove(start);
+            // Empty lines after the drawer stay where they were
+            for (offset, child) in end.children.into_iter().enumerate() {
+                self.children.insert(start + offset, child);
+            }
+        }
+        match removed.object_type {
+            object_types::ObjectTypes::Property { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+    /// The lines inside the :LOGBOOK: drawer directly underneath this Object, newest first like org writes them
+    pub fn logbook(&self) -> Vec<String> {
+        match self.drawer_position("LOGBOOK") {
+            Some((start, end)) => self.children[start + 1..end]
+                .iter()
+                .flat_map(|child| child.build())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+    /// Adds a line at the top of the :LOGBOOK: drawer, the drawer is created after the planning line and properties if needed
+    pub fn add_logbook_entry(&mut self, entry: &str) {
+        let line = Object::new(object_types::ObjectTypes::new_text(entry.to_string()));
+        match self.drawer_position("LOGBOOK") {
+            Some((start, _)) => self.children.insert(start + 1, line),
+            None => {
+                let index = match self.drawer_position("PROPERTIES") {
+                    Some((_, end)) => end + 1,
+                    None => self.planning_end(),
+                };
+                self.insert_drawer(index, "LOGBOOK", vec![line]);
+            }
+        }
+    }
+    /// Whether the newest state note in the LOGBOOK is a change to the todostate
+    fn logged(&self, todostate: &TodoStates) -> bool {
+        let Some((start, end)) = self.drawer_position("LOGBOOK") else {
+            return false;
+        };
+        let note = format!("- State \"{}\"", todostate.build());
+        self.children[start + 1..end]
+            .iter()
+            .find_map(|child| match &child.object_type {
+                object_types::ObjectTypes::Text { text } if text.trim_start().starts_with("- State ") => Some(text.trim_start().starts_with(&note)),
+                _ => None,
+            })
+            .unwrap_or(false)
+    }
+    /// Start and :END: index of a drawer among the children
+    fn drawer_position(&self, name: &str) -> Option<(usize, usize)> {
+        let start = self.children.iter().position(|child| {
+            matches!(&child.object_type, object_types::ObjectTypes::Drawer { name: drawer, .. } if drawer.eq_ignore_ascii_case(name))
+        })?;
+        let end = self.children[start..].iter().position(|child| {
+            matches!(&child.object_type, object_types::ObjectTypes::Drawer { name: drawer, .. } if drawer.eq_ignore_ascii_case("END"))
+        })?;
+        Some((start, start + end))
+    }
+    /// Index right after the planning line, or 0 if there is none
+    fn planning_end(&self) -> usize {
+        match self.children.first() {
+            Some(Object { object_type: object_types::ObjectTypes::INFO { .. }, .. }) => 1,
+            _ => 0,
+        }
+    }
+    fn insert_drawer(&mut self, index: usize, name: &str, contents: Vec<Object>) {
+        let mut drawer = vec![Object::new(object_types::ObjectTypes::Drawer { name: name.to_string(), raw: String::new() })];
+        drawer.extend(contents);
+        drawer.push(Object::new(object_types::ObjectTypes::Drawer { name: "END".to_string(), raw: String::new() }));
+        // Empty lines after the line before belong after the new drawer, so it sits directly below that line
+        if let Some(before) = index.checked_sub(1).map(|before| &mut self.children[before].children) {
+            let empty = before.iter().rev().take_while(|child| matches!(child.object_type, object_types::ObjectTypes::EmptyLine)).count();
+            drawer.extend(before.drain(before.len() - empty..));
+        }
+        self.children.splice(index..index, drawer);
+    }
+    /// Copies the planning line (INFO) directly underneath a heading into its deadline, scheduled and closed fields
+    pub fn sync_planning(&mut self) {
+        let info = match self.children.first() {
+            Some(Object { object_type: object_types::ObjectTypes::INFO { info, .. }, .. }) => info.clone(),
+            _ => Vec::new(),
+        };
+        if let object_types::ObjectTypes::Heading { deadline, scheduled, closed, .. } = &mut self.object_type {
+            *deadline = None;
+            *scheduled = None;
+            *closed = None;
+            for information in info {
+                match information {
+                    time_management::InfoType::DEADLINE { date } => *deadline = Some(date),
+                    time_management::InfoType::SCHEDULED { date } => *scheduled = Some(date),
+                    time_management::InfoType::CLOSED { date } => *closed = Some(date),
+                }
+            }
+        }
+    }
+    /// Sets DEADLINE, SCHEDULED or CLOSED in the planning line of a heading, replacing an existing entry of the same kind
+    /// If the heading has no planning line yet, one is created directly underneath it
+    pub fn set_planning(&mut self, planning: time_management::InfoType) {
+        let has_info = matches!(
+            self.children.first(),
+            Some(Object { object_type: object_types::ObjectTypes::INFO { .. }, .. })
+        );
+        if !has_info {
+            self.children.insert(
+                0,
+                Object::new(object_types::ObjectTypes::INFO { info: Vec::new(), text: "".to_string() }),
+            );
+        }
+        if let object_types::ObjectTypes::INFO { info, .. } = &mut self.children[0].object_type {
+            info.retain(|information| information.order() != planning.order());
+            info.push(planning);
         }
+        self.sync_planning();
     }
     /// Parses the Context of a file into a tree representation
     /// This converts a Linear representation of the Org mode into a Parent child construct
@@ -106,7 +316,8 @@ impl Object {
             if number == 0 {
                 lowest_value = obj.value();
                 result_obj = Object::new(obj);
-            } else if obj.value() > lowest_value {
+            // The first child sets the level its siblings split at, otherwise the lines after a drawer or planning line would become its children
+            } else if number > 1 && obj.value() > lowest_value {
                 obj_context.add_context_line((obj, string));
             } else {
                 if number != 1 {
@@ -120,6 +331,7 @@ impl Object {
         if !obj_context.lines.is_empty() {
             result_obj.add_child(object::Object::parse(obj_context));
         }
+        result_obj.sync_planning();
         return result_obj;
     }
 }
@@ -2,7 +2,7 @@ use std::u32::MAX;
 
 use regex::Regex;
 
-use crate::{builder, parser::count_initial_repeats, structs::{self, Priority, TodoStates}, time_management::{self, InfoType}};
+use crate::{builder, inline::{self, InlineObject}, parser::count_initial_repeats, structs::{self, Priority, TodoStates}, time_management::{self, InfoType}};
 
 /// The Object type specifies all types a Line in Orgmode could have.
 /// If you would like to add a new type, this is the first place it should be added.
@@ -16,14 +16,18 @@ use crate::{builder, parser::count_initial_repeats, structs::{self, Priority, To
 /// ListElements: List are able to embed list in themselfes, but only up to 100, so list values range from 500-599 inclusive
 /// Headings: are of high priority but are only allowed to recurse until 100, so heading values rango from 100-199 inclusive
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(tag = "type"))]
+#[allow(clippy::large_enum_variant)] // Headings carry their planning, boxing them would only add indirection to the most used type
 pub enum ObjectTypes {
     Heading {
         text: String,
         todo: Option<TodoStates>,
         /// The difference between schedule and in_line_scedule is, that the schedule is defined in the line underneath, while the in_line in the same line
-        /// As there is currently no nead for the out of line schedule to move the information into the header. This is not supported
+        /// deadline, scheduled and closed mirror the planning line (INFO) directly underneath the heading, they are filled in when the tree is parsed
+        /// The INFO child stays the one that is written by build, so change planning with Object::set_planning to keep both in sync
         deadline: Option<time_management::ParsedDateTime>,
         scheduled: Option<time_management::ParsedDateTime>,
+        closed: Option<time_management::ParsedDateTime>,
         in_line_scedule: Option<time_management::ParsedDateTime>,
         value: u32,
         priority: Option<Priority>,
@@ -44,7 +48,27 @@ pub enum ObjectTypes {
     },
     File {
         context: structs::FileContext,
-    }
+    },
+    /// Start or end of a drawer, like :PROPERTIES:, :LOGBOOK: or :END:
+    /// raw is the line as read, written back as it is so indentation stays, empty for new drawers
+    Drawer {
+        name: String,
+        #[cfg_attr(feature = "serde", serde(default))]
+        raw: String,
+    },
+    /// A single :NAME: value line inside a :PROPERTIES: drawer
+    /// raw is the line as read, written back as long as name and value were not changed
+    Property {
+        name: String,
+        value: String,
+        #[cfg_attr(feature = "serde", serde(default))]
+        raw: String,
+    },
+    /// [fn:label] text, at the very start of a line
+    FootnoteDefinition {
+        label: String,
+        text: String,
+    },
 }
 
 impl ObjectTypes {
@@ -55,6 +79,8 @@ impl ObjectTypes {
             ObjectTypes::EmptyLine => MAX,
             ObjectTypes::Text { text } => MAX - 1,
             ObjectTypes::INFO { info, text } => MAX - 1,
+            ObjectTypes::Drawer { .. } | ObjectTypes::Property { .. } => MAX - 1,
+            ObjectTypes::FootnoteDefinition { .. } => MAX - 1,
             ObjectTypes::ListElement {
                 text,
                 todo,
@@ -66,6 +92,7 @@ impl ObjectTypes {
                 todo,
                 deadline,
                 scheduled,
+                closed,
                 value,
                 priority,
                 in_line_scedule,
@@ -81,10 +108,21 @@ impl ObjectTypes {
             ObjectTypes::EmptyLine => "".to_string(),
             ObjectTypes::Text { text } => text.to_owned(),
             ObjectTypes::INFO { info, text } => {
-                let vector: Vec<String> = info
+                let mut sorted: Vec<&InfoType> = info.iter().collect();
+                sorted.sort_by_key(|inf| inf.order());
+                let mut vector: Vec<String> = sorted
                     .iter()
-                    .map(|inf| <InfoType as Clone>::clone(&inf).build())
+                    .map(|inf| <InfoType as Clone>::clone(inf).build())
                     .collect();
+                let rest = ObjectTypes::info_regex().replace_all(text, |caps: &regex::Captures| {
+                    match InfoType::get(&caps[0]) {
+                        Some(_) => "".to_string(),
+                        None => caps[0].to_string(),
+                    }
+                });
+                if !rest.trim().is_empty() {
+                    vector.push(rest.split_whitespace().collect::<Vec<&str>>().join(" "));
+                }
                 return vector.join(" ");
             }
             ObjectTypes::ListElement {
@@ -115,6 +153,7 @@ impl ObjectTypes {
                 todo,
                 deadline,
                 scheduled,
+                closed,
                 value,
                 priority,
                 in_line_scedule,
@@ -138,6 +177,17 @@ impl ObjectTypes {
                 );
             }
             ObjectTypes::File { context } => format!("File context: {}", context.build()),
+            ObjectTypes::Drawer { name, raw } => match ObjectTypes::new_drawer(raw) {
+                ObjectTypes::Drawer { name: read, .. } if !raw.is_empty() && read == *name => raw.to_owned(),
+                _ => format!(":{name}:"),
+            },
+            ObjectTypes::Property { name, value, raw } => match ObjectTypes::new_property(raw) {
+                Some(ObjectTypes::Property { name: read, value: read_value, .. }) if read == *name && read_value == *value => raw.to_owned(),
+                _ if value.is_empty() => format!(":{name}:"),
+                _ => format!(":{name}: {value}"),
+            },
+            ObjectTypes::FootnoteDefinition { label, text } if text.is_empty() => format!("[fn:{label}]"),
+            ObjectTypes::FootnoteDefinition { label, text } => format!("[fn:{label}] {text}"),
         }
     }
     /// Takes in a String which, should be a full line of org, and creates an ObjectType of Type Header
@@ -148,6 +198,7 @@ impl ObjectTypes {
             todo: TodoStates::get("*", input),
             deadline: None,
             scheduled: None,
+            closed: None,
             in_line_scedule: ObjectTypes::inline_schedule(input),
             value: count_initial_repeats(input),
             priority: Priority::get(input),
@@ -182,7 +233,8 @@ impl ObjectTypes {
             text: ObjectTypes::list_cleanup(input),
             todo: TodoStates::get("-", input),
             checkbox: ObjectTypes::get_checkbox(input),
-            value: count_initial_repeats(input),
+            // The indentation plus one, so build writes the item back with the same indentation
+            value: input.chars().take_while(|c| c.is_whitespace()).count() as u32 + 1,
         };
     }
     fn list_cleanup(input: &str) -> String {
@@ -216,8 +268,13 @@ impl ObjectTypes {
         return info;
     }
 
+    /// Matches one planning entry, the rest of an INFO line is kept as it is
+    fn info_regex() -> Regex {
+        Regex::new(r"(DEADLINE|SCHEDULED): <[^>]+>|CLOSED: \[[^\]]+\]").unwrap()
+    }
+
     fn info_add_info(&mut self, input: &str) {
-        let re = Regex::new(r"(DEADLINE|SCHEDULED): <[^>]+>").unwrap();
+        let re = ObjectTypes::info_regex();
         match self {
             ObjectTypes::INFO { info, text } => {
                 for mat in re.find_iter(input) {
@@ -230,8 +287,74 @@ impl ObjectTypes {
             _ => (),
         }
     }
+    /// Creates a new drawer start or end line, like :PROPERTIES: or :END:
+    pub fn new_drawer(input: &str) -> ObjectTypes {
+        ObjectTypes::Drawer {
+            name: input.trim().trim_matches(':').to_string(),
+            raw: input.to_string(),
+        }
+    }
+    /// Creates a new property from a line inside a :PROPERTIES: drawer
+    /// Returns None if the line is not a property
+    pub fn new_property(input: &str) -> Option<ObjectTypes> {
+        let re = Regex::new(r"^\s*:([^:\s]+):(?:\s+(.*?))?\s*$").unwrap();
+        let caps = re.captures(input)?;
+        Some(ObjectTypes::Property {
+            name: caps[1].to_string(),
+            value: caps.get(2).map(|v| v.as_str().to_string()).unwrap_or_default(),
+            raw: input.to_string(),
+        })
+    }
+    /// The tags at the end of a heading like :work:home:, empty for everything else
+    /// Tags stay part of the heading text, so the alignment of the line is kept when building
+    pub fn tags(&self) -> Vec<String> {
+        let re = Regex::new(r"\s:([\w@#%:]+):\s*$").unwrap();
+        match self {
+            ObjectTypes::Heading { text, .. } => match re.captures(&format!(" {text}")) {
+                Some(caps) => caps[1]
+                    .split(':')
+                    .filter(|tag| !tag.is_empty())
+                    .map(|tag| tag.to_string())
+                    .collect(),
+                None => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+    /// Mutable access to the text of Text, Heading, ListElement and FootnoteDefinition, None for other types
+    pub fn text_mut(&mut self) -> Option<&mut String> {
+        match self {
+            ObjectTypes::Text { text }
+            | ObjectTypes::Heading { text, .. }
+            | ObjectTypes::ListElement { text, .. }
+            | ObjectTypes::FootnoteDefinition { text, .. } => Some(text),
+            _ => None,
+        }
+    }
+    /// Creates a new footnote definition from a line starting with [fn:label]
+    pub fn new_footnote_definition(input: &str) -> ObjectTypes {
+        let re = Regex::new(r"^\[fn:([\w-]+)\]\s*(.*)$").unwrap();
+        match re.captures(input) {
+            Some(caps) => ObjectTypes::FootnoteDefinition {
+                label: caps[1].to_string(),
+                text: caps[2].to_string(),
+            },
+            None => ObjectTypes::new_text(input.to_string()),
+        }
+    }
     /// Creates a new Empty line
     pub fn new_empty() -> ObjectTypes {
         return ObjectTypes::EmptyLine;
     }
+    /// Parses the text of Text, Heading, ListElement and FootnoteDefinition into inline objects (markup, links, timestamps, ...)
+    /// All other types have no text that could contain inline objects and return an empty Vec
+    pub fn inline_objects(&self) -> Vec<InlineObject> {
+        match self {
+            ObjectTypes::Text { text }
+            | ObjectTypes::Heading { text, .. }
+            | ObjectTypes::ListElement { text, .. }
+            | ObjectTypes::FootnoteDefinition { text, .. } => inline::parse_inline(text),
+            _ => Vec::new(),
+        }
+    }
 }
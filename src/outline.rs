@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::{
+    html::escape,
+    inline::InlineObject,
+    link::{self, heading_title, Link, LinkType},
+    object::Object,
+    object_types::ObjectTypes,
+    structs::{self, Priority, TodoStates},
+    traversal::is_heading,
+};
+
+/// What goes into an OPML outline or a diagram
+#[derive(Debug, Clone, Default)]
+pub struct OutlineOptions {
+    /// Headings up to this level below the top, all of them if not given
+    pub depth: Option<u32>,
+    /// Diagrams: nodes filled by TODO state
+    pub colors: bool,
+    /// Diagrams: dashed edges for links between headings of the file
+    pub links: bool,
+}
+
+/// A heading as outliners and diagrams show it
+#[derive(Debug)]
+struct Node {
+    title: String,
+    todo: Option<TodoStates>,
+    priority: Option<Priority>,
+    tags: Vec<String>,
+    /// The text below the heading, without planning and drawers
+    note: Vec<String>,
+    /// CUSTOM_ID and ID, links to them point to this node even if another heading has the same title
+    custom_id: Option<String>,
+    id: Option<String>,
+    /// <<targets>> and #+NAME: below the heading
+    targets: Vec<String>,
+    /// The links written in the heading and the text below it
+    links: Vec<Link>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    /// The title with TODO keyword and priority in front, like the heading is written
+    fn label(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(todo) = &self.todo {
+            parts.push(todo.build());
+        }
+        if let Some(priority) = &self.priority {
+            parts.push(priority.build());
+        }
+        parts.push(self.title.clone());
+        parts.join(" ")
+    }
+}
+
+fn nodes(objects: &[Object], depth: u32, options: &OutlineOptions, abbreviations: &HashMap<String, String>) -> Vec<Node> {
+    if options.depth.is_some_and(|limit| depth >= limit) {
+        return Vec::new();
+    }
+    objects
+        .iter()
+        .filter_map(|obj| match obj.object_type() {
+            ObjectTypes::Heading { text, todo, priority, .. } => {
+                let mut targets = Vec::new();
+                let mut links = Vec::new();
+                collect_links(obj, abbreviations, &mut targets, &mut links);
+                Some(Node {
+                    title: heading_title(text),
+                    todo: todo.clone(),
+                    priority: priority.clone(),
+                    tags: obj.object_type().tags(),
+                    note: note(obj.children()),
+                    custom_id: obj.property("CUSTOM_ID").map(|id| id.to_string()),
+                    id: obj.property("ID").map(|id| id.to_string()),
+                    targets,
+                    links,
+                    children: nodes(obj.children(), depth + 1, options, abbreviations),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The targets and links of a heading and the text below it, subheadings are nodes of their own
+fn collect_links(obj: &Object, abbreviations: &HashMap<String, String>, targets: &mut Vec<String>, links: &mut Vec<Link>) {
+    if let ObjectTypes::Text { text } = obj.object_type() {
+        let re_name = Regex::new(r"^\s*#\+(?i:name):\s*(.+?)\s*$").unwrap();
+        if let Some(caps) = re_name.captures(text) {
+            targets.push(caps[1].to_string());
+        }
+    }
+    inline_links(&obj.object_type().inline_objects(), abbreviations, targets, links);
+    for child in obj.children().iter().filter(|child| !is_heading(child)) {
+        collect_links(child, abbreviations, targets, links);
+    }
+}
+
+fn inline_links(objects: &[InlineObject], abbreviations: &HashMap<String, String>, targets: &mut Vec<String>, links: &mut Vec<Link>) {
+    for object in objects {
+        match object {
+            InlineObject::Link { target, .. } => links.push(Link::parse(target, None, abbreviations)),
+            InlineObject::Target(target) | InlineObject::RadioTarget(target) => targets.push(target.clone()),
+            _ => (),
+        }
+        if let Some(children) = object.children() {
+            inline_links(children, abbreviations, targets, links);
+        }
+    }
+}
+
+/// The body lines of a heading, drawers and subheadings are left out
+fn note(children: &[Object]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut in_drawer = false;
+    for child in children {
+        match child.object_type() {
+            ObjectTypes::Drawer { name, .. } => in_drawer = !name.eq_ignore_ascii_case("END"),
+            ObjectTypes::Heading { .. } | ObjectTypes::INFO { .. } | ObjectTypes::Property { .. } | ObjectTypes::EmptyLine => (),
+            _ if in_drawer => (),
+            other => {
+                lines.push(other.build());
+                lines.extend(note(child.children()));
+            }
+        }
+    }
+    lines
+}
+
+/// All nodes depth first, with the number of each node used as its id in diagrams
+fn numbered(nodes: &[Node]) -> Vec<&Node> {
+    let mut result = Vec::new();
+    for node in nodes {
+        result.push(node);
+        result.extend(numbered(&node.children));
+    }
+    result
+}
+
+/// The edges between headings of the file, as pairs of node numbers
+/// Links start at the node they are written in, titles used twice point to the first heading like org resolves them
+fn link_edges(nodes: &[&Node]) -> Vec<(usize, usize)> {
+    let mut titles: HashMap<&str, usize> = HashMap::new();
+    let mut custom_ids: HashMap<&str, usize> = HashMap::new();
+    let mut ids: HashMap<&str, usize> = HashMap::new();
+    let mut targets: HashMap<&str, usize> = HashMap::new();
+    for (number, node) in nodes.iter().enumerate() {
+        titles.entry(node.title.as_str()).or_insert(number);
+        if let Some(custom_id) = &node.custom_id {
+            custom_ids.entry(custom_id.as_str()).or_insert(number);
+        }
+        if let Some(id) = &node.id {
+            ids.entry(id.as_str()).or_insert(number);
+        }
+        for target in &node.targets {
+            targets.entry(target.as_str()).or_insert(number);
+        }
+    }
+    let mut edges = Vec::new();
+    for (from, node) in nodes.iter().enumerate() {
+        for link in &node.links {
+            let to = match &link.link_type {
+                LinkType::Heading(title) => titles.get(heading_title(title).as_str()),
+                LinkType::CustomId(custom_id) => custom_ids.get(custom_id.as_str()),
+                LinkType::Id(id) => ids.get(id.as_str()),
+                LinkType::Fuzzy(target) => targets.get(target.as_str()).or(titles.get(heading_title(target).as_str())),
+                LinkType::File { .. } | LinkType::External { .. } => None,
+            };
+            if let Some(&to) = to {
+                if from != to && !edges.contains(&(from, to)) {
+                    edges.push((from, to));
+                }
+            }
+        }
+    }
+    edges
+}
+
+/// Fill colors of the TODO states, the same in DOT and Mermaid
+fn color(todo: &Option<TodoStates>) -> Option<&'static str> {
+    match todo {
+        Some(TodoStates::TODO) => Some("#f4cccc"),
+        Some(TodoStates::NEXT) => Some("#cfe2f3"),
+        Some(TodoStates::LOOP) => Some("#ead1dc"),
+        Some(TodoStates::DONE) => Some("#d9ead3"),
+        None => None,
+    }
+}
+
+impl structs::File {
+    /// The headings as OPML 2.0, with body text in _note and DONE headings marked _complete
+    pub fn to_opml(&self, name: &str, options: &OutlineOptions) -> String {
+        let title = self.keyword("TITLE").pop().unwrap_or_else(|| name.to_string());
+        let mut lines = vec![
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>".to_string(),
+            "<opml version=\"2.0\">".to_string(),
+            "  <head>".to_string(),
+            format!("    <title>{}</title>", escape(&title)),
+        ];
+        if let Some(author) = self.keyword("AUTHOR").pop() {
+            lines.push(format!("    <ownerName>{}</ownerName>", escape(&author)));
+        }
+        lines.push("  </head>".to_string());
+        lines.push("  <body>".to_string());
+        opml_outlines(&nodes(&self.children, 0, options, &link::abbreviations(self)), 2, &mut lines);
+        lines.push("  </body>".to_string());
+        lines.push("</opml>".to_string());
+        lines.join("\n") + "\n"
+    }
+
+    /// The headings as a Graphviz digraph below a node for the file, dot -Tsvg draws it
+    pub fn to_dot(&self, name: &str, options: &OutlineOptions) -> String {
+        let title = self.keyword("TITLE").pop().unwrap_or_else(|| name.to_string());
+        let tree = nodes(&self.children, 0, options, &link::abbreviations(self));
+        let all = numbered(&tree);
+        let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut lines = vec![
+            format!("digraph {} {{", quote(&title)),
+            "  rankdir=LR;".to_string(),
+            "  node [shape=box, style=\"rounded,filled\", fillcolor=white];".to_string(),
+            format!("  root [label={}, shape=ellipse];", quote(&title)),
+        ];
+        for (number, node) in all.iter().enumerate() {
+            let mut attributes = vec![format!("label={}", quote(&node.label()))];
+            if let Some(color) = color(&node.todo).filter(|_| options.colors) {
+                attributes.push(format!("fillcolor=\"{color}\""));
+            }
+            if !node.tags.is_empty() {
+                attributes.push(format!("tooltip={}", quote(&node.tags.join(" "))));
+            }
+            lines.push(format!("  n{number} [{}];", attributes.join(", ")));
+        }
+        tree_edges(&tree, "root", &mut 0, &mut |from, to| lines.push(format!("  {from} -> {to};")));
+        if options.links {
+            for (from, to) in link_edges(&all) {
+                lines.push(format!("  n{from} -> n{to} [style=dashed, constraint=false];"));
+            }
+        }
+        lines.push("}".to_string());
+        lines.join("\n") + "\n"
+    }
+
+    /// The headings as a Mermaid mindmap
+    /// Mindmaps can neither be colored nor have edges across branches, with colors or links this is a flowchart instead
+    pub fn to_mermaid(&self, name: &str, options: &OutlineOptions) -> String {
+        let title = self.keyword("TITLE").pop().unwrap_or_else(|| name.to_string());
+        let tree = nodes(&self.children, 0, options, &link::abbreviations(self));
+        // Quotes end a label, Mermaid reads #quot; as one
+        let quote = |text: &str| format!("\"{}\"", text.replace('"', "#quot;"));
+        if !options.colors && !options.links {
+            let mut lines = vec!["mindmap".to_string(), format!("  root(({}))", quote(&title))];
+            mindmap_nodes(&tree, 2, &mut 0, &quote, &mut lines);
+            return lines.join("\n") + "\n";
+        }
+        let all = numbered(&tree);
+        let mut lines = vec!["flowchart LR".to_string(), format!("  root([{}])", quote(&title))];
+        for (number, node) in all.iter().enumerate() {
+            lines.push(format!("  n{number}[{}]", quote(&node.label())));
+        }
+        tree_edges(&tree, "root", &mut 0, &mut |from, to| lines.push(format!("  {from} --> {to}")));
+        if options.links {
+            for (from, to) in link_edges(&all) {
+                lines.push(format!("  n{from} -.-> n{to}"));
+            }
+        }
+        if options.colors {
+            let states = [TodoStates::TODO, TodoStates::NEXT, TodoStates::LOOP, TodoStates::DONE];
+            for state in states {
+                let members: Vec<String> = all
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, node)| node.todo.as_ref() == Some(&state))
+                    .map(|(number, _)| format!("n{number}"))
+                    .collect();
+                if members.is_empty() {
+                    continue;
+                }
+                let class = state.build().to_lowercase();
+                let fill = color(&Some(state)).unwrap_or_default();
+                lines.push(format!("  classDef {class} fill:{fill}"));
+                lines.push(format!("  class {} {class}", members.join(",")));
+            }
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+fn opml_outlines(nodes: &[Node], indent: usize, lines: &mut Vec<String>) {
+    let pad = "  ".repeat(indent);
+    for node in nodes {
+        let mut attributes = vec![format!("text=\"{}\"", escape(&node.label()))];
+        if !node.note.is_empty() {
+            attributes.push(format!("_note=\"{}\"", escape(&node.note.join("\n")).replace('\n', "&#10;")));
+        }
+        if node.todo == Some(TodoStates::DONE) {
+            attributes.push("_complete=\"true\"".to_string());
+        }
+        if !node.tags.is_empty() {
+            attributes.push(format!("category=\"{}\"", escape(&node.tags.join(","))));
+        }
+        match node.children.is_empty() {
+            true => lines.push(format!("{pad}<outline {}/>", attributes.join(" "))),
+            false => {
+                lines.push(format!("{pad}<outline {}>", attributes.join(" ")));
+                opml_outlines(&node.children, indent + 1, lines);
+                lines.push(format!("{pad}</outline>"));
+            }
+        }
+    }
+}
+
+/// Calls edge for every parent and child, numbered in the order of numbered
+fn tree_edges(nodes: &[Node], parent: &str, next: &mut usize, edge: &mut dyn FnMut(&str, &str)) {
+    for node in nodes {
+        let id = format!("n{next}");
+        *next += 1;
+        edge(parent, &id);
+        tree_edges(&node.children, &id, next, edge);
+    }
+}
+
+/// Mindmaps nest by indentation
+fn mindmap_nodes(nodes: &[Node], indent: usize, next: &mut usize, quote: &dyn Fn(&str) -> String, lines: &mut Vec<String>) {
+    for node in nodes {
+        lines.push(format!("{}n{next}[{}]", "  ".repeat(indent), quote(&node.label())));
+        *next += 1;
+        mindmap_nodes(&node.children, indent + 1, next, quote, lines);
+    }
+}
@@ -7,16 +7,104 @@ use crate::object::Object;
 
 impl structs::Context {
     pub fn parse(&mut self, text: Vec<String>) {
-        for line in text {
-            self.lines.push((parse_line(line.clone()), line));
+        let re_drawer = Regex::new(r"^\s*:[\w-]+:\s*$").unwrap();
+        // Name of the drawer the line is in, lines of drawers other than PROPERTIES (like LOGBOOK) are kept as Text
+        let mut drawer: Option<String> = None;
+        let re_heading = Regex::new(r"^\*+\s").unwrap();
+        let re_begin = Regex::new(r"(?i)^\s*#\+begin_(\S+)").unwrap();
+        // Name of the #+begin_ block the line is in, everything up to its #+end_ is Text, even lines starting with * or -
+        // A #+begin_ without its #+end_ later on is no block, otherwise the rest of the file would be Text
+        let mut block: Option<String> = None;
+        let closed = |name: &str, rest: &[String]| {
+            let end = format!("#+end_{name}");
+            rest.iter().any(|line| line.trim().to_lowercase().starts_with(&end))
+        };
+        for (index, line) in text.iter().enumerate() {
+            let line = line.clone();
+            if let Some(name) = &block {
+                let end = format!("#+end_{name}");
+                let object = match line.trim().to_lowercase().starts_with(&end) {
+                    true => {
+                        block = None;
+                        ObjectTypes::new_text(line.clone())
+                    }
+                    false if line.trim().is_empty() => ObjectTypes::new_empty(),
+                    false => ObjectTypes::new_text(line.clone()),
+                };
+                self.lines.push((object, line));
+                continue;
+            }
+            if let Some(caps) = re_begin.captures(&line) {
+                let name = caps[1].to_lowercase();
+                if closed(&name, &text[index + 1..]) {
+                    block = Some(name);
+                }
+            }
+            if re_heading.is_match(&line) {
+                drawer = None; // A drawer without :END: does not reach past the next heading
+            }
+            // Inside a drawer only :END: is a drawer line, so empty properties like :ORDERED: don't end it
+            let drawer_line = match drawer {
+                Some(_) => re_drawer.is_match(&line) && line.trim().eq_ignore_ascii_case(":END:"),
+                None => re_drawer.is_match(&line),
+            };
+            let object = if drawer_line {
+                let object = ObjectTypes::new_drawer(&line);
+                if let ObjectTypes::Drawer { name, .. } = &object {
+                    drawer = match name.eq_ignore_ascii_case("END") {
+                        true => None,
+                        false => Some(name.to_uppercase()),
+                    };
+                }
+                object
+            } else {
+                match drawer.as_deref() {
+                    Some("PROPERTIES") => ObjectTypes::new_property(&line).unwrap_or_else(|| parse_line(line.clone())),
+                    Some(_) => ObjectTypes::new_text(line.clone()),
+                    None => parse_line(line.clone()),
+                }
+            };
+            self.lines.push((object, line));
         }
     }
 }
 
+impl structs::File {
+    /// Parses the lines of an org file into a File with its tree
+    /// The first line of the tree is the file context, the same way main.rs reads in files
+    pub fn parse(file_context: structs::FileContext, lines: Vec<String>) -> structs::File {
+        let mut all_lines = vec![format!("File context: {}", file_context.build())];
+        all_lines.extend(lines);
+        let mut context = structs::Context::new();
+        context.parse(all_lines);
+        let mut file = structs::File::new();
+        file.add_children(Object::parse(context));
+        file
+    }
+    /// Reads and parses an org file from disk
+    pub fn open(file_context: structs::FileContext, path: &str) -> std::io::Result<structs::File> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(structs::File::parse(
+            file_context,
+            content.lines().map(|line| line.to_string()).collect(),
+        ))
+    }
+    /// Writes the file back to disk
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let mut content = self.build().join("\n");
+        content.push('\n');
+        std::fs::write(path, content)
+    }
+}
+
 fn parse_line(text: String) -> ObjectTypes {
-    let re_heading = Regex::new(r"^\s*\*").unwrap();
+    // Stars at the very start and then whitespace, *bold* text and indented stars are no headings
+    let re_heading = Regex::new(r"^\*+(\s|$)").unwrap();
     let re_list = Regex::new(r"^\s*-").unwrap();
+    // Five or more dashes are a horizontal rule, checked before lists
+    let re_rule = Regex::new(r"^\s*-{5,}\s*$").unwrap();
     let re_comment = Regex::new(r"^\s*#").unwrap();
+    let re_footnote = Regex::new(r"^\[fn:[\w-]+\]").unwrap();
     let re_info = Regex::new(r"^\s*(SCHEDULED|DEADLINE|CLOSED)").unwrap();
     let re_empty = Regex::new(r"^\s*$").unwrap();
 
@@ -24,8 +112,12 @@ fn parse_line(text: String) -> ObjectTypes {
 
     if re_heading.is_match(&text) {
         object = ObjectTypes::new_heading(&text);
+    } else if re_rule.is_match(&text) {
+        object = ObjectTypes::new_text(text);
     } else if re_list.is_match(&text) {
         object = ObjectTypes::new_list_element(&text);
+    } else if re_footnote.is_match(&text) {
+        object = ObjectTypes::new_footnote_definition(&text);
     } else if re_comment.is_match(&text) {
         object = ObjectTypes::new_text(text);
     } else if re_info.is_match(&text) {
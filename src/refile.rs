@@ -0,0 +1,203 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{filter::Query, object::Object, structs};
+
+/// Which headings get refiled
+#[derive(Debug, Clone)]
+pub enum Selector {
+    /// The heading with this :ID: property
+    Id(String),
+    /// The heading with this outline path
+    Path(Vec<String>),
+    /// All headings matching a tags/property match
+    Query(Query),
+}
+
+/// Where headings get refiled to
+/// Written as Inbox/Today for a heading of the same file, work.org::Inbox/Today for another file and work.org:: for its top level
+/// ::Inbox is the same as Inbox
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    /// None for the file the headings come from
+    pub file: Option<String>,
+    pub path: Vec<String>,
+}
+
+/// A refile rule like +work -> work.org::Inbox, every heading matching the query goes to the target
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub query: Query,
+    pub target: Target,
+}
+
+/// A heading that was refiled, with the outline path it had before
+#[derive(Debug, Clone, PartialEq)]
+pub struct Refiled {
+    pub path: Vec<String>,
+    pub target: Target,
+}
+
+impl Target {
+    pub fn parse(input: &str) -> Target {
+        let (file, path) = match input.split_once("::") {
+            Some((file, path)) => (Some(file.to_string()).filter(|file| !file.is_empty()), path),
+            None => (None, input),
+        };
+        Target { file, path: split_path(path) }
+    }
+    pub fn path(&self) -> Vec<&str> {
+        self.path.iter().map(|title| title.as_str()).collect()
+    }
+    /// The target without file if its file, relative to source, is source itself
+    /// Opening the same file twice would let the second save overwrite the first
+    pub fn local_to(mut self, source: &str) -> Target {
+        if self.file.as_deref().is_some_and(|file| same_file(source, file)) {
+            self.file = None;
+        }
+        self
+    }
+}
+
+/// Whether other, relative to the directory of source, is the file source
+/// Both are canonicalized, so ./todo.org, ../notes/todo.org and links to it count as the same file
+pub fn same_file(source: &str, other: &str) -> bool {
+    let source = Path::new(source);
+    let other = source.parent().unwrap_or(Path::new("")).join(other);
+    match (source.canonicalize(), other.canonicalize()) {
+        (Ok(source), Ok(other)) => source == other,
+        _ => false,
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{file}::{}", self.path.join("/")),
+            None => write!(f, "{}", self.path.join("/")),
+        }
+    }
+}
+
+impl Rule {
+    /// Parses a rule line: the match, -> and the target
+    pub fn parse(line: &str) -> Result<Rule, String> {
+        let Some((query, target)) = line.rsplit_once("->") else {
+            return Err(format!("rule without -> target: {line}"));
+        };
+        Ok(Rule {
+            query: Query::parse(query.trim())?,
+            target: Target::parse(target.trim()),
+        })
+    }
+}
+
+/// Parses a rules file, one rule per line, empty lines and lines starting with # are skipped
+pub fn parse_rules(text: &str) -> Result<Vec<Rule>, String> {
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(Rule::parse)
+        .collect()
+}
+
+/// Splits an outline path like Projects/Website into its titles
+pub fn split_path(path: &str) -> Vec<String> {
+    path.split('/')
+        .map(|title| title.trim())
+        .filter(|title| !title.is_empty())
+        .map(|title| title.to_string())
+        .collect()
+}
+
+impl structs::File {
+    /// Outline paths of the selected headings, in the order of the file
+    /// Headings below another selected heading are left out, they move together with it
+    pub fn select(&self, selector: &Selector) -> Vec<Vec<String>> {
+        let paths: Vec<Vec<String>> = match selector {
+            Selector::Id(id) => self
+                .headings()
+                .filter(|entry| entry.object.property("ID") == Some(id.as_str()))
+                .map(|entry| entry.outline_path())
+                .take(1)
+                .collect(),
+            Selector::Path(path) => self
+                .find_path(&path.iter().map(|title| title.as_str()).collect::<Vec<_>>())
+                .map(|entry| entry.outline_path())
+                .into_iter()
+                .collect(),
+            Selector::Query(query) => self.query(query).into_iter().map(|found| found.path).collect(),
+        };
+        let mut selected: Vec<Vec<String>> = Vec::new();
+        for path in paths {
+            if !selected.iter().any(|parent| path.starts_with(parent)) {
+                selected.push(path);
+            }
+        }
+        selected
+    }
+
+    /// Moves the heading at path, with everything below it, to the end of the heading at target_path in another file
+    pub fn refile_to(&mut self, path: &[&str], target: &mut structs::File, target_path: &[&str]) -> Result<(), String> {
+        if target.find_path(target_path).is_none() && !target_path.is_empty() {
+            return Err(format!("no heading {}", target_path.join("/")));
+        }
+        let subtree: Object = self.cut(path).ok_or_else(|| format!("no heading {}", path.join("/")))?;
+        target.insert_heading(target_path, subtree)
+    }
+}
+
+/// Moves the headings at paths under the target, other is the file of the target if it is not source itself
+pub fn refile(
+    source: &mut structs::File,
+    paths: &[Vec<String>],
+    target: &[&str],
+    mut other: Option<&mut structs::File>,
+) -> Result<(), String> {
+    for path in paths {
+        let path: Vec<&str> = path.iter().map(|title| title.as_str()).collect();
+        match other.as_deref_mut() {
+            Some(file) => source.refile_to(&path, file, target)?,
+            None => source.move_subtree(&path, target)?,
+        }
+    }
+    Ok(())
+}
+
+/// Refiles every heading of source matching a rule, the first matching rule wins
+/// files are the other files the rules point to, by the name used in the rule
+/// Headings that are already below the target of their rule, or contain it, stay where they are
+/// All rules are matched against the file as it was, a heading taken by a rule moves with its subtree and no other rule moves it
+pub fn apply_rules(
+    source: &mut structs::File,
+    rules: &[Rule],
+    files: &mut HashMap<String, structs::File>,
+) -> Result<Vec<Refiled>, String> {
+    let mut taken: Vec<Vec<String>> = Vec::new();
+    let mut moves = Vec::new();
+    for rule in rules {
+        let mut paths = Vec::new();
+        for path in source.select(&Selector::Query(rule.query.clone())) {
+            if taken.iter().any(|taken| path.starts_with(taken)) {
+                continue;
+            }
+            taken.push(path.clone());
+            if rule.target.file.is_some() || !(path.starts_with(&rule.target.path) || rule.target.path.starts_with(&path)) {
+                paths.push(path);
+            }
+        }
+        moves.push((rule, paths));
+    }
+    let mut refiled = Vec::new();
+    for (rule, paths) in moves {
+        let other = match &rule.target.file {
+            Some(name) => Some(files.get_mut(name).ok_or_else(|| format!("file {name} is not loaded"))?),
+            None => None,
+        };
+        refile(source, &paths, &rule.target.path(), other)?;
+        refiled.extend(paths.into_iter().map(|path| Refiled {
+            path,
+            target: rule.target.clone(),
+        }));
+    }
+    Ok(refiled)
+}
@@ -1,4 +1,5 @@
-use crate::{object, object_types};
+use crate::{object, object_types, terminal, time_management};
+use chrono::Timelike;
 use std::{default, u32::MAX};
 use clap::ValueEnum;
 
@@ -7,12 +8,15 @@ use clap::ValueEnum;
 use regex::{self, Regex, Replacer};
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Org is a Context dependant language, so this is a place to add potetial contexts.
 pub enum FileContext {
     Todo,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(clippy::upper_case_acronyms)] // Named like the keywords they are written as
 pub enum TodoStates {
     TODO,
     DONE,
@@ -20,7 +24,8 @@ pub enum TodoStates {
     NEXT,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Priority {
     A,
     B,
@@ -28,6 +33,20 @@ pub enum Priority {
 }
 
 
+/// Which records are written when a heading gets completed, by the archiver or when a LOOP heading is reset
+/// The default logs nothing, like before logging existed
+#[derive(Debug, Clone, Default)]
+pub struct CompletionLog {
+    /// Insert CLOSED: [timestamp] into the planning line
+    pub closed: bool,
+    /// Add - State "DONE" from "TODO" [timestamp] notes to the LOGBOOK drawer
+    pub state_notes: bool,
+    /// Set the LAST_REPEAT property of repeating headings
+    pub last_repeat: bool,
+    /// The time that gets logged, the moment the CompletionLog was created
+    pub time: chrono::NaiveDateTime,
+}
+
 #[derive(Debug, Default)]
 pub struct Context {
     pub lines: Vec<(object_types::ObjectTypes, String)>,
@@ -83,7 +102,7 @@ impl TodoStates {
 
 impl Priority {
     /// Gives values to the priorities if you would have to sort by priority
-    fn value(&self) -> u32 {
+    pub fn value(&self) -> u32 {
         match self {
             Priority::A => 0,
             Priority::B => 1,
@@ -113,6 +132,35 @@ impl Priority {
     }
 }
 
+impl CompletionLog {
+    pub fn new(closed: bool, state_notes: bool, last_repeat: bool) -> CompletionLog {
+        CompletionLog {
+            closed,
+            state_notes,
+            last_repeat,
+            time: chrono::Local::now().naive_local(),
+        }
+    }
+    /// True if anything gets logged at all
+    pub fn enabled(&self) -> bool {
+        self.closed || self.state_notes || self.last_repeat
+    }
+    /// The inactive timestamp of the logging time, without seconds like org writes them
+    pub fn timestamp(&self) -> time_management::ParsedDateTime {
+        let time = chrono::NaiveTime::from_hms_opt(self.time.hour(), self.time.minute(), 0);
+        time_management::ParsedDateTime::new(self.time.date(), time, false)
+    }
+    /// A state change note like org writes them into the LOGBOOK
+    pub fn state_note(&self, to: &TodoStates, from: &TodoStates) -> String {
+        format!(
+            "- State {:<12} from {:<12} {}",
+            format!("\"{}\"", to.build()),
+            format!("\"{}\"", from.build()),
+            self.timestamp().build()
+        )
+    }
+}
+
 impl Context {
     pub fn new() -> Context {
         return Context { lines: Vec::new()};
@@ -152,12 +200,53 @@ impl File {
     pub fn add_children(&mut self, obj: object::Object) {
         self.children = obj.get_children();
     }
+    /// Collects all #+KEY: value lines of the file, keys are uppercased
+    pub fn keywords(&self) -> Vec<(String, String)> {
+        let re = Regex::new(r"^\s*#\+([\w-]+):\s*(.*?)\s*$").unwrap();
+        let mut keywords = Vec::new();
+        for obj in self.iter() {
+            if let object_types::ObjectTypes::Text { text } = obj.object_type() {
+                if let Some(caps) = re.captures(text) {
+                    keywords.push((caps[1].to_uppercase(), caps[2].to_string()));
+                }
+            }
+        }
+        keywords
+    }
+    /// All values of a keyword like #+LINK, in the order they appear in the file
+    pub fn keyword(&self, key: &str) -> Vec<String> {
+        self.keywords()
+            .into_iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+            .collect()
+    }
+    /// The category of the file: #+CATEGORY or, like org does, the file name without extension
+    pub fn category(&self, name: &str) -> String {
+        self.keyword("CATEGORY").pop().unwrap_or_else(|| {
+            std::path::Path::new(name)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_else(|| name.to_string())
+        })
+    }
+    /// Prints the tree indented and, in a terminal, colored
     pub fn print_children(&self) {
-        println!("{:#?}", self.children);
+        let options = terminal::TerminalOptions {
+            style: terminal::Style::detect(),
+            ..Default::default()
+        };
+        print!("{}", self.render_terminal(&options));
+    }
+    pub fn update_loop(&mut self, log: &CompletionLog) {
+        for child in &mut self.children {
+            child.update_loop(log);
+        }
     }
-    pub fn update_loop(&mut self) {
+    /// Logs completion of all headings with the todostate that were not logged yet, see Object::log_completion
+    pub fn log_completion(&mut self, todostate: &TodoStates, log: &CompletionLog) {
         for child in &mut self.children {
-            child.update_loop();
+            child.log_completion(todostate, log);
         }
     }
 }
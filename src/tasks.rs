@@ -0,0 +1,464 @@
+use chrono::NaiveDate;
+use regex::Regex;
+
+use crate::{
+    link::heading_title,
+    object::Object,
+    object_types::ObjectTypes,
+    structs::{self, Priority, TodoStates},
+    time_management::{InfoType, ParsedDateTime},
+};
+
+/// A heading with a TODO keyword, as todo.txt and Taskwarrior see it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Task {
+    /// The ID property, used to find the heading again when importing
+    pub id: Option<String>,
+    pub description: String,
+    pub todo: TodoStates,
+    pub priority: Option<Priority>,
+    /// The titles of the headings above, from the top
+    pub projects: Vec<String>,
+    /// The tags of the heading
+    pub contexts: Vec<String>,
+    pub deadline: Option<ParsedDateTime>,
+    pub scheduled: Option<ParsedDateTime>,
+    pub closed: Option<ParsedDateTime>,
+}
+
+impl structs::File {
+    /// Every heading with a TODO keyword, in the order of the file
+    pub fn tasks(&self) -> Vec<Task> {
+        self.headings()
+            .filter_map(|entry| {
+                let ObjectTypes::Heading {
+                    text,
+                    todo: Some(todo),
+                    priority,
+                    deadline,
+                    scheduled,
+                    closed,
+                    ..
+                } = entry.object.object_type()
+                else {
+                    return None;
+                };
+                let mut projects = entry.outline_path();
+                projects.pop();
+                Some(Task {
+                    id: entry.object.property("ID").map(|id| id.to_string()),
+                    description: heading_title(text),
+                    todo: todo.clone(),
+                    priority: priority.clone(),
+                    projects,
+                    contexts: entry.object.object_type().tags(),
+                    deadline: deadline.clone(),
+                    scheduled: scheduled.clone(),
+                    closed: closed.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// The tasks as todo.txt, one line each
+    pub fn to_todo_txt(&self) -> String {
+        self.tasks().iter().map(|task| task.to_todo_txt() + "\n").collect()
+    }
+
+    /// Reads the tasks of a todo.txt file as org headings
+    pub fn from_todo_txt(text: &str) -> structs::File {
+        let tasks: Vec<Task> = text.lines().filter_map(Task::parse_todo_txt).collect();
+        structs::File::from_tasks(&tasks)
+    }
+
+    /// The headings of tasks, below headings for their projects
+    pub fn from_tasks(tasks: &[Task]) -> structs::File {
+        let mut file = structs::File::new();
+        for task in tasks {
+            let mut path: Vec<&str> = Vec::new();
+            for project in &task.projects {
+                let mut inner = path.clone();
+                inner.push(project);
+                if file.heading_mut(&inner).is_none() {
+                    file.insert_heading(&path, Object::heading(project)).expect("the path was just created");
+                }
+                path.push(project);
+            }
+            file.insert_heading(&path, task.heading()).expect("the path was just created");
+        }
+        file
+    }
+
+    /// Adds imported tasks below the heading at parent, in headings for their projects
+    /// A task with the ID of a heading in the file, or an open task with the same title, updates that heading instead
+    pub fn merge_tasks(&mut self, parent: &[&str], imported: structs::File) -> Result<(), String> {
+        let parent: Vec<String> = parent.iter().map(|title| title.to_string()).collect();
+        merge_children(self, &parent, imported.children)
+    }
+}
+
+fn merge_children(file: &mut structs::File, parent: &[String], children: Vec<Object>) -> Result<(), String> {
+    let path: Vec<&str> = parent.iter().map(|title| title.as_str()).collect();
+    for mut heading in children.into_iter().filter(|child| child.level().is_some()) {
+        let ObjectTypes::Heading { text, todo, .. } = heading.object_type() else {
+            continue;
+        };
+        let title = heading_title(text);
+        if todo.is_none() {
+            // A project, the one with the same title below parent is used if there is one
+            let existing = siblings(file, &path)
+                .iter()
+                .filter_map(|sibling| match sibling.object_type() {
+                    ObjectTypes::Heading { text, .. } => Some(heading_title(text)),
+                    _ => None,
+                })
+                .find(|existing| same_project(existing, &title));
+            let children = std::mem::take(heading.children_mut());
+            let mut inner = parent.to_vec();
+            match existing {
+                Some(existing) => inner.push(existing),
+                None => {
+                    file.insert_heading(&path, heading)?;
+                    inner.push(title);
+                }
+            }
+            merge_children(file, &inner, children)?;
+            continue;
+        }
+        let id = heading.property("ID").map(|id| id.to_string());
+        let found = match &id {
+            Some(id) => find_heading(&mut file.children, &|obj| obj.property("ID") == Some(id.as_str())),
+            None => find_heading(&mut file.children, &|obj| {
+                matches!(obj.object_type(), ObjectTypes::Heading { text, todo: Some(todo), .. } if *todo != TodoStates::DONE && heading_title(text) == title)
+            }),
+        };
+        match found {
+            Some(existing) => update(existing, &heading),
+            None => file.insert_heading(&path, heading)?,
+        }
+    }
+    Ok(())
+}
+
+/// The children of the heading at path, the top level of the file for an empty path
+fn siblings<'a>(file: &'a structs::File, path: &[&str]) -> &'a [Object] {
+    match path.is_empty() {
+        true => &file.children,
+        false => file.find_path(path).map(|entry| entry.object.children().as_slice()).unwrap_or_default(),
+    }
+}
+
+/// todo.txt projects can't have spaces, Website Redesign is written as +Website-Redesign
+fn same_project(title: &str, project: &str) -> bool {
+    project_name(title) == project_name(project)
+}
+
+fn project_name(title: &str) -> String {
+    title.split_whitespace().collect::<Vec<_>>().join("-")
+}
+
+fn find_heading<'a>(objects: &'a mut [Object], predicate: &dyn Fn(&Object) -> bool) -> Option<&'a mut Object> {
+    for obj in objects.iter_mut() {
+        if obj.level().is_some() && predicate(obj) {
+            return Some(obj);
+        }
+        if let Some(found) = find_heading(obj.children_mut(), predicate) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+/// Takes the state, priority, tags and dates of an imported heading over, the body stays as it is
+/// Dates that did not change are kept, so their repeaters and times are not lost
+fn update(existing: &mut Object, imported: &Object) {
+    let ObjectTypes::Heading {
+        text,
+        todo,
+        priority,
+        deadline,
+        scheduled,
+        closed,
+        ..
+    } = imported.object_type()
+    else {
+        return;
+    };
+    let planning = [
+        deadline.clone().map(|date| InfoType::DEADLINE { date }),
+        scheduled.clone().map(|date| InfoType::SCHEDULED { date }),
+        closed.clone().map(|date| InfoType::CLOSED { date }),
+    ];
+    if let ObjectTypes::Heading {
+        text: old_text,
+        todo: old_todo,
+        priority: old_priority,
+        ..
+    } = existing.object_type_mut()
+    {
+        *old_text = text.clone();
+        *old_todo = todo.clone();
+        *old_priority = priority.clone();
+    }
+    let old = [
+        existing_date(existing, |info| matches!(info, InfoType::DEADLINE { .. })),
+        existing_date(existing, |info| matches!(info, InfoType::SCHEDULED { .. })),
+        existing_date(existing, |info| matches!(info, InfoType::CLOSED { .. })),
+    ];
+    for (planning, old) in planning.into_iter().zip(old) {
+        if let Some(planning) = planning.filter(|planning| old != Some(planning.date().date())) {
+            existing.set_planning(planning);
+        }
+    }
+}
+
+fn existing_date(obj: &Object, kind: impl Fn(&InfoType) -> bool) -> Option<NaiveDate> {
+    match obj.children().first().map(|first| first.object_type()) {
+        Some(ObjectTypes::INFO { info, .. }) => info.iter().find(|info| kind(info)).map(|info| info.date().date()),
+        _ => None,
+    }
+}
+
+impl Task {
+    /// The heading of the task, with its planning line and ID
+    pub fn heading(&self) -> Object {
+        let mut text = self.todo.build();
+        if let Some(priority) = &self.priority {
+            text += &format!(" {}", priority.build());
+        }
+        text += &format!(" {}", self.description);
+        if !self.contexts.is_empty() {
+            text += &format!(" :{}:", self.contexts.join(":"));
+        }
+        let mut heading = Object::heading(&text);
+        let planning = [
+            self.deadline.clone().map(|date| InfoType::DEADLINE { date }),
+            self.scheduled.clone().map(|date| InfoType::SCHEDULED { date }),
+            self.closed.clone().map(|date| InfoType::CLOSED { date }),
+        ];
+        for planning in planning.into_iter().flatten() {
+            heading.set_planning(planning);
+        }
+        if let Some(id) = &self.id {
+            heading.set_property("ID", id);
+        }
+        heading
+    }
+
+    /// A todo.txt line like (A) Call mom +Family @phone due:2024-07-12 id:abc
+    /// Scheduled dates are written as t: (threshold), NEXT and LOOP as state:NEXT
+    pub fn to_todo_txt(&self) -> String {
+        let mut parts = Vec::new();
+        match (&self.todo, &self.priority) {
+            (TodoStates::DONE, priority) => {
+                parts.push("x".to_string());
+                if let Some(closed) = &self.closed {
+                    parts.push(closed.date().to_string());
+                }
+                parts.push(self.description.clone());
+                if let Some(priority) = priority {
+                    parts.push(format!("pri:{}", letter(priority)));
+                }
+            }
+            (_, Some(priority)) => {
+                parts.push(format!("({})", letter(priority)));
+                parts.push(self.description.clone());
+            }
+            (_, None) => parts.push(self.description.clone()),
+        }
+        parts.extend(self.projects.iter().map(|project| format!("+{}", project_name(project))));
+        parts.extend(self.contexts.iter().map(|context| format!("@{context}")));
+        if let Some(deadline) = &self.deadline {
+            parts.push(format!("due:{}", deadline.date()));
+        }
+        if let Some(scheduled) = &self.scheduled {
+            parts.push(format!("t:{}", scheduled.date()));
+        }
+        if matches!(self.todo, TodoStates::NEXT | TodoStates::LOOP) {
+            parts.push(format!("state:{}", self.todo.build()));
+        }
+        if let Some(id) = &self.id {
+            parts.push(format!("id:{id}"));
+        }
+        parts.join(" ")
+    }
+
+    /// Reads a todo.txt line, None for empty lines
+    pub fn parse_todo_txt(line: &str) -> Option<Task> {
+        let re_date = Regex::new(r"^\d{4}-\d{2}-\d{2}$").unwrap();
+        let re_priority = Regex::new(r"^\(([A-Z])\)$").unwrap();
+        // Keys are letters, and the value must not start with // so urls stay in the description
+        let re_field = Regex::new(r"^([A-Za-z]+):([^\s/][^\s]*)$").unwrap();
+        let mut tokens = line.split_whitespace().peekable();
+        tokens.peek()?;
+        let date = |token: &str| NaiveDate::parse_from_str(token, "%Y-%m-%d").ok();
+        let mut task = Task {
+            id: None,
+            description: String::new(),
+            todo: TodoStates::TODO,
+            priority: None,
+            projects: Vec::new(),
+            contexts: Vec::new(),
+            deadline: None,
+            scheduled: None,
+            closed: None,
+        };
+        if tokens.next_if_eq(&"x").is_some() {
+            task.todo = TodoStates::DONE;
+            task.closed = tokens.next_if(|token| re_date.is_match(token)).and_then(date).map(|date| ParsedDateTime::new(date, None, false));
+        } else if let Some(caps) = tokens.peek().and_then(|token| re_priority.captures(token)) {
+            task.priority = priority(&caps[1]);
+            tokens.next();
+        }
+        // The creation date, org has no place for it
+        tokens.next_if(|token| re_date.is_match(token));
+        let mut words = Vec::new();
+        for token in tokens {
+            if let Some(project) = token.strip_prefix('+').filter(|project| !project.is_empty()) {
+                task.projects.push(project.to_string());
+            } else if let Some(context) = token.strip_prefix('@').filter(|context| !context.is_empty()) {
+                task.contexts.push(context.to_string());
+            } else if let Some(caps) = re_field.captures(token) {
+                match caps[1].to_lowercase().as_str() {
+                    "due" => task.deadline = date(&caps[2]).map(|date| ParsedDateTime::new(date, None, true)),
+                    "t" => task.scheduled = date(&caps[2]).map(|date| ParsedDateTime::new(date, None, true)),
+                    "id" => task.id = Some(caps[2].to_string()),
+                    "pri" => task.priority = priority(&caps[2]),
+                    "state" if task.todo != TodoStates::DONE => task.todo = TodoStates::get("", &format!("{} ", caps[2].to_uppercase())).unwrap_or(TodoStates::TODO),
+                    _ => words.push(token),
+                }
+            } else {
+                words.push(token);
+            }
+        }
+        task.description = words.join(" ");
+        Some(task)
+    }
+}
+
+fn letter(priority: &Priority) -> char {
+    match priority {
+        Priority::A => 'A',
+        Priority::B => 'B',
+        Priority::C => 'C',
+    }
+}
+
+/// todo.txt has priorities up to Z, everything below C is C
+fn priority(letter: &str) -> Option<Priority> {
+    match letter.chars().next()? {
+        'A' => Some(Priority::A),
+        'B' => Some(Priority::B),
+        'C'..='Z' => Some(Priority::C),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "serde")]
+pub mod taskwarrior {
+    //! The JSON of task export and task import
+    use chrono::{NaiveDateTime, NaiveTime, TimeZone};
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// Taskwarrior wants uuids, other IDs are kept in the orgid attribute
+    fn is_uuid(id: &str) -> bool {
+        Regex::new(r"^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}$").unwrap().is_match(id)
+    }
+
+    /// Dates are written in UTC, times of org are local times in zone, dates without time are midnight
+    fn write_date<Tz: TimeZone>(date: &ParsedDateTime, zone: &Tz) -> Option<String> {
+        let local = date.date().and_time(date.time().unwrap_or_default());
+        let utc = zone.from_local_datetime(&local).earliest()?.naive_utc();
+        Some(utc.format("%Y%m%dT%H%M%SZ").to_string())
+    }
+
+    fn read_date<Tz: TimeZone>(value: &Value, zone: &Tz, active: bool) -> Option<ParsedDateTime> {
+        let utc = NaiveDateTime::parse_from_str(value.as_str()?.trim_end_matches('Z'), "%Y%m%dT%H%M%S").ok()?;
+        let local = zone.from_utc_datetime(&utc).naive_local();
+        let time = Some(local.time()).filter(|time| *time != NaiveTime::MIN);
+        Some(ParsedDateTime::new(local.date(), time, active))
+    }
+
+    impl structs::File {
+        /// The tasks as the JSON array task import reads
+        pub fn to_taskwarrior<Tz: TimeZone>(&self, zone: &Tz) -> String {
+            let tasks: Vec<Value> = self.tasks().iter().map(|task| task_json(task, zone)).collect();
+            serde_json::to_string_pretty(&tasks).expect("tasks are always valid JSON")
+        }
+
+        /// Reads the output of task export, a JSON array or one task per line, deleted tasks are left out
+        pub fn from_taskwarrior<Tz: TimeZone>(text: &str, zone: &Tz) -> Result<structs::File, String> {
+            let values: Vec<Value> = match serde_json::from_str(text) {
+                Ok(Value::Array(values)) => values,
+                _ => text
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| serde_json::from_str(line.trim().trim_end_matches(',')).map_err(|error| error.to_string()))
+                    .collect::<Result<_, _>>()?,
+            };
+            let tasks: Vec<Task> = values.iter().filter_map(|value| read_task(value, zone)).collect();
+            Ok(structs::File::from_tasks(&tasks))
+        }
+    }
+
+    fn task_json<Tz: TimeZone>(task: &Task, zone: &Tz) -> Value {
+        let mut object = json!({
+            "description": task.description,
+            "status": if task.todo == TodoStates::DONE { "completed" } else { "pending" },
+        });
+        let mut tags = task.contexts.clone();
+        // NEXT is the next tag of Taskwarrior, LOOP gets a tag of its own to come back
+        if matches!(task.todo, TodoStates::NEXT | TodoStates::LOOP) {
+            tags.push(task.todo.build().to_lowercase());
+        }
+        let fields = [
+            ("uuid", task.id.clone().filter(|id| is_uuid(id)).map(Value::from)),
+            ("orgid", task.id.clone().filter(|id| !is_uuid(id)).map(Value::from)),
+            ("priority", task.priority.as_ref().map(|priority| Value::from(["H", "M", "L"][priority.value() as usize]))),
+            ("project", Some(task.projects.iter().map(|project| project.replace('.', "-")).collect::<Vec<_>>().join(".")).filter(|project| !project.is_empty()).map(Value::from)),
+            ("tags", Some(tags).filter(|tags| !tags.is_empty()).map(Value::from)),
+            ("due", task.deadline.as_ref().and_then(|date| write_date(date, zone)).map(Value::from)),
+            ("scheduled", task.scheduled.as_ref().and_then(|date| write_date(date, zone)).map(Value::from)),
+            ("end", task.closed.as_ref().and_then(|date| write_date(date, zone)).map(Value::from)),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                object[key] = value;
+            }
+        }
+        object
+    }
+
+    fn read_task<Tz: TimeZone>(value: &Value, zone: &Tz) -> Option<Task> {
+        let status = value["status"].as_str().unwrap_or("pending");
+        if status == "deleted" {
+            return None;
+        }
+        let mut tags: Vec<String> = value["tags"].as_array().map(|tags| tags.iter().filter_map(|tag| tag.as_str().map(String::from)).collect()).unwrap_or_default();
+        let todo = match status {
+            "completed" => TodoStates::DONE,
+            _ if tags.iter().any(|tag| tag == "next") => TodoStates::NEXT,
+            _ if tags.iter().any(|tag| tag == "loop") => TodoStates::LOOP,
+            _ => TodoStates::TODO,
+        };
+        tags.retain(|tag| tag != "next" && tag != "loop");
+        Some(Task {
+            id: value["orgid"].as_str().or(value["uuid"].as_str()).map(String::from),
+            description: value["description"].as_str()?.to_string(),
+            todo,
+            priority: match value["priority"].as_str() {
+                Some("H") => Some(Priority::A),
+                Some("M") => Some(Priority::B),
+                Some("L") => Some(Priority::C),
+                _ => None,
+            },
+            projects: value["project"].as_str().map(|project| project.split('.').map(String::from).collect()).unwrap_or_default(),
+            contexts: tags,
+            deadline: read_date(&value["due"], zone, true),
+            scheduled: read_date(&value["scheduled"], zone, true),
+            closed: read_date(&value["end"], zone, false).filter(|_| status == "completed"),
+        })
+    }
+}
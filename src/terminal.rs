@@ -0,0 +1,242 @@
+use std::io::IsTerminal;
+
+use chrono::NaiveDate;
+
+use crate::{
+    agenda::DEFAULT_WARNING_DAYS,
+    export::without_tags,
+    object::Object,
+    object_types::ObjectTypes,
+    structs::{self, Priority, TodoStates},
+    time_management::InfoType,
+};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const BLUE: &str = "\x1b[34m";
+const MAGENTA: &str = "\x1b[35m";
+const CYAN: &str = "\x1b[36m";
+
+/// Whether text gets ANSI colors, the same text without them otherwise
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Style {
+    pub color: bool,
+}
+
+impl Style {
+    /// Colors when writing to a terminal, unless NO_COLOR is set
+    pub fn detect() -> Style {
+        Style {
+            color: std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        }
+    }
+
+    fn paint(&self, text: &str, codes: &[&str]) -> String {
+        match self.color {
+            true => format!("{}{text}{RESET}", codes.concat()),
+            false => text.to_string(),
+        }
+    }
+
+    pub fn todo(&self, todo: &TodoStates) -> String {
+        let color = match todo {
+            TodoStates::TODO => RED,
+            TodoStates::NEXT => CYAN,
+            TodoStates::LOOP => MAGENTA,
+            TodoStates::DONE => GREEN,
+        };
+        self.paint(&todo.build(), &[BOLD, color])
+    }
+
+    pub fn priority(&self, priority: &Priority) -> String {
+        let color = match priority {
+            Priority::A => RED,
+            Priority::B => YELLOW,
+            Priority::C => BLUE,
+        };
+        self.paint(&priority.build(), &[color])
+    }
+
+    /// :tag1:tag2:, empty without tags
+    pub fn tags(&self, tags: &[String]) -> String {
+        match tags.is_empty() {
+            true => String::new(),
+            false => self.paint(&format!(":{}:", tags.join(":")), &[DIM]),
+        }
+    }
+
+    pub fn dim(&self, text: &str) -> String {
+        self.paint(text, &[DIM])
+    }
+
+    pub fn bold(&self, text: &str) -> String {
+        self.paint(text, &[BOLD])
+    }
+
+    /// Deadlines that passed
+    pub fn overdue(&self, text: &str) -> String {
+        self.paint(text, &[BOLD, RED])
+    }
+
+    /// Deadlines that are close
+    pub fn due_soon(&self, text: &str) -> String {
+        self.paint(text, &[YELLOW])
+    }
+}
+
+/// The text without ANSI color codes, for measuring how wide it is
+pub fn strip_colors(text: &str) -> String {
+    let mut result = String::new();
+    let mut in_code = false;
+    for c in text.chars() {
+        match c {
+            '\x1b' => in_code = true,
+            'm' if in_code => in_code = false,
+            _ if in_code => (),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// How a tree is printed to the terminal
+#[derive(Debug, Clone)]
+pub struct TerminalOptions {
+    pub style: Style,
+    /// Spaces per heading level
+    pub indent: usize,
+    /// Headings this many levels below the top are folded, only their heading and planning are shown
+    pub depth: Option<u32>,
+    /// Deadlines before this day are overdue
+    pub today: NaiveDate,
+}
+
+impl Default for TerminalOptions {
+    fn default() -> Self {
+        TerminalOptions {
+            style: Style::default(),
+            indent: 2,
+            depth: None,
+            today: chrono::Local::now().date_naive(),
+        }
+    }
+}
+
+impl structs::File {
+    /// The file indented by heading level, with colored TODO keywords and drawers left out
+    pub fn render_terminal(&self, options: &TerminalOptions) -> String {
+        render(&self.children, options)
+    }
+}
+
+impl Object {
+    /// The subtree, this heading is the top level for indentation and depth
+    pub fn render_terminal(&self, options: &TerminalOptions) -> String {
+        render(std::slice::from_ref(self), options)
+    }
+}
+
+/// The lines of the trees, the first heading among them is the top level
+pub fn render(objects: &[Object], options: &TerminalOptions) -> String {
+    let top = objects.iter().find_map(|obj| obj.level()).unwrap_or(1);
+    let mut lines = Vec::new();
+    for obj in objects {
+        render_object(obj, top, 0, options, &mut lines);
+    }
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+/// Adds the lines of obj, depth is the number of headings above it that are printed
+fn render_object(obj: &Object, top: u32, depth: u32, options: &TerminalOptions, lines: &mut Vec<String>) {
+    let style = &options.style;
+    let pad = " ".repeat(depth as usize * options.indent);
+    match obj.object_type() {
+        ObjectTypes::Heading { text, todo, priority, value, .. } => {
+            // Headings above the top, like a level 1 heading after a level 2 subtree, start at the left
+            let depth = value.saturating_sub(top);
+            let pad = " ".repeat(depth as usize * options.indent);
+            let mut parts = vec![style.dim("*")];
+            if let Some(todo) = todo {
+                parts.push(style.todo(todo));
+            }
+            if let Some(priority) = priority {
+                parts.push(style.priority(priority));
+            }
+            parts.push(style.bold(&without_tags(text)));
+            let tags = style.tags(&obj.object_type().tags());
+            if !tags.is_empty() {
+                parts.push(tags);
+            }
+            let folded = options.depth.is_some_and(|limit| depth + 1 >= limit);
+            let hidden = obj.children().iter().any(|child| {
+                !matches!(
+                    child.object_type(),
+                    ObjectTypes::INFO { .. } | ObjectTypes::Drawer { .. } | ObjectTypes::Property { .. } | ObjectTypes::EmptyLine
+                )
+            });
+            if folded && hidden {
+                parts.push(style.dim("..."));
+            }
+            lines.push(format!("{pad}{}", parts.join(" ")));
+            let done = *todo == Some(TodoStates::DONE);
+            let mut in_drawer = false;
+            for child in obj.children() {
+                match child.object_type() {
+                    ObjectTypes::INFO { info, .. } if !info.is_empty() => {
+                        let body_pad = " ".repeat((depth as usize + 1) * options.indent);
+                        lines.push(format!("{body_pad}{}", planning(info, done, options)));
+                    }
+                    // Drawers are folded in org too
+                    ObjectTypes::Drawer { name, .. } => in_drawer = !name.eq_ignore_ascii_case("END"),
+                    _ if folded || in_drawer => (),
+                    _ => render_object(child, top, depth + 1, options, lines),
+                }
+            }
+        }
+        ObjectTypes::Drawer { .. } | ObjectTypes::Property { .. } | ObjectTypes::INFO { .. } | ObjectTypes::File { .. } => (),
+        ObjectTypes::EmptyLine => {
+            if lines.last().is_some_and(|line| !line.is_empty()) {
+                lines.push(String::new());
+            }
+        }
+        other => {
+            lines.push(format!("{pad}{}", other.build()));
+            for child in obj.children() {
+                render_object(child, top, depth, options, lines);
+            }
+        }
+    }
+}
+
+/// The planning line, with passed deadlines of open headings highlighted
+fn planning(info: &[InfoType], done: bool, options: &TerminalOptions) -> String {
+    let style = &options.style;
+    let parts: Vec<String> = info
+        .iter()
+        .map(|info| {
+            let text = info.clone().build();
+            let InfoType::DEADLINE { date } = info else {
+                return style.dim(&text);
+            };
+            let days = (date.date() - options.today).num_days();
+            let warning = date.warning().map(|warning| warning.days()).unwrap_or(DEFAULT_WARNING_DAYS);
+            match days {
+                _ if done => style.dim(&text),
+                days if days < 0 => style.overdue(&format!("{text} ({} d. ago)", -days)),
+                0 => style.overdue(&format!("{text} (today)")),
+                days if days <= warning => style.due_soon(&format!("{text} (in {days} d.)")),
+                _ => text,
+            }
+        })
+        .collect();
+    parts.join(" ")
+}
@@ -1,8 +1,9 @@
-use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use chrono::{Duration, Months, NaiveDate, NaiveTime};
 use regex::Regex;
 
-#[derive(Debug, Clone, Copy)]
-enum Repeat {
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Repeat {
     Dayly,
     Weekly,
     Monthly,
@@ -18,65 +19,306 @@ impl Repeat {
             Repeat::Yearly => 'y',
         }
     }
+    fn get(input: char) -> Option<Repeat> {
+        match input {
+            'd' => Some(Repeat::Dayly),
+            'w' => Some(Repeat::Weekly),
+            'm' => Some(Repeat::Monthly),
+            'y' => Some(Repeat::Yearly),
+            _ => None,
+        }
+    }
+    /// Length of one unit in days, months count as 30 days
+    pub fn days(&self) -> i64 {
+        match self {
+            Repeat::Dayly => 1,
+            Repeat::Weekly => 7,
+            Repeat::Monthly => 30,
+            Repeat::Yearly => 365,
+        }
+    }
+    /// Moves a date by value units, with real calendar months and years
+    pub fn add_to(&self, date: NaiveDate, value: u32) -> Option<NaiveDate> {
+        match self {
+            Repeat::Dayly => date.checked_add_signed(Duration::days(value as i64)),
+            Repeat::Weekly => date.checked_add_signed(Duration::weeks(value as i64)),
+            Repeat::Monthly => date.checked_add_months(Months::new(value)),
+            Repeat::Yearly => date.checked_add_months(Months::new(value * 12)),
+        }
+    }
+}
+
+/// How a repeater moves the date, like org: + cumulates, ++ catches up to the future, .+ restarts from the day it was done
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RepeatKind {
+    Cumulate,
+    CatchUp,
+    Restart,
+}
+
+/// The repeater part of a timestamp like +1w, ++2d or .+1m
+/// Habits can have a second interval, .+2d/4d means at least every 4 days but not more often than every 2 days
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Repeater {
+    pub kind: RepeatKind,
+    pub value: u32,
+    pub unit: Repeat,
+    /// The /4d of a habit repeater
+    pub deadline: Option<(u32, Repeat)>,
+    /// Files written by earlier versions of this tool use an l instead of the number (.+ld), which is kept when building
+    pub written_as_l: bool,
+}
+
+impl Repeater {
+    fn parse(input: &str) -> Option<Repeater> {
+        let re = Regex::new(r"^(\.\+|\+\+|\+)(\d+|l)([dwmy])(?:/(\d+)([dwmy]))?$").unwrap();
+        let caps = re.captures(input)?;
+        let kind = match &caps[1] {
+            "+" => RepeatKind::Cumulate,
+            "++" => RepeatKind::CatchUp,
+            _ => RepeatKind::Restart,
+        };
+        let written_as_l = &caps[2] == "l";
+        Some(Repeater {
+            kind,
+            value: match written_as_l {
+                true => 1,
+                false => caps[2].parse().ok()?,
+            },
+            unit: Repeat::get(caps[3].chars().next()?)?,
+            deadline: match (caps.get(4), caps.get(5)) {
+                (Some(value), Some(unit)) => Some((value.as_str().parse().ok()?, Repeat::get(unit.as_str().chars().next()?)?)),
+                _ => None,
+            },
+            written_as_l,
+        })
+    }
+    fn build(&self) -> String {
+        let kind = match self.kind {
+            RepeatKind::Cumulate => "+",
+            RepeatKind::CatchUp => "++",
+            RepeatKind::Restart => ".+",
+        };
+        let deadline = match self.deadline {
+            Some((value, unit)) => format!("/{value}{}", unit.build()),
+            None => "".to_string(),
+        };
+        match self.written_as_l {
+            true => format!("{kind}l{}{deadline}", self.unit.build()),
+            false => format!("{kind}{}{}{deadline}", self.value, self.unit.build()),
+        }
+    }
+    /// The interval in days, months count as 30 days like everywhere else here
+    pub fn days(&self) -> i64 {
+        self.value as i64 * self.unit.days()
+    }
+    /// The habit deadline interval in days, the repeater itself if there is no /4d part
+    pub fn deadline_days(&self) -> i64 {
+        match self.deadline {
+            Some((value, unit)) => value as i64 * unit.days(),
+            None => self.days(),
+        }
+    }
+}
+
+/// The warning period of a deadline like -3d, how long before the deadline it should show up
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Warning {
+    pub value: u32,
+    pub unit: Repeat,
+    /// --3d only warns for the first occurence of a repeating deadline
+    pub first_only: bool,
+}
+
+impl Warning {
+    fn parse(input: &str) -> Option<Warning> {
+        let re = Regex::new(r"^(--?)(\d+)([dwmy])$").unwrap();
+        let caps = re.captures(input)?;
+        Some(Warning {
+            value: caps[2].parse().ok()?,
+            unit: Repeat::get(caps[3].chars().next()?)?,
+            first_only: &caps[1] == "--",
+        })
+    }
+    fn build(&self) -> String {
+        let dashes = match self.first_only {
+            true => "--",
+            false => "-",
+        };
+        format!("{dashes}{}{}", self.value, self.unit.build())
+    }
+    /// The warning period in days, months count as 30 days
+    pub fn days(&self) -> i64 {
+        self.value as i64 * self.unit.days()
+    }
 }
 
 /// Saves dates in an org document
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ParsedDateTime {
     date: NaiveDate,
     day: String,
-    repeat: Option<Repeat>,
+    /// Time of day, if the timestamp has one like <2024-07-12 Fri 10:30>
+    time: Option<NaiveTime>,
+    /// End of a time range like <2024-07-12 Fri 10:30-11:00>
+    end_time: Option<NaiveTime>,
+    repeat: Option<Repeater>,
+    warning: Option<Warning>,
+    /// Active timestamps are written in <>, inactive ones in []
+    active: bool,
 }
 /// Returns true when the date was changed, false if it was not changed
 impl ParsedDateTime {
     /// Updates a ParsedDateTime, by it's repeater if the date passed todays date
     pub fn update(&mut self) -> bool {
-        let tody = chrono::Utc::now().date_naive();
-        if self.date < tody {
-            if let Some(repeater) = self.repeat {
-                match repeater {
-                    Repeat::Dayly => self.date = self.date + Duration::days(1),
-                    Repeat::Weekly => self.date = self.date + Duration::weeks(1),
-                    Repeat::Monthly => self.date = self.date + Duration::days(30),// Months are now 30 days, fact, pull request if you have a better idea
-                    Repeat::Yearly => self.date = self.date + Duration::days(365),// Fuck Schaltjahre
-                }
-                return true;
-            }
+        self.update_on(chrono::Utc::now().date_naive())
+    }
+    /// Moves the date one interval of its repeater on if it is before today, LOOP headings do the same for +, ++ and .+
+    /// Months and years are calendar months and years
+    pub fn update_on(&mut self, today: NaiveDate) -> bool {
+        if self.date >= today {
+            return false;
         }
-        return false;
+        let Some(next) = self.repeat.and_then(|repeater| repeater.unit.add_to(self.date, repeater.value)) else {
+            return false;
+        };
+        self.date = next;
+        self.day = self.date.format("%a").to_string();
+        true
     }
-    /// Creates a String representation of the Time, allways in <>
+    /// Creates a String representation of the Time, in <> if active, in [] if inactive
     pub fn build(&self) -> String {
         let repeat = match &self.repeat {
-            Some(rep) => format!(" .+l{}", rep.build()),
+            Some(rep) => format!(" {}", rep.build()),
             None => "".to_owned(),
         };
-        return format!("<{} {}{repeat}>", self.date.to_string(), self.day);
+        let warning = match &self.warning {
+            Some(warning) => format!(" {}", warning.build()),
+            None => "".to_owned(),
+        };
+        let time = match (&self.time, &self.end_time) {
+            (Some(time), Some(end)) => format!(" {}-{}", time.format("%H:%M"), end.format("%H:%M")),
+            (Some(time), None) => format!(" {}", time.format("%H:%M")),
+            _ => "".to_owned(),
+        };
+        let (open, close) = match self.active {
+            true => ('<', '>'),
+            false => ('[', ']'),
+        };
+        format!("{open}{} {}{time}{repeat}{warning}{close}", self.date, self.day)
+    }
+    /// Creates a new timestamp, the day name is calculated from the date
+    pub fn new(date: NaiveDate, time: Option<NaiveTime>, active: bool) -> ParsedDateTime {
+        ParsedDateTime {
+            date,
+            day: date.format("%a").to_string(),
+            time,
+            end_time: None,
+            repeat: None,
+            warning: None,
+            active,
+        }
+    }
+    /// The date of the timestamp
+    pub fn date(&self) -> NaiveDate {
+        self.date
+    }
+    /// The time of day, None if the timestamp only has a date
+    pub fn time(&self) -> Option<NaiveTime> {
+        self.time
+    }
+    /// The end of a time range, None if the timestamp is not a range
+    pub fn end_time(&self) -> Option<NaiveTime> {
+        self.end_time
+    }
+    /// Active timestamps (<>) show up in the agenda, inactive ones ([]) don't
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+    pub fn repeater(&self) -> Option<Repeater> {
+        self.repeat
+    }
+    pub fn set_repeater(&mut self, repeater: Option<Repeater>) {
+        self.repeat = repeater;
+    }
+    pub fn warning(&self) -> Option<Warning> {
+        self.warning
+    }
+    pub fn set_warning(&mut self, warning: Option<Warning>) {
+        self.warning = warning;
+    }
+    /// Makes the timestamp a time range ending at end, only used when it has a time
+    pub fn set_end_time(&mut self, end: Option<NaiveTime>) {
+        self.end_time = end;
+    }
+    /// All dates of this timestamp between from and to (inclusive), repeating timestamps can have many
+    pub fn occurrences(&self, from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+        let mut dates = Vec::new();
+        let Some(repeater) = self.repeat.filter(|r| r.value > 0) else {
+            if self.date >= from && self.date <= to {
+                dates.push(self.date);
+            }
+            return dates;
+        };
+        let mut step = 0;
+        while let Some(date) = repeater.unit.add_to(self.date, repeater.value * step) {
+            if date > to {
+                break;
+            }
+            if date >= from {
+                dates.push(date);
+            }
+            step += 1;
+        }
+        dates
     }
     /// Parses a org time into this struct, returns None if it could not parse
+    /// Both active <2024-07-12 Fri> and inactive [2024-07-12 Fri 10:30] timestamps are understood
+    /// After the day, a time (range), a repeater like +1w and a warning like -3d can follow
     pub fn parse(input: &str) -> Option<Self> {
-        let re = Regex::new(r"<(\d{4}-\d{2}-\d{2}) (\w{3})( \.\+l(d|w|m|y)|)>").unwrap();
+        let re = Regex::new(r"([<\[])(\d{4}-\d{2}-\d{2}) (\w{2,3}\.?)((?: [^<>\[\]\s]+)*)([>\]])").unwrap();
+        let re_time = Regex::new(r"^(\d{1,2}:\d{2})(?:-(\d{1,2}:\d{2}))?$").unwrap();
 
         if let Some(caps) = re.captures(input) {
-            let date_str = caps.get(1)?.as_str();
-            let day_str = caps.get(2)?.as_str().to_string();
-            let repeat = match caps.get(3)?.as_str().chars().last() {
-                Some(character) => match character {
-                    'd' => Some(Repeat::Dayly),
-                    'w' => Some(Repeat::Weekly),
-                    'm' => Some(Repeat::Monthly),
-                    'y' => Some(Repeat::Yearly),
-                    _ => None,
-                },
-                None => None,
-            };
+            let active = caps.get(1)?.as_str() == "<";
+            if active != (caps.get(5)?.as_str() == ">") {
+                return None;
+            }
+            let date_str = caps.get(2)?.as_str();
+            let day_str = caps.get(3)?.as_str().to_string();
+            let mut time = None;
+            let mut end_time = None;
+            let mut repeat = None;
+            let mut warning = None;
+            for token in caps.get(4)?.as_str().split_whitespace() {
+                if let Some(times) = re_time.captures(token) {
+                    time = Some(NaiveTime::parse_from_str(&times[1], "%H:%M").ok()?);
+                    end_time = match times.get(2) {
+                        Some(end) => Some(NaiveTime::parse_from_str(end.as_str(), "%H:%M").ok()?),
+                        None => None,
+                    };
+                } else if let Some(repeater) = Repeater::parse(token) {
+                    repeat = Some(repeater);
+                } else if let Some(parsed) = Warning::parse(token) {
+                    warning = Some(parsed);
+                }
+                // Tokens that are not understood are skipped, the rest of the timestamp is still read
+            }
 
             let date = NaiveDate::parse_from_str(date_str, "%Y-%m-%d").ok()?;
 
             Some(ParsedDateTime {
                 date,
                 repeat,
+                warning,
                 day: day_str,
+                time,
+                end_time,
+                active,
             })
         } else {
             None
@@ -85,10 +327,15 @@ impl ParsedDateTime {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(tag = "type"))]
 /// Information types, often given underneath a heading
+/// Together they form the planning line, they are allways written in the order of this enum
+#[allow(clippy::upper_case_acronyms)] // Named like the keywords they are written as
 pub enum InfoType {
-    SCHEDULED { date: ParsedDateTime },
     DEADLINE { date: ParsedDateTime },
+    SCHEDULED { date: ParsedDateTime },
+    /// When the heading was set to DONE, allways an inactive timestamp
+    CLOSED { date: ParsedDateTime },
 }
 
 impl InfoType {
@@ -96,28 +343,42 @@ impl InfoType {
     pub fn get(input: &str) -> Option<InfoType> {
         let re_dead = Regex::new("DEADLINE:").unwrap();
         let re_sche = Regex::new("SCHEDULED:").unwrap();
+        let re_clos = Regex::new("CLOSED:").unwrap();
         if re_dead.is_match(input) {
-            return Some(InfoType::DEADLINE {
-                date: match ParsedDateTime::parse(input) {
-                    Some(datetime) => datetime,
-                    None => return None,
-                },
-            });
+            Some(InfoType::DEADLINE {
+                date: ParsedDateTime::parse(input)?,
+            })
         } else if re_sche.is_match(input) {
-            return Some(InfoType::SCHEDULED {
-                date: match ParsedDateTime::parse(input) {
-                    Some(datetime) => datetime,
-                    None => return None,
-                },
-            });
+            Some(InfoType::SCHEDULED {
+                date: ParsedDateTime::parse(input)?,
+            })
+        } else if re_clos.is_match(input) {
+            Some(InfoType::CLOSED {
+                date: ParsedDateTime::parse(input)?,
+            })
         } else {
-            return None;
+            None
         }
     }
     pub fn build(self) -> String {
         match self {
             InfoType::DEADLINE { date } => format!("DEADLINE: {}", date.build()),
             InfoType::SCHEDULED { date } => format!("SCHEDULED: {}", date.build()),
+            InfoType::CLOSED { date } => format!("CLOSED: {}", date.build()),
+        }
+    }
+    /// Position in the planning line, DEADLINE SCHEDULED CLOSED like org writes them
+    pub fn order(&self) -> u32 {
+        match self {
+            InfoType::DEADLINE { .. } => 0,
+            InfoType::SCHEDULED { .. } => 1,
+            InfoType::CLOSED { .. } => 2,
+        }
+    }
+    /// The timestamp of the planning information
+    pub fn date(&self) -> &ParsedDateTime {
+        match self {
+            InfoType::DEADLINE { date } | InfoType::SCHEDULED { date } | InfoType::CLOSED { date } => date,
         }
     }
 }
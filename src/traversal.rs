@@ -0,0 +1,258 @@
+use std::collections::VecDeque;
+
+use crate::{link::heading_title, object::Object, object_types::ObjectTypes, structs};
+
+/// Depth first (pre-order) iterator, visits an Object before its children, in the order they appear in the file
+pub struct DepthFirst<'a> {
+    stack: Vec<&'a Object>,
+}
+
+/// Breadth first iterator, visits all objects of one depth before going deeper
+pub struct BreadthFirst<'a> {
+    queue: VecDeque<&'a Object>,
+}
+
+/// Depth first iterator that also knows all objects above the current one
+pub struct Walk<'a> {
+    stack: Vec<(&'a Object, usize)>,
+    ancestors: Vec<&'a Object>,
+}
+
+/// An Object together with the objects above it, handed out by Walk
+#[derive(Debug, Clone)]
+pub struct Entry<'a> {
+    pub object: &'a Object,
+    /// All objects above, the top most first
+    pub ancestors: Vec<&'a Object>,
+}
+
+/// What a Visitor wants to happen after visiting an Object
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VisitAction {
+    Continue,
+    /// Do not go into the children of this Object, but continue with the rest
+    SkipChildren,
+    Stop,
+}
+
+/// Walks over a tree without changing it
+/// path are the objects above the visited one, the top most first
+pub trait Visitor {
+    fn visit(&mut self, obj: &Object, path: &[&Object]) -> VisitAction;
+    /// Called after all children of obj where visited
+    fn leave(&mut self, _obj: &Object, _path: &[&Object]) {}
+}
+
+/// Walks over a tree and is allowed to change it
+/// As the objects above can't be borrowed while changing one, path is the outline path (titles of the headings above)
+pub trait VisitorMut {
+    fn visit(&mut self, obj: &mut Object, path: &[String]) -> VisitAction;
+    /// Called after all children of obj where visited
+    fn leave(&mut self, _obj: &mut Object, _path: &[String]) {}
+}
+
+impl<'a> DepthFirst<'a> {
+    pub fn new(roots: &'a [Object]) -> DepthFirst<'a> {
+        DepthFirst {
+            stack: roots.iter().rev().collect(),
+        }
+    }
+}
+
+impl<'a> Iterator for DepthFirst<'a> {
+    type Item = &'a Object;
+
+    fn next(&mut self) -> Option<&'a Object> {
+        let obj = self.stack.pop()?;
+        self.stack.extend(obj.children().iter().rev());
+        Some(obj)
+    }
+}
+
+impl<'a> BreadthFirst<'a> {
+    pub fn new(roots: &'a [Object]) -> BreadthFirst<'a> {
+        BreadthFirst {
+            queue: roots.iter().collect(),
+        }
+    }
+}
+
+impl<'a> Iterator for BreadthFirst<'a> {
+    type Item = &'a Object;
+
+    fn next(&mut self) -> Option<&'a Object> {
+        let obj = self.queue.pop_front()?;
+        self.queue.extend(obj.children().iter());
+        Some(obj)
+    }
+}
+
+impl<'a> Walk<'a> {
+    pub fn new(roots: &'a [Object]) -> Walk<'a> {
+        Walk {
+            stack: roots.iter().rev().map(|root| (root, 0)).collect(),
+            ancestors: Vec::new(),
+        }
+    }
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = Entry<'a>;
+
+    fn next(&mut self) -> Option<Entry<'a>> {
+        let (obj, depth) = self.stack.pop()?;
+        self.ancestors.truncate(depth);
+        let entry = Entry {
+            object: obj,
+            ancestors: self.ancestors.clone(),
+        };
+        self.ancestors.push(obj);
+        self.stack
+            .extend(obj.children().iter().rev().map(|child| (child, depth + 1)));
+        Some(entry)
+    }
+}
+
+impl Entry<'_> {
+    /// Titles of the headings above and including this Object, like org shows outline paths
+    pub fn outline_path(&self) -> Vec<String> {
+        outline_path(self.ancestors.iter().copied().chain([self.object]))
+    }
+    /// The closest heading above this Object, None for objects before the first heading
+    pub fn parent_heading(&self) -> Option<&Object> {
+        self.ancestors.iter().rev().copied().find(|obj| is_heading(obj))
+    }
+}
+
+/// Titles of the headings among the objects
+pub fn outline_path<'a>(objects: impl Iterator<Item = &'a Object>) -> Vec<String> {
+    objects
+        .filter_map(|obj| match obj.object_type() {
+            ObjectTypes::Heading { text, .. } => Some(heading_title(text)),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn is_heading(obj: &Object) -> bool {
+    matches!(obj.object_type(), ObjectTypes::Heading { .. })
+}
+
+impl Object {
+    /// This Object and everything below it, depth first
+    pub fn iter(&self) -> DepthFirst<'_> {
+        DepthFirst::new(std::slice::from_ref(self))
+    }
+    /// This Object and everything below it, breadth first
+    pub fn iter_breadth_first(&self) -> BreadthFirst<'_> {
+        BreadthFirst::new(std::slice::from_ref(self))
+    }
+    /// This Object and everything below it depth first, together with the objects above them
+    pub fn walk(&self) -> Walk<'_> {
+        Walk::new(std::slice::from_ref(self))
+    }
+    /// The first Object (depth first) the predicate is true for
+    pub fn find(&self, predicate: impl Fn(&Object) -> bool) -> Option<&Object> {
+        self.iter().find(|obj| predicate(obj))
+    }
+    /// All objects the predicate is true for, depth first
+    pub fn find_all(&self, predicate: impl Fn(&Object) -> bool) -> Vec<&Object> {
+        self.iter().filter(|obj| predicate(obj)).collect()
+    }
+    /// Runs a visitor over this Object and everything below it
+    pub fn accept(&self, visitor: &mut impl Visitor) -> VisitAction {
+        accept(self, visitor, &mut Vec::new())
+    }
+    /// Runs a changing visitor over this Object and everything below it
+    pub fn accept_mut(&mut self, visitor: &mut impl VisitorMut) -> VisitAction {
+        accept_mut(self, visitor, &mut Vec::new())
+    }
+}
+
+impl structs::File {
+    /// Every Object of the file, depth first
+    pub fn iter(&self) -> DepthFirst<'_> {
+        DepthFirst::new(&self.children)
+    }
+    /// Every Object of the file, breadth first
+    pub fn iter_breadth_first(&self) -> BreadthFirst<'_> {
+        BreadthFirst::new(&self.children)
+    }
+    /// Every Object of the file depth first, together with the objects above them
+    pub fn walk(&self) -> Walk<'_> {
+        Walk::new(&self.children)
+    }
+    /// All headings of the file with their outline path
+    pub fn headings(&self) -> impl Iterator<Item = Entry<'_>> {
+        self.walk().filter(|entry| is_heading(entry.object))
+    }
+    /// The first Object (depth first) the predicate is true for, with the objects above it
+    pub fn find(&self, predicate: impl Fn(&Object) -> bool) -> Option<Entry<'_>> {
+        self.walk().find(|entry| predicate(entry.object))
+    }
+    /// All objects the predicate is true for, with the objects above them
+    pub fn find_all(&self, predicate: impl Fn(&Object) -> bool) -> Vec<Entry<'_>> {
+        self.walk().filter(|entry| predicate(entry.object)).collect()
+    }
+    /// The heading with the given outline path, like ["Projects", "Website"]
+    pub fn find_path(&self, path: &[&str]) -> Option<Entry<'_>> {
+        self.headings().find(|entry| entry.outline_path() == path)
+    }
+    /// Runs a visitor over every Object of the file
+    pub fn accept(&self, visitor: &mut impl Visitor) {
+        for child in &self.children {
+            if accept(child, visitor, &mut Vec::new()) == VisitAction::Stop {
+                return;
+            }
+        }
+    }
+    /// Runs a changing visitor over every Object of the file
+    pub fn accept_mut(&mut self, visitor: &mut impl VisitorMut) {
+        for child in &mut self.children {
+            if accept_mut(child, visitor, &mut Vec::new()) == VisitAction::Stop {
+                return;
+            }
+        }
+    }
+}
+
+fn accept<'a>(obj: &'a Object, visitor: &mut impl Visitor, path: &mut Vec<&'a Object>) -> VisitAction {
+    match visitor.visit(obj, path) {
+        VisitAction::Stop => return VisitAction::Stop,
+        VisitAction::SkipChildren => return VisitAction::Continue,
+        VisitAction::Continue => (),
+    }
+    path.push(obj);
+    for child in obj.children() {
+        if accept(child, visitor, path) == VisitAction::Stop {
+            return VisitAction::Stop;
+        }
+    }
+    path.pop();
+    visitor.leave(obj, path);
+    VisitAction::Continue
+}
+
+fn accept_mut(obj: &mut Object, visitor: &mut impl VisitorMut, path: &mut Vec<String>) -> VisitAction {
+    match visitor.visit(obj, path) {
+        VisitAction::Stop => return VisitAction::Stop,
+        VisitAction::SkipChildren => return VisitAction::Continue,
+        VisitAction::Continue => (),
+    }
+    let title = match obj.object_type() {
+        ObjectTypes::Heading { text, .. } => Some(heading_title(text)),
+        _ => None,
+    };
+    let pushed = title.is_some();
+    path.extend(title);
+    for child in obj.children_mut() {
+        if accept_mut(child, visitor, path) == VisitAction::Stop {
+            return VisitAction::Stop;
+        }
+    }
+    if pushed {
+        path.pop();
+    }
+    visitor.leave(obj, path);
+    VisitAction::Continue
+}